@@ -0,0 +1,85 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use chip8::scheduler::Scheduler;
+use chip8::{HeadlessKeypad, Processor, CHIP8_SCREEN_HEIGHT, CHIP8_SCREEN_WIDTH};
+
+mod roms;
+
+// instructions per iteration for the per instruction benchmarks
+const CYCLES: usize = 1000;
+
+fn processor(rom: &[u8]) -> Processor {
+    let mut processor = Processor::new();
+    processor.seed_rng(0);
+    processor.load(rom);
+    processor
+}
+
+fn emulate_cycle(c: &mut Criterion) {
+    let mut group = c.benchmark_group("emulate_cycle");
+    group.throughput(Throughput::Elements(CYCLES as u64));
+    for (name, rom) in roms::ALL {
+        let mut processor = processor(rom);
+        let mut keypad = HeadlessKeypad::new();
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for _ in 0..CYCLES {
+                    black_box(processor.emulate_cycle(&mut keypad));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+// LD V0, 27; LD V1, 5; then `DRW V0, V1, n` over and over with I at the font (0x000) and a
+// JP 204 at the end. The sprites start in the middle of a byte of the packed rows.
+fn draw_rom(rows: u8) -> Vec<u8> {
+    let mut rom = vec![0x60, 0x1B, 0x61, 0x05];
+    for _ in 0..CYCLES - 1 {
+        rom.extend_from_slice(&[0xD0, 0x10 | rows]);
+    }
+    rom.extend_from_slice(&[0x12, 0x04]);
+    rom
+}
+
+fn op_d(c: &mut Criterion) {
+    let mut group = c.benchmark_group("op_d");
+    group.throughput(Throughput::Elements(CYCLES as u64));
+    for rows in [1, 4, 8, 15] {
+        let mut processor = processor(&draw_rom(rows));
+        let mut keypad = HeadlessKeypad::new();
+        processor.emulate_cycle(&mut keypad);
+        processor.emulate_cycle(&mut keypad);
+        group.bench_with_input(BenchmarkId::from_parameter(rows), &rows, |b, _| {
+            b.iter(|| {
+                for _ in 0..CYCLES {
+                    black_box(processor.emulate_cycle(&mut keypad));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+// A 60 Hz frame at 1000 instructions per second like the headless tools run it, including
+// copying the screen out.
+fn frame(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frame");
+    let cycles = Scheduler::cycles_per_frame(1000);
+    let mut screen = vec![0; CHIP8_SCREEN_WIDTH * CHIP8_SCREEN_HEIGHT];
+    for (name, rom) in roms::ALL {
+        let mut processor = processor(rom);
+        let mut keypad = HeadlessKeypad::new();
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                black_box(processor.run_frame(&mut keypad, cycles));
+                processor.vram().write_bytes(&mut screen);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, emulate_cycle, op_d, frame);
+criterion_main!(benches);
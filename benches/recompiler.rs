@@ -0,0 +1,28 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+use chip8::{HeadlessKeypad, Processor};
+
+mod roms;
+
+const CYCLES: usize = 1000;
+
+// The interpreter and the block cache running the same ROMs.
+fn interpreter_vs_blocks(c: &mut Criterion) {
+    for (name, rom) in roms::ALL {
+        let mut group = c.benchmark_group(format!("recompiler/{}", name));
+        group.throughput(Throughput::Elements(CYCLES as u64));
+        for (label, blocks) in [("interpreter", false), ("blocks", true)] {
+            let mut processor = Processor::new();
+            processor.set_block_cache(blocks);
+            processor.load(rom);
+            let mut keypad = HeadlessKeypad::new();
+            group.bench_function(label, |b| {
+                b.iter(|| black_box(processor.run_frame(&mut keypad, CYCLES)))
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, interpreter_vs_blocks);
+criterion_main!(benches);
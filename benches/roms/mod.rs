@@ -0,0 +1,26 @@
+// Small hand assembled programs that stand in for typical ROMs in the benchmarks.
+
+// Register arithmetic in a tight loop, nothing is drawn:
+// LD V0, 0; LD V1, 0; ADD V0, 1; ADD V1, V0; LD V2, V1; SHR V2; XOR V3, V2; SE V0, 0;
+// JP 204; JP 200
+pub const COMPUTE: [u8; 20] = [
+    0x60, 0x00, 0x61, 0x00, 0x70, 0x01, 0x81, 0x04, 0x82, 0x10, 0x82, 0x26, 0x83, 0x23, 0x30, 0x00,
+    0x12, 0x04, 0x12, 0x00,
+];
+
+// Fills the screen with 8x8 sprites over and over:
+// CLS; LD V0, 0; LD V1, 0; LD I, 220; DRW V0, V1, 8; ADD V0, 8; SE V0, 64; JP 208; LD V0, 0;
+// ADD V1, 8; SE V1, 32; JP 208; JP 202; then the sprite at 0x220
+pub const DRAW: [u8; 40] = [
+    0x00, 0xE0, 0x60, 0x00, 0x61, 0x00, 0xA2, 0x20, 0xD0, 0x18, 0x70, 0x08, 0x30, 0x40, 0x12, 0x08,
+    0x60, 0x00, 0x71, 0x08, 0x31, 0x20, 0x12, 0x08, 0x12, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xFF, 0x81, 0xBD, 0xA5, 0xA5, 0xBD, 0x81, 0xFF,
+];
+
+// Turns a counter into BCD digits and reads them back, like a score display:
+// LD I, 300; LD B, V0; LD V2, [I]; ADD V0, 1; ADD V3, V2; JP 200
+pub const BCD: [u8; 12] = [
+    0xA3, 0x00, 0xF0, 0x33, 0xF2, 0x65, 0x70, 0x01, 0x83, 0x24, 0x12, 0x00,
+];
+
+pub const ALL: [(&str, &[u8]); 3] = [("compute", &COMPUTE), ("draw", &DRAW), ("bcd", &BCD)];
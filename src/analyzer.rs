@@ -0,0 +1,310 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+use crate::disassembler::disassemble_with;
+use crate::symbols::Symbols;
+use crate::{CHIP8_RAM, OPCODE_SIZE};
+
+// Static analysis of a ROM: follows every path from 0x200 to find the reachable code, splits
+// it into basic blocks and guesses which bytes are sprite data from `Annn` ... `Dxyn` pairs.
+// Anything reached only through `Bnnn` (or self-modifying code) can't be seen statically.
+
+const START: usize = 0x200;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum EdgeKind {
+    // next instruction in memory
+    Next,
+    Jump,
+    Call,
+    // the instruction after a skip instruction
+    Skip,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Block {
+    pub start: usize,
+    // address of the last instruction in the block
+    pub end: usize,
+    pub successors: Vec<(usize, EdgeKind)>,
+}
+
+#[derive(Default, Debug)]
+pub struct Analysis {
+    pub rom_size: usize,
+    // addresses of reachable instructions
+    pub code: BTreeSet<usize>,
+    // addresses of bytes drawn as sprites
+    pub sprites: BTreeSet<usize>,
+    pub blocks: BTreeMap<usize, Block>,
+    pub subroutines: BTreeSet<usize>,
+    // Bnnn, its targets depend on V0 at runtime
+    pub indirect_jumps: BTreeSet<usize>,
+    // quirk name (as in `Quirks`) -> instructions whose behaviour depends on it
+    pub quirks: BTreeMap<&'static str, BTreeSet<usize>>,
+    // names from the assembler, preferred over the generated labels
+    pub symbols: Symbols,
+}
+
+impl Analysis {
+    pub fn new(rom: &[u8]) -> Self {
+        let rom = &rom[..rom.len().min(CHIP8_RAM - START)];
+        let mut analysis = Analysis {
+            rom_size: rom.len(),
+            ..Analysis::default()
+        };
+        let opcode_at = |addr: usize| -> Option<u16> {
+            let offset = addr.checked_sub(START)?;
+            let bytes = rom.get(offset..offset + OPCODE_SIZE)?;
+            Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+        };
+
+        // I is followed along each path so that `Dxyn` can mark the sprite it draws, it's
+        // only known right after `Annn`
+        let mut successors: BTreeMap<usize, Vec<(usize, EdgeKind)>> = BTreeMap::new();
+        let mut worklist = vec![(START, None)];
+        while let Some((addr, mut i)) = worklist.pop() {
+            if analysis.code.contains(&addr) {
+                continue;
+            }
+            let Some(opcode) = opcode_at(addr) else {
+                continue;
+            };
+            analysis.code.insert(addr);
+
+            let next = addr + OPCODE_SIZE;
+            let nnn = (opcode & 0x0FFF) as usize;
+            let n = (opcode & 0x000F) as usize;
+            let kk = opcode & 0x00FF;
+            let edges = match opcode & 0xF000 {
+                0x0000 if opcode == 0x00EE => vec![],
+                0x1000 => vec![(nnn, EdgeKind::Jump)],
+                0x2000 => {
+                    analysis.subroutines.insert(nnn);
+                    vec![(nnn, EdgeKind::Call), (next, EdgeKind::Next)]
+                }
+                0x3000 | 0x4000 | 0x5000 | 0x9000 => {
+                    vec![(next, EdgeKind::Next), (next + OPCODE_SIZE, EdgeKind::Skip)]
+                }
+                0xE000 if kk == 0x9E || kk == 0xA1 => {
+                    vec![(next, EdgeKind::Next), (next + OPCODE_SIZE, EdgeKind::Skip)]
+                }
+                0xB000 => {
+                    analysis.indirect_jumps.insert(addr);
+                    analysis.quirk("jump_vx", addr);
+                    vec![]
+                }
+                _ => vec![(next, EdgeKind::Next)],
+            };
+
+            match opcode & 0xF00F {
+                0x8001..=0x8003 => analysis.quirk("vf_reset", addr),
+                0x8006 | 0x800E => analysis.quirk("shift_vy", addr),
+                _ => {}
+            }
+            match opcode & 0xF000 {
+                0xA000 => i = Some(nnn),
+                0xD000 => {
+                    analysis.quirk("wrap_sprites", addr);
+                    if let Some(i) = i {
+                        analysis.sprites.extend(i..(i + n).min(CHIP8_RAM));
+                    }
+                }
+                0xF000 => match kk {
+                    0x55 | 0x65 => {
+                        analysis.quirk("increment_i", addr);
+                        i = None;
+                    }
+                    0x1E | 0x29 | 0x33 => i = None,
+                    _ => {}
+                },
+                _ => {}
+            }
+
+            for &(target, kind) in edges.iter().rev() {
+                // the callee doesn't inherit I, it may be called from anywhere
+                let i = if kind == EdgeKind::Call { None } else { i };
+                worklist.push((target, i));
+            }
+            successors.insert(addr, edges);
+        }
+
+        analysis.build_blocks(&successors);
+        analysis
+    }
+
+    pub fn with_symbols(mut self, symbols: Symbols) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    fn quirk(&mut self, name: &'static str, addr: usize) {
+        self.quirks.entry(name).or_default().insert(addr);
+    }
+
+    // A block starts at 0x200, at every branch target and after every branch.
+    fn build_blocks(&mut self, successors: &BTreeMap<usize, Vec<(usize, EdgeKind)>>) {
+        let mut leaders = BTreeSet::from([START]);
+        for (&addr, edges) in successors.iter() {
+            if edges.len() != 1 || edges[0].1 != EdgeKind::Next {
+                leaders.extend(edges.iter().map(|&(target, _)| target));
+                leaders.insert(addr + OPCODE_SIZE);
+            }
+        }
+
+        let mut current: Option<Block> = None;
+        for (&addr, edges) in successors.iter() {
+            let continues = current
+                .as_ref()
+                .is_some_and(|block| block.end + OPCODE_SIZE == addr && !leaders.contains(&addr));
+            if !continues {
+                if let Some(block) = current.take() {
+                    self.finish_block(block, successors);
+                }
+                current = Some(Block {
+                    start: addr,
+                    end: addr,
+                    successors: Vec::new(),
+                });
+            }
+            let block = current.as_mut().unwrap();
+            block.end = addr;
+            block.successors = edges.clone();
+        }
+        if let Some(block) = current {
+            self.finish_block(block, successors);
+        }
+    }
+
+    fn finish_block(
+        &mut self,
+        mut block: Block,
+        successors: &BTreeMap<usize, Vec<(usize, EdgeKind)>>,
+    ) {
+        // only keep edges to code that exists, e.g. not a jump past the end of the ROM
+        block
+            .successors
+            .retain(|(target, _)| successors.contains_key(target));
+        self.blocks.insert(block.start, block);
+    }
+
+    // Label for an address, so that disassemblies can show `sub_2A0` instead of `0x2A0`.
+    pub fn label(&self, addr: usize) -> Option<String> {
+        if let Some(name) = self.symbols.name(addr) {
+            Some(name.to_string())
+        } else if self.subroutines.contains(&addr) {
+            Some(format!("sub_{:03X}", addr))
+        } else if self.blocks.contains_key(&addr) {
+            Some(format!("loc_{:03X}", addr))
+        } else if self.sprites.contains(&addr) && !self.sprites.contains(&(addr.wrapping_sub(1))) {
+            Some(format!("sprite_{:03X}", addr))
+        } else {
+            None
+        }
+    }
+
+    // Jump, call and LD I targets are shown by label.
+    fn disassemble(&self, opcode: u16) -> String {
+        disassemble_with(opcode, |addr| {
+            self.label(addr)
+                .unwrap_or_else(|| format!("0x{:03X}", addr))
+        })
+    }
+
+    fn opcode(rom: &[u8], addr: usize) -> u16 {
+        let offset = addr - START;
+        u16::from_be_bytes([rom[offset], rom.get(offset + 1).copied().unwrap_or(0)])
+    }
+
+    pub fn write_report(&self, rom: &[u8], out: &mut impl Write) -> io::Result<()> {
+        let code_bytes = self.code.len() * OPCODE_SIZE;
+        let sprite_bytes = self
+            .sprites
+            .iter()
+            .filter(|a| !self.code.contains(a))
+            .count();
+        writeln!(out, "ROM size:       {} bytes", self.rom_size)?;
+        writeln!(
+            out,
+            "Code:           {} bytes",
+            code_bytes.min(self.rom_size)
+        )?;
+        writeln!(out, "Sprite data:    {} bytes", sprite_bytes)?;
+        writeln!(out, "Basic blocks:   {}", self.blocks.len())?;
+        writeln!(out, "Subroutines:    {}", self.subroutines.len())?;
+
+        writeln!(out, "\nIndirect jumps (Bnnn):")?;
+        if self.indirect_jumps.is_empty() {
+            writeln!(out, "  none")?;
+        }
+        for addr in self.indirect_jumps.iter() {
+            writeln!(
+                out,
+                "  {:03X}  {}",
+                addr,
+                self.disassemble(Analysis::opcode(rom, *addr))
+            )?;
+        }
+
+        writeln!(out, "\nQuirk-sensitive instructions:")?;
+        if self.quirks.is_empty() {
+            writeln!(out, "  none")?;
+        }
+        for (quirk, addrs) in self.quirks.iter() {
+            let addrs: Vec<String> = addrs.iter().map(|addr| format!("{:03X}", addr)).collect();
+            writeln!(out, "  {:<13} {}", quirk, addrs.join(" "))?;
+        }
+
+        writeln!(out, "\nDisassembly:")?;
+        for offset in (0..self.rom_size).step_by(OPCODE_SIZE) {
+            let addr = START + offset;
+            if let Some(label) = self.label(addr) {
+                writeln!(out, "{}:", label)?;
+            }
+            let opcode = Analysis::opcode(rom, addr);
+            let text = if self.code.contains(&addr) {
+                self.disassemble(opcode)
+            } else if self.sprites.contains(&addr) {
+                format!(
+                    "DB 0x{:02X}, 0x{:02X}  ; sprite",
+                    opcode >> 8,
+                    opcode & 0xFF
+                )
+            } else {
+                format!("DB 0x{:02X}, 0x{:02X}", opcode >> 8, opcode & 0xFF)
+            };
+            writeln!(out, "  {:03X}  {:04X}  {}", addr, opcode, text)?;
+        }
+        Ok(())
+    }
+
+    // Graphviz DOT of the basic blocks, e.g. `dot -Tsvg cfg.dot > cfg.svg`.
+    pub fn write_dot(&self, rom: &[u8], out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "digraph cfg {{")?;
+        writeln!(out, "  node [shape=box fontname=monospace];")?;
+        for block in self.blocks.values() {
+            let mut label = self.label(block.start).unwrap_or_default() + "\\l";
+            for addr in (block.start..=block.end).step_by(OPCODE_SIZE) {
+                let text = self.disassemble(Analysis::opcode(rom, addr));
+                label += &format!("{:03X}  {}\\l", addr, text);
+            }
+            writeln!(out, "  b{:03X} [label=\"{}\"];", block.start, label)?;
+        }
+        for block in self.blocks.values() {
+            for &(target, kind) in block.successors.iter() {
+                let style = match kind {
+                    EdgeKind::Next => "",
+                    EdgeKind::Jump => " [style=bold]",
+                    EdgeKind::Call => " [style=dashed]",
+                    EdgeKind::Skip => " [label=skip]",
+                };
+                writeln!(out, "  b{:03X} -> b{:03X}{};", block.start, target, style)?;
+            }
+        }
+        writeln!(out, "}}")
+    }
+}
+
+#[cfg(test)]
+#[path = "./analyzer_test.rs"]
+mod analyzer_test;
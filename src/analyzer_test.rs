@@ -0,0 +1,68 @@
+use super::*;
+
+// 200: LD I, 0x20C
+// 202: DRW V0, V1, 2
+// 204: SE V0, 0x01
+// 206: CALL 0x20E
+// 208: JP V0, 0x200
+// 20A: JP 0x20A
+// 20C: sprite (2 bytes)
+// 20E: SHR V1, V2
+// 210: RET
+const ROM: [u8; 18] = [
+    0xA2, 0x0C, 0xD0, 0x12, 0x30, 0x01, 0x22, 0x0E, 0xB2, 0x00, 0x12, 0x0A, 0xF0, 0x90, 0x81, 0x26,
+    0x00, 0xEE,
+];
+
+#[test]
+fn test_code_and_data() {
+    let analysis = Analysis::new(&ROM);
+    assert_eq!(
+        analysis.code,
+        BTreeSet::from([0x200, 0x202, 0x204, 0x206, 0x208, 0x20E, 0x210])
+    );
+    assert_eq!(analysis.sprites, BTreeSet::from([0x20C, 0x20D]));
+    assert_eq!(analysis.subroutines, BTreeSet::from([0x20E]));
+    assert_eq!(analysis.indirect_jumps, BTreeSet::from([0x208]));
+    // nothing falls through the indirect jump, so 20A is unreachable
+    assert!(!analysis.code.contains(&0x20A));
+
+    assert_eq!(analysis.quirks["shift_vy"], BTreeSet::from([0x20E]));
+    assert_eq!(analysis.quirks["jump_vx"], BTreeSet::from([0x208]));
+    assert_eq!(analysis.quirks["wrap_sprites"], BTreeSet::from([0x202]));
+    assert!(!analysis.quirks.contains_key("vf_reset"));
+}
+
+#[test]
+fn test_blocks() {
+    let analysis = Analysis::new(&ROM);
+    let starts: Vec<usize> = analysis.blocks.keys().copied().collect();
+    assert_eq!(starts, vec![0x200, 0x206, 0x208, 0x20E]);
+
+    assert_eq!(
+        analysis.blocks[&0x200].successors,
+        vec![(0x206, EdgeKind::Next), (0x208, EdgeKind::Skip)]
+    );
+    assert_eq!(
+        analysis.blocks[&0x206].successors,
+        vec![(0x20E, EdgeKind::Call), (0x208, EdgeKind::Next)]
+    );
+    assert_eq!(analysis.blocks[&0x20E].end, 0x210);
+    assert!(analysis.blocks[&0x20E].successors.is_empty());
+
+    assert_eq!(analysis.label(0x20E).as_deref(), Some("sub_20E"));
+    assert_eq!(analysis.label(0x208).as_deref(), Some("loc_208"));
+    assert_eq!(analysis.label(0x20C).as_deref(), Some("sprite_20C"));
+    assert_eq!(analysis.label(0x202), None);
+}
+
+#[test]
+fn test_dot() {
+    let analysis = Analysis::new(&ROM);
+    let mut out = Vec::new();
+    analysis.write_dot(&ROM, &mut out).unwrap();
+    let dot = String::from_utf8(out).unwrap();
+    assert!(dot.starts_with("digraph cfg {"));
+    assert!(dot.contains("b206 -> b20E [style=dashed];"));
+    assert!(dot.contains("b200 -> b208 [label=skip];"));
+}
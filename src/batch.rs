@@ -0,0 +1,139 @@
+use rayon::prelude::*;
+
+use crate::scheduler::Scheduler;
+use crate::{HeadlessKeypad, Processor, Quirks};
+use crate::{CHIP8_SCREEN_HEIGHT, CHIP8_SCREEN_WIDTH};
+
+// Runs many processors side by side for fuzzing and training, one 60 Hz frame at a time
+// spread over all cores. After every frame the screens are copied into one contiguous buffer,
+// one byte (0 or 1) per pixel and one screen after the other, that can be handed to other
+// code (e.g. as an N x 32 x 64 array) without going through every processor.
+
+pub const SCREEN_SIZE: usize = CHIP8_SCREEN_WIDTH * CHIP8_SCREEN_HEIGHT;
+
+pub struct Batch {
+    processors: Vec<Processor>,
+    keypads: Vec<HeadlessKeypad>,
+    cycles_per_frame: usize,
+    screens: Vec<u8>,
+}
+
+impl Batch {
+    // `count` processors running the same ROM, each with its own random numbers.
+    pub fn new(count: usize, rom: &[u8], quirks: Quirks, ips: u32) -> Self {
+        let processors = (0..count)
+            .map(|_| {
+                let mut processor = Processor::new();
+                processor.set_quirks(quirks);
+                processor.set_block_cache(true);
+                processor.load(rom);
+                processor
+            })
+            .collect();
+        Batch::from_processors(processors, ips)
+    }
+
+    // Processors that were set up one by one, e.g. with different ROMs.
+    pub fn from_processors(processors: Vec<Processor>, ips: u32) -> Self {
+        let count = processors.len();
+        let mut batch = Batch {
+            processors,
+            keypads: vec![HeadlessKeypad::new(); count],
+            cycles_per_frame: Scheduler::cycles_per_frame(ips),
+            screens: vec![0; count * SCREEN_SIZE],
+        };
+        batch.copy_screens();
+        batch
+    }
+
+    pub fn len(&self) -> usize {
+        self.processors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+
+    pub fn processors(&self) -> &[Processor] {
+        &self.processors
+    }
+
+    // Changes show up in `screens` after the next frame.
+    pub fn processors_mut(&mut self) -> &mut [Processor] {
+        &mut self.processors
+    }
+
+    // Keys stay pressed until they are released again.
+    pub fn keypad_mut(&mut self, index: usize) -> &mut HeadlessKeypad {
+        &mut self.keypads[index]
+    }
+
+    // Gives every processor its own fixed seed (`seed + index`) so runs can be repeated.
+    pub fn seed(&mut self, seed: u64) {
+        for (index, processor) in self.processors.iter_mut().enumerate() {
+            processor.seed_rng(seed.wrapping_add(index as u64));
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.processors.par_iter_mut().for_each(Processor::reset);
+        self.copy_screens();
+    }
+
+    // One frame of every processor, in parallel.
+    pub fn run_frame(&mut self) {
+        let cycles = self.cycles_per_frame;
+        self.map(vec![(); self.len()], |processor, keypad, ()| {
+            processor.run_frame(keypad, cycles);
+        });
+    }
+
+    // Calls `f` with every processor, its keypad and its item of `items` in parallel and
+    // returns the results in the same order. The screens are copied afterwards like after a
+    // frame.
+    pub fn map<T, R, F>(&mut self, items: Vec<T>, f: F) -> Vec<R>
+    where
+        T: Send,
+        R: Send,
+        F: Fn(&mut Processor, &mut HeadlessKeypad, T) -> R + Sync,
+    {
+        assert_eq!(items.len(), self.len(), "one item per processor");
+        self.processors
+            .par_iter_mut()
+            .zip(self.keypads.par_iter_mut())
+            .zip(self.screens.par_chunks_mut(SCREEN_SIZE))
+            .zip(items)
+            .map(|(((processor, keypad), screen), item)| {
+                let result = f(processor, keypad, item);
+                processor.vram().write_bytes(screen);
+                result
+            })
+            .collect()
+    }
+
+    pub fn run_frames(&mut self, frames: usize) {
+        for _ in 0..frames {
+            self.run_frame();
+        }
+    }
+
+    // All screens after the last frame.
+    pub fn screens(&self) -> &[u8] {
+        &self.screens
+    }
+
+    pub fn screen(&self, index: usize) -> &[u8] {
+        &self.screens[index * SCREEN_SIZE..(index + 1) * SCREEN_SIZE]
+    }
+
+    fn copy_screens(&mut self) {
+        let screens = self.screens.chunks_mut(SCREEN_SIZE);
+        for (processor, screen) in self.processors.iter().zip(screens) {
+            processor.vram().write_bytes(screen);
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./batch_test.rs"]
+mod batch_test;
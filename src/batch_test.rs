@@ -0,0 +1,79 @@
+use super::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// RND V0, 0x1F; LD I, 0; DRW V0, V1, 5; JP 206
+const ROM: [u8; 8] = [0xC0, 0x1F, 0xA0, 0x00, 0xD0, 0x15, 0x12, 0x06];
+
+fn assert_send<T: Send>() {}
+
+#[test]
+fn test_processor_is_send() {
+    assert_send::<Processor>();
+}
+
+#[test]
+fn test_run_frame() {
+    let mut batch = Batch::new(8, &ROM, Quirks::default(), 600);
+    assert_eq!(batch.len(), 8);
+    assert_eq!(batch.screens().len(), 8 * SCREEN_SIZE);
+    assert!(batch.screens().iter().all(|&pixel| pixel == 0));
+
+    batch.seed(7);
+    batch.run_frame();
+    for (index, processor) in batch.processors().iter().enumerate() {
+        let mut expected = [0; SCREEN_SIZE];
+        processor.vram().write_bytes(&mut expected);
+        assert_eq!(batch.screen(index), expected);
+        // the top row of the 0 sprite is 4 pixels wide
+        assert_eq!(batch.screen(index).iter().filter(|&&p| p == 1).count(), 14);
+    }
+
+    // the same seeds draw at the same places
+    let mut again = Batch::new(8, &ROM, Quirks::default(), 600);
+    again.seed(7);
+    again.run_frame();
+    assert_eq!(again.screens(), batch.screens());
+
+    batch.reset();
+    assert!(batch.screens().iter().all(|&pixel| pixel == 0));
+}
+
+#[test]
+fn test_keypads() {
+    // LD V1, 5; SKNP V1; DRW V0, V0, 1; JP 206
+    let rom = [0x61, 0x05, 0xE1, 0xA1, 0xD0, 0x01, 0x12, 0x06];
+    let mut batch = Batch::new(2, &rom, Quirks::default(), 600);
+    batch.keypad_mut(1).set_key(5, true);
+    batch.run_frame();
+    assert!(!batch.screen(0).contains(&1));
+    assert!(batch.screen(1).contains(&1));
+}
+
+#[test]
+fn test_random_roms() {
+    // a fuzzer runs whatever it generates, none of it may take down the batch
+    let mut rng = StdRng::seed_from_u64(1);
+    let processors = (0..64)
+        .map(|_| {
+            let mut rom = vec![0; 256];
+            rng.fill(&mut rom[..]);
+            let mut processor = Processor::new();
+            processor.set_block_cache(true);
+            processor.load(&rom);
+            processor
+        })
+        .collect();
+    let mut batch = Batch::from_processors(processors, 6000);
+    batch.run_frames(30);
+}
+
+#[test]
+fn test_memory_per_processor() {
+    let mut batch = Batch::new(16, &ROM, Quirks::default(), 600);
+    batch.run_frames(10);
+    for processor in batch.processors() {
+        let cache = processor.block_cache().unwrap().heap_size();
+        assert!(std::mem::size_of::<Processor>() + cache < 8 * 1024);
+    }
+}
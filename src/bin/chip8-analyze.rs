@@ -0,0 +1,70 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use chip8::analyzer::Analysis;
+use chip8::symbols::Symbols;
+
+const USAGE: &str = "Usage: chip8-analyze [--dot FILE] [--symbols FILE] ROM";
+
+fn main() -> ExitCode {
+    let mut dot = None;
+    let mut symbols = Symbols::new();
+    let mut rom_path = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dot" => match args.next() {
+                Some(path) => dot = Some(path),
+                None => return usage(),
+            },
+            "--symbols" => match args.next().map(|path| Symbols::load(Path::new(&path))) {
+                Some(Ok(loaded)) => symbols = loaded,
+                Some(Err(e)) => {
+                    println!("{}", e);
+                    return ExitCode::FAILURE;
+                }
+                None => return usage(),
+            },
+            _ if arg.starts_with("--") => return usage(),
+            _ => rom_path = Some(arg),
+        }
+    }
+    let Some(rom_path) = rom_path else {
+        return usage();
+    };
+
+    let rom = match fs::read(&rom_path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            println!("Failed to read {}: {}", rom_path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let analysis = Analysis::new(&rom).with_symbols(symbols);
+
+    let result = match dot.as_deref() {
+        // the report would get in the way of piping the graph into dot
+        Some("-") => analysis.write_dot(&rom, &mut io::stdout().lock()),
+        Some(path) => File::create(path)
+            .map(BufWriter::new)
+            .and_then(|mut out| {
+                analysis.write_dot(&rom, &mut out)?;
+                out.flush()
+            })
+            .and_then(|_| analysis.write_report(&rom, &mut io::stdout().lock())),
+        None => analysis.write_report(&rom, &mut io::stdout().lock()),
+    };
+    if let Err(e) = result {
+        println!("Failed to write the analysis: {}", e);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+fn usage() -> ExitCode {
+    println!("{}", USAGE);
+    ExitCode::FAILURE
+}
@@ -0,0 +1,110 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use chip8::testrom::{Outcome, TestRun, DEFAULT_FRAMES, DEFAULT_IPF};
+use chip8::Quirks;
+
+const USAGE: &str = "Usage: chip8-test [--frames N] [--ipf N] [--profiles a,b] [--bless] [--record] [--output DIR] ROM_DIR REF_DIR";
+
+fn main() -> ExitCode {
+    let mut frames = DEFAULT_FRAMES;
+    let mut ipf = DEFAULT_IPF;
+    let mut profiles: Vec<String> = Quirks::PROFILES.iter().map(|p| p.to_string()).collect();
+    let mut bless = false;
+    let mut record = false;
+    let mut output = std::env::temp_dir().join("chip8-test");
+    let mut dirs = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut number = || args.next().and_then(|n| n.parse().ok());
+        match arg.as_str() {
+            "--frames" => match number() {
+                Some(n) => frames = n,
+                None => return usage(),
+            },
+            "--ipf" => match number() {
+                Some(n) => ipf = n,
+                None => return usage(),
+            },
+            "--profiles" => match args.next() {
+                Some(list) => profiles = list.split(',').map(|p| p.to_string()).collect(),
+                None => return usage(),
+            },
+            "--bless" => bless = true,
+            "--record" => record = true,
+            "--output" => match args.next() {
+                Some(dir) => output = PathBuf::from(dir),
+                None => return usage(),
+            },
+            _ if arg.starts_with("--") => return usage(),
+            _ => dirs.push(PathBuf::from(arg)),
+        }
+    }
+    if dirs.len() != 2 {
+        return usage();
+    }
+
+    let roms = match find_roms(&dirs[0]) {
+        Ok(roms) => roms,
+        Err(e) => {
+            println!("Failed to read {}: {}", dirs[0].display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let run = TestRun {
+        frames,
+        cycles_per_frame: ipf,
+        references: &dirs[1],
+        output: &output,
+        bless,
+        record,
+    };
+
+    let mut failures = 0;
+    for rom in roms.iter() {
+        for profile in profiles.iter() {
+            let name = format!("{} [{}]", rom.display(), profile);
+            match run.check(rom, profile) {
+                Ok(Outcome::Pass) => println!("PASS    {}", name),
+                Ok(Outcome::Blessed) => println!("BLESSED {}", name),
+                Ok(Outcome::Missing) => println!("MISSING {}", name),
+                Ok(Outcome::Fail(diff)) => {
+                    failures += 1;
+                    println!("FAIL    {} (diff: {})", name, diff.display());
+                    if record {
+                        println!(
+                            "        recording: {}",
+                            run.recording(rom, profile).display()
+                        );
+                    }
+                }
+                Err(e) => {
+                    failures += 1;
+                    println!("ERROR   {}: {}", name, e);
+                }
+            }
+        }
+    }
+
+    if failures > 0 {
+        println!("{} failed", failures);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+fn usage() -> ExitCode {
+    println!("{}", USAGE);
+    ExitCode::FAILURE
+}
+
+fn find_roms(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut roms: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ch8"))
+        .collect();
+    roms.sort();
+    Ok(roms)
+}
@@ -0,0 +1,117 @@
+use std::io::{self, Write};
+
+use crate::framebuffer::Framebuffer;
+use crate::CHIP8_SCREEN_HEIGHT;
+use crate::CHIP8_SCREEN_WIDTH;
+
+// A monochrome copy of the framebuffer that can be saved as a PBM and compared.
+// https://netpbm.sourceforge.net/doc/pbm.html
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<bool>,
+}
+
+impl Bitmap {
+    pub fn from_vram(vram: &Framebuffer) -> Self {
+        Bitmap {
+            width: CHIP8_SCREEN_WIDTH,
+            height: CHIP8_SCREEN_HEIGHT,
+            pixels: vram.pixels().collect(),
+        }
+    }
+
+    pub fn new(width: usize, height: usize) -> Self {
+        Bitmap {
+            width,
+            height,
+            pixels: vec![false; width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.pixels[y * self.width + x]
+    }
+
+    // Pixels outside the bitmap are ignored.
+    pub fn set(&mut self, x: usize, y: usize, on: bool) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = on;
+        }
+    }
+
+    // Plain (ASCII) PBM so that reference images are readable in diffs.
+    pub fn write_pbm(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "P1\n{} {}", self.width, self.height)?;
+        for row in self.pixels.chunks(self.width) {
+            let row: Vec<&str> = row.iter().map(|&on| if on { "1" } else { "0" }).collect();
+            writeln!(out, "{}", row.join(" "))?;
+        }
+        Ok(())
+    }
+
+    pub fn parse_pbm(data: &str) -> Result<Self, String> {
+        let mut tokens = data
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or(""))
+            .flat_map(|line| line.split_whitespace());
+        if tokens.next() != Some("P1") {
+            return Err("Not a plain PBM image".to_string());
+        }
+        let mut size = || -> Result<usize, String> {
+            tokens
+                .next()
+                .and_then(|token| token.parse().ok())
+                .ok_or_else(|| "Invalid PBM size".to_string())
+        };
+        let (width, height) = (size()?, size()?);
+
+        // pixels don't have to be separated by whitespace
+        let pixels: Vec<bool> = tokens
+            .flat_map(|token| token.chars())
+            .map(|c| c == '1')
+            .collect();
+        if pixels.len() != width * height {
+            return Err(format!(
+                "Expected {} pixels but found {}",
+                width * height,
+                pixels.len()
+            ));
+        }
+        Ok(Bitmap {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    // Binary PPM highlighting the differences between `self` (expected) and `actual`:
+    // white/black where both agree, red where only the expected pixel is set and green
+    // where only the actual one is.
+    pub fn write_diff_ppm(&self, actual: &Bitmap, out: &mut impl Write) -> io::Result<()> {
+        let width = self.width.max(actual.width);
+        let height = self.height.max(actual.height);
+        let pixel = |bitmap: &Bitmap, x: usize, y: usize| {
+            x < bitmap.width && y < bitmap.height && bitmap.get(x, y)
+        };
+
+        write!(out, "P6\n{} {}\n255\n", width, height)?;
+        for y in 0..height {
+            for x in 0..width {
+                let color = match (pixel(self, x, y), pixel(actual, x, y)) {
+                    (true, true) => [255, 255, 255],
+                    (false, false) => [0, 0, 0],
+                    (true, false) => [255, 0, 0],
+                    (false, true) => [0, 255, 0],
+                };
+                out.write_all(&color)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[path = "./bitmap_test.rs"]
+mod bitmap_test;
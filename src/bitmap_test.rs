@@ -0,0 +1,43 @@
+use super::*;
+
+fn build_bitmap() -> Bitmap {
+    let mut vram = Framebuffer::new();
+    vram.set(0, 0, true);
+    vram.set(63, 31, true);
+    Bitmap::from_vram(&vram)
+}
+
+#[test]
+fn test_pbm_round_trip() {
+    let bitmap = build_bitmap();
+    let mut pbm = Vec::new();
+    bitmap.write_pbm(&mut pbm).unwrap();
+
+    let pbm = String::from_utf8(pbm).unwrap();
+    assert!(pbm.starts_with("P1\n64 32\n1 0 0"));
+    assert_eq!(Bitmap::parse_pbm(&pbm).unwrap(), bitmap);
+}
+
+#[test]
+fn test_parse_pbm() {
+    let bitmap = Bitmap::parse_pbm("P1\n# comment\n3 2\n101\n0 1 0\n").unwrap();
+    assert_eq!(bitmap.width, 3);
+    assert!(bitmap.get(2, 0));
+    assert!(bitmap.get(1, 1));
+    assert!(!bitmap.get(0, 1));
+
+    assert!(Bitmap::parse_pbm("P4\n3 2\n").is_err());
+    assert!(Bitmap::parse_pbm("P1\n3 2\n101\n").is_err());
+}
+
+#[test]
+fn test_diff_ppm() {
+    let expected = Bitmap::parse_pbm("P1 2 1 1 1").unwrap();
+    let actual = Bitmap::parse_pbm("P1 2 1 1 0").unwrap();
+    let mut ppm = Vec::new();
+    expected.write_diff_ppm(&actual, &mut ppm).unwrap();
+
+    let header = b"P6\n2 1\n255\n";
+    assert_eq!(&ppm[..header.len()], header);
+    assert_eq!(&ppm[header.len()..], &[255, 255, 255, 255, 0, 0]);
+}
@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+
+use crate::{CHIP8_RAM, OPCODE_SIZE};
+
+// Translation of straight-line runs of instructions (basic blocks) into pre-decoded micro-ops,
+// cached by start address so that hot loops skip fetching and decoding. A block ends with the
+// first instruction that can change the program counter in any other way than moving on to
+// the next instruction (jumps, calls, returns, skips and Fx0A).
+//
+// Instructions that aren't worth specializing are kept as `Interpret` and go through the
+// normal decoder. Writes into translated RAM (Fx33, Fx55) drop every block they touch.
+
+// long runs without a jump are rare, this only bounds the work of translating one
+const MAX_BLOCK_LEN: usize = 256;
+
+// entries of the table of recently run blocks that is checked before the map
+const RECENT: usize = 64;
+const NO_BLOCK: (u16, u16) = (u16::MAX, 0);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MicroOp {
+    // 6xkk
+    Load(usize, u8),
+    // 7xkk
+    Add(usize, u8),
+    // 8xy0
+    Copy(usize, usize),
+    // 8xy1, 8xy2, 8xy3
+    Or(usize, usize),
+    And(usize, usize),
+    Xor(usize, usize),
+    // 8xy4, 8xy5, 8xy7
+    AddReg(usize, usize),
+    Sub(usize, usize),
+    SubN(usize, usize),
+    // 8xy6, 8xyE
+    Shr(usize, usize),
+    Shl(usize, usize),
+    // Annn
+    LoadI(usize),
+    // 1nnn
+    Jump(usize),
+    // 3xkk, 4xkk, 5xy0, 9xy0 (like the interpreter the last nibble of 5xyn and 9xyn is ignored)
+    SkipEq(usize, u8),
+    SkipNe(usize, u8),
+    SkipEqReg(usize, usize),
+    SkipNeReg(usize, usize),
+    // anything else, decoded again when it runs
+    Interpret(u16),
+}
+
+impl MicroOp {
+    pub fn decode(opcode: u16) -> Self {
+        let x = ((opcode & 0x0F00) >> 8) as usize;
+        let y = ((opcode & 0x00F0) >> 4) as usize;
+        let kk = (opcode & 0x00FF) as u8;
+        let nnn = (opcode & 0x0FFF) as usize;
+        match (opcode & 0xF000, opcode & 0x000F) {
+            (0x1000, _) => MicroOp::Jump(nnn),
+            (0x3000, _) => MicroOp::SkipEq(x, kk),
+            (0x4000, _) => MicroOp::SkipNe(x, kk),
+            (0x5000, _) => MicroOp::SkipEqReg(x, y),
+            (0x6000, _) => MicroOp::Load(x, kk),
+            (0x7000, _) => MicroOp::Add(x, kk),
+            (0x8000, 0x0) => MicroOp::Copy(x, y),
+            (0x8000, 0x1) => MicroOp::Or(x, y),
+            (0x8000, 0x2) => MicroOp::And(x, y),
+            (0x8000, 0x3) => MicroOp::Xor(x, y),
+            (0x8000, 0x4) => MicroOp::AddReg(x, y),
+            (0x8000, 0x5) => MicroOp::Sub(x, y),
+            (0x8000, 0x6) => MicroOp::Shr(x, y),
+            (0x8000, 0x7) => MicroOp::SubN(x, y),
+            (0x8000, 0xE) => MicroOp::Shl(x, y),
+            (0x9000, _) => MicroOp::SkipNeReg(x, y),
+            (0xA000, _) => MicroOp::LoadI(nnn),
+            _ => MicroOp::Interpret(opcode),
+        }
+    }
+
+    // Whether the instruction after this one isn't necessarily the next one in RAM.
+    pub fn ends_block(&self) -> bool {
+        match *self {
+            MicroOp::Jump(_)
+            | MicroOp::SkipEq(..)
+            | MicroOp::SkipNe(..)
+            | MicroOp::SkipEqReg(..)
+            | MicroOp::SkipNeReg(..) => true,
+            // 00E0 is the only 0nnn that carries on, Fx0A repeats itself until a key is down
+            MicroOp::Interpret(opcode) => match opcode & 0xF000 {
+                0x0000 => opcode != 0x00E0,
+                0x2000 | 0xB000 | 0xE000 => true,
+                0xF000 => opcode & 0x00FF == 0x0A,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Block {
+    pub start: usize,
+    // first byte after the block
+    pub end: usize,
+    pub ops: Vec<MicroOp>,
+}
+
+impl Block {
+    // Empty when `start` is too close to the end of RAM to hold an instruction.
+    pub fn translate(ram: &[u8; CHIP8_RAM], start: usize) -> Self {
+        let mut ops = Vec::new();
+        let mut addr = start;
+        while addr + 1 < CHIP8_RAM && ops.len() < MAX_BLOCK_LEN {
+            let op = MicroOp::decode((ram[addr] as u16) << 8 | ram[addr + 1] as u16);
+            ops.push(op);
+            addr += OPCODE_SIZE;
+            if op.ends_block() {
+                break;
+            }
+        }
+        Block {
+            start,
+            end: addr,
+            ops,
+        }
+    }
+}
+
+// Only the blocks that ran are kept, so a `Batch` of thousands of processors doesn't pay for a
+// table with an entry per byte of RAM.
+pub struct BlockCache {
+    // start address -> position in `blocks`
+    index: HashMap<usize, usize, BuildHasherDefault<AddrHasher>>,
+    // (start, position) by start address, so hot loops don't hash at all
+    recent: [(u16, u16); RECENT],
+    blocks: Vec<Block>,
+    // one bit per byte of RAM, set when a cached block includes it
+    covered: [u64; CHIP8_RAM / 64],
+    // set when a write dropped a block, the one that is running may be among them
+    invalidated: bool,
+}
+
+// The map is looked up once per block that runs, addresses don't need SipHash's protection.
+#[derive(Default)]
+struct AddrHasher(u64);
+
+impl Hasher for AddrHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_u64(byte as u64);
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.0 = (self.0 ^ value).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    }
+
+    fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+}
+
+impl Default for BlockCache {
+    fn default() -> Self {
+        BlockCache::new()
+    }
+}
+
+impl BlockCache {
+    pub fn new() -> Self {
+        BlockCache {
+            index: HashMap::default(),
+            recent: [NO_BLOCK; RECENT],
+            blocks: Vec::new(),
+            covered: [0; CHIP8_RAM / 64],
+            invalidated: false,
+        }
+    }
+
+    // Where the block starting at `addr` is, translated on first use. Stays valid until
+    // blocks are dropped, see `take_invalidated`.
+    pub fn get(&mut self, ram: &[u8; CHIP8_RAM], addr: usize) -> usize {
+        let slot = (addr / OPCODE_SIZE) % RECENT;
+        let (start, pos) = self.recent[slot];
+        if start as usize == addr {
+            return pos as usize;
+        }
+
+        let pos = match self.index.get(&addr) {
+            Some(&pos) => pos,
+            None => {
+                let block = Block::translate(ram, addr);
+                BlockCache::cover(&mut self.covered, &block);
+                self.index.insert(addr, self.blocks.len());
+                self.blocks.push(block);
+                self.blocks.len() - 1
+            }
+        };
+        self.recent[slot] = (addr as u16, pos as u16);
+        pos
+    }
+
+    pub fn block(&self, pos: usize) -> &Block {
+        &self.blocks[pos]
+    }
+
+    // The `index`-th op of the block at `pos`.
+    pub fn op(&self, pos: usize, index: usize) -> MicroOp {
+        self.blocks[pos].ops[index]
+    }
+
+    // Called for every write to RAM, drops the blocks that include `addr`.
+    pub fn invalidate(&mut self, addr: usize) {
+        if !self.is_covered(addr) {
+            return;
+        }
+        self.blocks
+            .retain(|block| !(block.start..block.end).contains(&addr));
+        self.index.clear();
+        self.recent = [NO_BLOCK; RECENT];
+        self.covered = [0; CHIP8_RAM / 64];
+        for (pos, block) in self.blocks.iter().enumerate() {
+            self.index.insert(block.start, pos);
+            BlockCache::cover(&mut self.covered, block);
+        }
+        self.invalidated = true;
+    }
+
+    // Drops everything, e.g. after RAM was replaced.
+    pub fn clear(&mut self) {
+        self.index.clear();
+        self.recent = [NO_BLOCK; RECENT];
+        self.blocks.clear();
+        self.covered = [0; CHIP8_RAM / 64];
+        self.invalidated = true;
+    }
+
+    // Returns whether blocks were dropped since the last call.
+    pub fn take_invalidated(&mut self) -> bool {
+        std::mem::take(&mut self.invalidated)
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    // Bytes allocated for the cached blocks, on top of `size_of::<BlockCache>()`.
+    pub fn heap_size(&self) -> usize {
+        let index = self.index.capacity() * (2 * std::mem::size_of::<usize>() + 1);
+        let blocks = self.blocks.capacity() * std::mem::size_of::<Block>();
+        let ops: usize = self
+            .blocks
+            .iter()
+            .map(|block| block.ops.capacity() * std::mem::size_of::<MicroOp>())
+            .sum();
+        index + blocks + ops
+    }
+
+    fn is_covered(&self, addr: usize) -> bool {
+        addr < CHIP8_RAM && self.covered[addr / 64] & (1 << (addr % 64)) != 0
+    }
+
+    fn cover(covered: &mut [u64; CHIP8_RAM / 64], block: &Block) {
+        for addr in block.start..block.end {
+            covered[addr / 64] |= 1 << (addr % 64);
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./blocks_test.rs"]
+mod blocks_test;
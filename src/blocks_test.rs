@@ -0,0 +1,89 @@
+use super::*;
+
+fn ram(program: &[u8]) -> [u8; CHIP8_RAM] {
+    let mut ram = [0; CHIP8_RAM];
+    ram[0x200..0x200 + program.len()].copy_from_slice(program);
+    ram
+}
+
+#[test]
+fn test_decode() {
+    assert_eq!(MicroOp::decode(0x6A12), MicroOp::Load(0xA, 0x12));
+    assert_eq!(MicroOp::decode(0x8124), MicroOp::AddReg(1, 2));
+    assert_eq!(MicroOp::decode(0xA300), MicroOp::LoadI(0x300));
+    assert_eq!(MicroOp::decode(0x5121), MicroOp::SkipEqReg(1, 2));
+    assert_eq!(MicroOp::decode(0x8128), MicroOp::Interpret(0x8128));
+    assert_eq!(MicroOp::decode(0xD125), MicroOp::Interpret(0xD125));
+
+    assert!(MicroOp::decode(0x1200).ends_block());
+    assert!(MicroOp::decode(0x00EE).ends_block());
+    assert!(MicroOp::decode(0xF10A).ends_block());
+    assert!(MicroOp::decode(0xE19E).ends_block());
+    assert!(!MicroOp::decode(0x00E0).ends_block());
+    assert!(!MicroOp::decode(0xF155).ends_block());
+}
+
+#[test]
+fn test_translate() {
+    // LD V0, 1; DRW V0, V0, 1; SE V0, 1; LD V1, 2
+    let ram = ram(&[0x60, 0x01, 0xD0, 0x01, 0x30, 0x01, 0x61, 0x02]);
+    let block = Block::translate(&ram, 0x200);
+    assert_eq!((block.start, block.end), (0x200, 0x206));
+    assert_eq!(
+        block.ops,
+        vec![
+            MicroOp::Load(0, 1),
+            MicroOp::Interpret(0xD001),
+            MicroOp::SkipEq(0, 1)
+        ]
+    );
+
+    assert_eq!(Block::translate(&ram, CHIP8_RAM - 1).ops, vec![]);
+
+    // nothing but LD V0, 1
+    let ram = [0x60, 0x01].repeat(CHIP8_RAM / 2).try_into().unwrap();
+    assert_eq!(Block::translate(&ram, 0x200).ops.len(), MAX_BLOCK_LEN);
+}
+
+#[test]
+fn test_invalidate() {
+    // LD V0, 1; LD V1, 2; JP 200
+    let ram = ram(&[0x60, 0x01, 0x61, 0x02, 0x12, 0x00]);
+    let mut cache = BlockCache::new();
+    cache.get(&ram, 0x200);
+    cache.get(&ram, 0x202);
+    assert_eq!(cache.len(), 2);
+    assert!(!cache.take_invalidated());
+
+    // after the blocks
+    cache.invalidate(0x206);
+    assert_eq!(cache.len(), 2);
+    assert!(!cache.take_invalidated());
+
+    cache.invalidate(0x201);
+    assert_eq!(cache.len(), 1);
+    assert!(cache.take_invalidated());
+    cache.invalidate(0x205);
+    assert!(cache.is_empty());
+    assert!(cache.covered.iter().all(|&covered| covered == 0));
+
+    // translated again from the new RAM
+    let mut ram = ram;
+    ram[0x201] = 0x07;
+    let block = cache.get(&ram, 0x200);
+    assert_eq!(cache.op(block, 0), MicroOp::Load(0, 7));
+}
+
+#[test]
+fn test_memory() {
+    // an empty cache is a few hundred bytes and grows with the blocks that ran
+    let mut cache = BlockCache::new();
+    assert!(std::mem::size_of::<BlockCache>() <= 1024);
+    assert_eq!(cache.heap_size(), 0);
+
+    let ram = ram(&[0x60, 0x01, 0x61, 0x02, 0x12, 0x00]);
+    let block = cache.get(&ram, 0x200);
+    assert_eq!(cache.get(&ram, 0x200), block);
+    assert_eq!(cache.block(block).ops.len(), 3);
+    assert!(cache.heap_size() < 512);
+}
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::octo;
+use crate::romdb::{RomDatabase, RomInfo};
+use crate::Quirks;
+
+// Octo "cartridges": GIF images that carry a program and its options in the pixel data.
+// https://github.com/JohnEarnest/Octo
+//
+// The low two bits of every pixel's palette index hold the data, four pixels per byte with
+// the first one in the high bits, over all frames. It starts with the payload size as a 32-bit
+// big-endian number followed by UTF-8 JSON: `{ "options": {...}, "program": ... }`.
+//
+// Cartridges saved by Octo carry the Octo source code, which is assembled when loading. An
+// array of bytes is taken as an assembled program.
+
+pub struct Cartridge {
+    pub rom: Vec<u8>,
+    // title from the file name, the rest from the cartridge options
+    pub info: RomInfo,
+}
+
+impl Cartridge {
+    pub fn is_cartridge(path: &Path) -> bool {
+        path.extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("gif"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data =
+            fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut cartridge = Cartridge::parse(&data)?;
+        if let Some(stem) = path.file_stem() {
+            cartridge.info.title = stem.to_string_lossy().into_owned();
+        }
+        Ok(cartridge)
+    }
+
+    pub fn parse(gif: &[u8]) -> Result<Self, String> {
+        let payload = Cartridge::payload(gif)?;
+        let json: Value =
+            serde_json::from_slice(&payload).map_err(|e| format!("Invalid cartridge: {}", e))?;
+        let rom = Cartridge::program(json.get("program").unwrap_or(&Value::Null))?;
+        let options = json.get("options").cloned().unwrap_or(Value::Null);
+        Ok(Cartridge {
+            rom,
+            info: Cartridge::info(&options),
+        })
+    }
+
+    // The embedded JSON.
+    fn payload(gif: &[u8]) -> Result<Vec<u8>, String> {
+        let invalid = |e: gif::DecodingError| format!("Invalid GIF: {}", e);
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(gif).map_err(invalid)?;
+
+        let mut bits = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().map_err(invalid)? {
+            bits.extend(frame.buffer.iter().map(|index| index & 0b11));
+        }
+        let bytes: Vec<u8> = bits
+            .chunks_exact(4)
+            .map(|pixels| pixels.iter().fold(0, |byte, bits| byte << 2 | bits))
+            .collect();
+
+        let size = bytes
+            .get(..4)
+            .map(|size| u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize)
+            .ok_or("Not an Octo cartridge")?;
+        bytes
+            .get(4..4 + size)
+            .map(|payload| payload.to_vec())
+            .ok_or_else(|| "Not an Octo cartridge".to_string())
+    }
+
+    fn program(program: &Value) -> Result<Vec<u8>, String> {
+        let byte = |value: &Value| value.as_u64().and_then(|n| u8::try_from(n).ok());
+        match program {
+            Value::Array(values) => values
+                .iter()
+                .map(byte)
+                .collect::<Option<_>>()
+                .ok_or_else(|| "Invalid program in cartridge".to_string()),
+            Value::String(source) => octo::assemble(source)
+                .map_err(|e| format!("Failed to assemble the cartridge program: {}", e)),
+            _ => Err("Cartridge has no program".to_string()),
+        }
+    }
+
+    // Octo's option names, quirks that aren't supported (vfOrder, vBlank) are ignored.
+    fn info(options: &Value) -> RomInfo {
+        let flag = |name: &str| match options.get(name) {
+            Some(Value::Bool(flag)) => Some(*flag),
+            Some(Value::String(flag)) => Some(flag == "true"),
+            _ => None,
+        };
+        let number = |name: &str| match options.get(name) {
+            Some(Value::Number(n)) => n.as_u64(),
+            Some(Value::String(n)) => n.parse().ok(),
+            _ => None,
+        };
+        let color = |name: &str| {
+            options
+                .get(name)
+                .and_then(|color| color.as_str())
+                .and_then(RomDatabase::parse_color)
+        };
+
+        let mut quirks = Quirks::default();
+        if let Some(shift) = flag("shiftQuirks") {
+            quirks.shift_vy = !shift;
+        }
+        if let Some(load_store) = flag("loadStoreQuirks") {
+            quirks.increment_i = !load_store;
+        }
+        if let Some(clip) = flag("clipQuirks") {
+            quirks.wrap_sprites = !clip;
+        }
+        if let Some(jump) = flag("jumpQuirks") {
+            quirks.jump_vx = jump;
+        }
+        if let Some(logic) = flag("logicQuirks") {
+            quirks.vf_reset = logic;
+        }
+
+        // Octo picks the platform through the memory size
+        let platform = number("maxSize").map(|size| match size {
+            65024 => "xochip",
+            3583 => "superchip",
+            _ => "originalChip8",
+        });
+        let colors = color("backgroundColor").zip(color("fillColor"));
+
+        RomInfo {
+            title: String::new(),
+            authors: Vec::new(),
+            platform: platform.map(|platform| platform.to_string()),
+            ips: number("tickrate")
+                .and_then(|tickrate| u32::try_from(tickrate).ok()?.checked_mul(60)),
+            quirks,
+            keys: HashMap::new(),
+            colors,
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./cartridge_test.rs"]
+mod cartridge_test;
@@ -0,0 +1,71 @@
+use super::*;
+use std::borrow::Cow;
+
+// Builds a cartridge the way Octo lays out the data: a 4 color image whose palette indices
+// carry two bits each.
+fn build_cartridge(json: &str) -> Vec<u8> {
+    let mut payload = (json.len() as u32).to_be_bytes().to_vec();
+    payload.extend_from_slice(json.as_bytes());
+    let mut pixels: Vec<u8> = payload
+        .iter()
+        .flat_map(|byte| [6, 4, 2, 0].map(|shift| (byte >> shift) & 0b11))
+        .collect();
+    let (width, height) = (32, pixels.len().div_ceil(32));
+    pixels.resize(width * height, 0);
+
+    let palette = [0, 0, 0, 85, 85, 85, 170, 170, 170, 255, 255, 255];
+    let mut gif = Vec::new();
+    let mut encoder = gif::Encoder::new(&mut gif, width as u16, height as u16, &palette).unwrap();
+    let frame = gif::Frame {
+        width: width as u16,
+        height: height as u16,
+        buffer: Cow::Owned(pixels),
+        ..gif::Frame::default()
+    };
+    encoder.write_frame(&frame).unwrap();
+    drop(encoder);
+    gif
+}
+
+#[test]
+fn test_parse() {
+    let gif = build_cartridge(
+        r##"{
+            "options": {
+                "tickrate": 20, "maxSize": 3583, "shiftQuirks": true, "loadStoreQuirks": true,
+                "clipQuirks": true, "jumpQuirks": true, "logicQuirks": false,
+                "backgroundColor": "#996600", "fillColor": "#FFCC00"
+            },
+            "program": ": main\n  clear\n  loop again"
+        }"##,
+    );
+    let cartridge = Cartridge::parse(&gif).unwrap();
+    assert_eq!(cartridge.rom, vec![0x12, 0x02, 0x00, 0xE0, 0x12, 0x04]);
+    assert_eq!(cartridge.info.quirks, Quirks::schip());
+    assert_eq!(cartridge.info.ips, Some(1200));
+    assert_eq!(cartridge.info.platform.as_deref(), Some("superchip"));
+    assert_eq!(cartridge.info.colors, Some((0x996600, 0xFFCC00)));
+
+    let gif = build_cartridge(r#"{"options": {}, "program": [0, 224, 18, 2]}"#);
+    let cartridge = Cartridge::parse(&gif).unwrap();
+    assert_eq!(cartridge.rom, vec![0x00, 0xE0, 0x12, 0x02]);
+    assert_eq!(cartridge.info.quirks, Quirks::default());
+    assert_eq!(cartridge.info.colors, None);
+
+    // a speed that doesn't fit is ignored
+    let gif = build_cartridge(r#"{"options": {"tickrate": 4294967296}, "program": [0, 224]}"#);
+    assert_eq!(Cartridge::parse(&gif).unwrap().info.ips, None);
+    let gif = build_cartridge(r#"{"options": {"tickrate": 100000000}, "program": [0, 224]}"#);
+    assert_eq!(Cartridge::parse(&gif).unwrap().info.ips, None);
+}
+
+#[test]
+fn test_invalid() {
+    let gif = build_cartridge(r#"{"options": {}, "program": ": main\n  loop"}"#);
+    let error = Cartridge::parse(&gif).err().unwrap();
+    assert!(error.contains("Missing 'again'"), "{}", error);
+    let gif = build_cartridge(r#"{"options": {}, "program": [0, 256]}"#);
+    assert!(Cartridge::parse(&gif).is_err());
+    assert!(Cartridge::parse(b"GIF89a").is_err());
+    assert!(Cartridge::parse(b"not a gif").is_err());
+}
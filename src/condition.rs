@@ -0,0 +1,327 @@
+use crate::Processor;
+
+// Small expression language for conditional breakpoints, e.g. `v3 == 0x10 && i > 0x300`.
+//
+// expr    = and ('||' and)*
+// and     = compare ('&&' compare)*
+// compare = term (('==' | '!=' | '<' | '<=' | '>' | '>=') term)?
+// term    = factor (('+' | '-' | '&' | '|' | '^') factor)*
+// factor  = unary ('*' unary)*
+// unary   = '!' unary | atom
+// atom    = number | register | '[' expr ']' | '(' expr ')'
+//
+// Registers are v0 - vf, i, pc, sp, dt and st. `[addr]` reads a byte of RAM.
+// Numbers are decimal or hex with a 0x prefix. Booleans evaluate to 0 or 1.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Operand {
+    V(usize),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum BinOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    BitAnd,
+    BitOr,
+    BitXor,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Expr {
+    Num(i64),
+    Reg(Operand),
+    Mem(Box<Expr>),
+    Not(Box<Expr>),
+    Bin(BinOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Token {
+    Num(i64),
+    Reg(Operand),
+    Op(&'static str),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = Condition::tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.expr()?;
+        if parser.pos != parser.tokens.len() {
+            return Err(format!(
+                "Unexpected {:?} in condition",
+                parser.tokens[parser.pos]
+            ));
+        }
+        Ok(Condition {
+            source: source.trim().to_string(),
+            expr,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    // Returns the operand if the whole condition is a single register, e.g. `vf`.
+    pub fn as_operand(&self) -> Option<Operand> {
+        match self.expr {
+            Expr::Reg(operand) => Some(operand),
+            _ => None,
+        }
+    }
+
+    pub fn eval(&self, processor: &Processor) -> i64 {
+        Condition::eval_expr(&self.expr, processor)
+    }
+
+    pub fn is_true(&self, processor: &Processor) -> bool {
+        self.eval(processor) != 0
+    }
+
+    pub fn read_operand(operand: Operand, processor: &Processor) -> i64 {
+        match operand {
+            Operand::V(x) => processor.register(x) as i64,
+            Operand::I => processor.i() as i64,
+            Operand::Pc => processor.pc() as i64,
+            Operand::Sp => processor.sp() as i64,
+            Operand::Dt => processor.delay_timer() as i64,
+            Operand::St => processor.sound_timer() as i64,
+        }
+    }
+
+    fn eval_expr(expr: &Expr, processor: &Processor) -> i64 {
+        match expr {
+            Expr::Num(n) => *n,
+            Expr::Reg(operand) => Condition::read_operand(*operand, processor),
+            Expr::Mem(addr) => {
+                let addr = Condition::eval_expr(addr, processor);
+                processor
+                    .ram()
+                    .get(addr as usize)
+                    .map_or(0, |&byte| byte as i64)
+            }
+            Expr::Not(expr) => (Condition::eval_expr(expr, processor) == 0) as i64,
+            Expr::Bin(op, lhs, rhs) => {
+                let lhs = Condition::eval_expr(lhs, processor);
+                // && and || short circuit
+                match op {
+                    BinOp::And if lhs == 0 => return 0,
+                    BinOp::Or if lhs != 0 => return 1,
+                    _ => {}
+                }
+                let rhs = Condition::eval_expr(rhs, processor);
+                match op {
+                    BinOp::Or | BinOp::And => (rhs != 0) as i64,
+                    BinOp::Eq => (lhs == rhs) as i64,
+                    BinOp::Ne => (lhs != rhs) as i64,
+                    BinOp::Lt => (lhs < rhs) as i64,
+                    BinOp::Le => (lhs <= rhs) as i64,
+                    BinOp::Gt => (lhs > rhs) as i64,
+                    BinOp::Ge => (lhs >= rhs) as i64,
+                    BinOp::Add => lhs.wrapping_add(rhs),
+                    BinOp::Sub => lhs.wrapping_sub(rhs),
+                    BinOp::Mul => lhs.wrapping_mul(rhs),
+                    BinOp::BitAnd => lhs & rhs,
+                    BinOp::BitOr => lhs | rhs,
+                    BinOp::BitXor => lhs ^ rhs,
+                }
+            }
+        }
+    }
+
+    pub fn parse_operand(name: &str) -> Option<Operand> {
+        let name = name.to_ascii_lowercase();
+        match name.as_str() {
+            "i" => Some(Operand::I),
+            "pc" => Some(Operand::Pc),
+            "sp" => Some(Operand::Sp),
+            "dt" => Some(Operand::Dt),
+            "st" => Some(Operand::St),
+            _ => {
+                let x = name.strip_prefix('v')?;
+                if x.len() != 1 {
+                    return None;
+                }
+                usize::from_str_radix(x, 16).ok().map(Operand::V)
+            }
+        }
+    }
+
+    fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+        // longest operators first so that `<=` isn't read as `<`
+        const OPS: [&str; 20] = [
+            "==", "!=", "<=", ">=", "&&", "||", "<", ">", "+", "-", "*", "&", "|", "^", "!", "(",
+            ")", "[", "]", "=",
+        ];
+
+        let mut tokens = Vec::new();
+        let mut rest = source.trim_start();
+        while !rest.is_empty() {
+            if let Some(op) = OPS.iter().find(|op| rest.starts_with(*op)) {
+                if *op == "=" {
+                    return Err("Use == to compare values".to_string());
+                }
+                tokens.push(Token::Op(op));
+                rest = &rest[op.len()..];
+            } else {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric())
+                    .unwrap_or(rest.len());
+                if len == 0 {
+                    return Err(format!("Unexpected character in condition: {}", rest));
+                }
+                let word = &rest[..len];
+                tokens.push(Condition::parse_word(word)?);
+                rest = &rest[len..];
+            }
+            rest = rest.trim_start();
+        }
+        Ok(tokens)
+    }
+
+    fn parse_word(word: &str) -> Result<Token, String> {
+        if let Some(operand) = Condition::parse_operand(word) {
+            return Ok(Token::Reg(operand));
+        }
+        let num = match word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+            Some(hex) => i64::from_str_radix(hex, 16),
+            None => word.parse(),
+        };
+        num.map(Token::Num)
+            .map_err(|_| format!("Unknown name in condition: {}", word))
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.peek_op() == Some(op) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("Expected {} in condition", op))
+        }
+    }
+
+    fn binary(
+        &mut self,
+        ops: &[(&str, BinOp)],
+        next: fn(&mut Parser) -> Result<Expr, String>,
+        repeat: bool,
+    ) -> Result<Expr, String> {
+        let mut lhs = next(self)?;
+        while let Some(&(_, op)) = ops.iter().find(|(s, _)| Some(*s) == self.peek_op()) {
+            self.pos += 1;
+            let rhs = next(self)?;
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(rhs));
+            if !repeat {
+                break;
+            }
+        }
+        Ok(lhs)
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        self.binary(&[("||", BinOp::Or)], Parser::and, true)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        self.binary(&[("&&", BinOp::And)], Parser::compare, true)
+    }
+
+    fn compare(&mut self) -> Result<Expr, String> {
+        let ops = [
+            ("==", BinOp::Eq),
+            ("!=", BinOp::Ne),
+            ("<", BinOp::Lt),
+            ("<=", BinOp::Le),
+            (">", BinOp::Gt),
+            (">=", BinOp::Ge),
+        ];
+        self.binary(&ops, Parser::term, false)
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let ops = [
+            ("+", BinOp::Add),
+            ("-", BinOp::Sub),
+            ("&", BinOp::BitAnd),
+            ("|", BinOp::BitOr),
+            ("^", BinOp::BitXor),
+        ];
+        self.binary(&ops, Parser::factor, true)
+    }
+
+    fn factor(&mut self) -> Result<Expr, String> {
+        self.binary(&[("*", BinOp::Mul)], Parser::unary, true)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.peek_op() == Some("!") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or("Unexpected end of condition")?;
+        self.pos += 1;
+        match token {
+            Token::Num(n) => Ok(Expr::Num(n)),
+            Token::Reg(operand) => Ok(Expr::Reg(operand)),
+            Token::Op("(") => {
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Op("[") => {
+                let expr = self.expr()?;
+                self.expect("]")?;
+                Ok(Expr::Mem(Box::new(expr)))
+            }
+            Token::Op(op) => Err(format!("Unexpected {} in condition", op)),
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./condition_test.rs"]
+mod condition_test;
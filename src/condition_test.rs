@@ -0,0 +1,51 @@
+use super::*;
+
+fn build_processor() -> Processor {
+    let mut processor = Processor::new();
+    processor.set_register(0x3, 0x10);
+    processor.set_register(0xF, 0x01);
+    processor.set_i(0x345);
+    processor.ram_mut()[0x345] = 0x42;
+    processor
+}
+
+fn eval(source: &str) -> i64 {
+    Condition::parse(source).unwrap().eval(&build_processor())
+}
+
+#[test]
+fn test_eval() {
+    assert_eq!(eval("v3 == 0x10 && i > 0x300"), 1);
+    assert_eq!(eval("v3 == 0x10 && i > 0x400"), 0);
+    assert_eq!(eval("v3 != 16 || VF == 1"), 1);
+    assert_eq!(eval("[i] == 0x42"), 1);
+    assert_eq!(eval("[i + 1]"), 0);
+    assert_eq!(eval("!(pc >= 0x200)"), 0);
+    assert_eq!(eval("v3 + vf - 1"), 0x10);
+    assert_eq!(eval("i & 0xF0 | 1"), 0x41);
+    assert_eq!(eval("dt <= st"), 1);
+    assert_eq!(eval("[i] * 10 + v3 * 2"), 0x42 * 10 + 0x20);
+}
+
+#[test]
+fn test_operand() {
+    assert_eq!(
+        Condition::parse("vf").unwrap().as_operand(),
+        Some(Operand::V(0xF))
+    );
+    assert_eq!(
+        Condition::parse(" sp ").unwrap().as_operand(),
+        Some(Operand::Sp)
+    );
+    assert_eq!(Condition::parse("vf == 1").unwrap().as_operand(), None);
+}
+
+#[test]
+fn test_parse_errors() {
+    assert!(Condition::parse("").is_err());
+    assert!(Condition::parse("v3 = 1").is_err());
+    assert!(Condition::parse("v10 == 1").is_err());
+    assert!(Condition::parse("(v3 == 1").is_err());
+    assert!(Condition::parse("v3 == 1 v4").is_err());
+    assert!(Condition::parse("v3 == $").is_err());
+}
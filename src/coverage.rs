@@ -0,0 +1,181 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+use crate::analyzer::Analysis;
+use crate::disassembler::disassemble;
+use crate::{CHIP8_RAM, OPCODE_SIZE};
+
+// Records which instructions ran and which way every skip instruction went, and exports it
+// as an annotated disassembly or, with a source map from the assembler, as an lcov
+// tracefile (https://github.com/linux-test-project/lcov) for the usual coverage tools.
+
+const START: usize = 0x200;
+
+pub struct Coverage {
+    counts: Vec<u64>,
+    // skip instruction address -> [times it fell through, times it skipped]
+    branches: BTreeMap<usize, [u64; 2]>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Coverage::new()
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage {
+            counts: vec![0; CHIP8_RAM],
+            branches: BTreeMap::new(),
+        }
+    }
+
+    // Called after the instruction at `pc` ran and moved the PC to `next_pc`.
+    pub fn record(&mut self, pc: usize, opcode: u16, next_pc: usize) {
+        self.counts[pc] += 1;
+        if Coverage::is_skip(opcode) {
+            let skipped = next_pc == pc + 2 * OPCODE_SIZE;
+            self.branches.entry(pc).or_default()[skipped as usize] += 1;
+        }
+    }
+
+    pub fn count(&self, addr: usize) -> u64 {
+        self.counts.get(addr).copied().unwrap_or(0)
+    }
+
+    // [not skipped, skipped] for a skip instruction.
+    pub fn branch(&self, addr: usize) -> [u64; 2] {
+        self.branches.get(&addr).copied().unwrap_or_default()
+    }
+
+    pub fn is_skip(opcode: u16) -> bool {
+        match opcode & 0xF000 {
+            0x3000 | 0x4000 => true,
+            0x5000 | 0x9000 => opcode & 0x000F == 0,
+            0xE000 => matches!(opcode & 0x00FF, 0x9E | 0xA1),
+            _ => false,
+        }
+    }
+
+    // gcov style listing of the ROM: execution counts in front of every instruction, `#####`
+    // for code that never ran and `-` for data.
+    pub fn write_annotated(&self, rom: &[u8], out: &mut impl Write) -> io::Result<()> {
+        let rom = &rom[..rom.len().min(CHIP8_RAM - START)];
+        let analysis = Analysis::new(rom);
+        for offset in (0..rom.len()).step_by(OPCODE_SIZE) {
+            let addr = START + offset;
+            let opcode =
+                u16::from_be_bytes([rom[offset], rom.get(offset + 1).copied().unwrap_or(0)]);
+            let count = self.count(addr);
+            if let Some(label) = analysis.label(addr) {
+                writeln!(out, "{:>10}: {}:", "", label)?;
+            }
+            if count == 0 && !analysis.code.contains(&addr) {
+                writeln!(out, "{:>10}: {:03X}  {:04X}", "-", addr, opcode)?;
+                continue;
+            }
+
+            let count = match count {
+                0 => "#####".to_string(),
+                count => count.to_string(),
+            };
+            let mut line = format!(
+                "{:>10}: {:03X}  {:04X}  {}",
+                count,
+                addr,
+                opcode,
+                disassemble(opcode)
+            );
+            if Coverage::is_skip(opcode) {
+                let [next, skipped] = self.branch(addr);
+                line = format!("{:<44} ; next {}, skipped {}", line, next, skipped);
+            }
+            writeln!(out, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+
+    // lcov tracefile with line and branch coverage of every source line in `map`.
+    pub fn write_lcov(&self, map: &SourceMap, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "TN:")?;
+        for (file, lines) in map.files.iter() {
+            writeln!(out, "SF:{}", file)?;
+            let (mut hit_lines, mut branches, mut hit_branches) = (0, 0, 0);
+            for (&line, addrs) in lines.iter() {
+                let count: u64 = addrs.iter().map(|&addr| self.count(addr)).sum();
+                writeln!(out, "DA:{},{}", line, count)?;
+                hit_lines += (count > 0) as usize;
+
+                for (block, &addr) in addrs
+                    .iter()
+                    .filter(|addr| map.skips.contains(addr))
+                    .enumerate()
+                {
+                    let counts = self.branch(addr);
+                    for (branch, &taken) in counts.iter().enumerate() {
+                        // `-` marks branches of instructions that never ran
+                        let taken = if self.count(addr) == 0 {
+                            "-".to_string()
+                        } else {
+                            taken.to_string()
+                        };
+                        writeln!(out, "BRDA:{},{},{},{}", line, block, branch, taken)?;
+                        branches += 1;
+                        hit_branches += (counts[branch] > 0) as usize;
+                    }
+                }
+            }
+            writeln!(out, "BRF:{}\nBRH:{}", branches, hit_branches)?;
+            writeln!(out, "LF:{}\nLH:{}", lines.len(), hit_lines)?;
+            writeln!(out, "end_of_record")?;
+        }
+        Ok(())
+    }
+}
+
+// Which source line every instruction came from, one `ADDR FILE:LINE` pair per line, e.g.
+// `0x202 game.8o:14`. Lines starting with `#` are comments.
+#[derive(Default, Debug)]
+pub struct SourceMap {
+    // file -> line -> addresses
+    files: BTreeMap<String, BTreeMap<u32, Vec<usize>>>,
+    // addresses of skip instructions, so that branches that never ran are listed too
+    skips: BTreeSet<usize>,
+}
+
+impl SourceMap {
+    pub fn parse(data: &str, rom: &[u8]) -> Result<Self, String> {
+        let mut map = SourceMap::default();
+        for (n, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || format!("Invalid source map entry on line {}: {}", n + 1, line);
+            let (addr, location) = line.split_once(char::is_whitespace).ok_or_else(invalid)?;
+            let addr =
+                usize::from_str_radix(addr.trim_start_matches("0x"), 16).map_err(|_| invalid())?;
+            let (file, source_line) = location.trim().rsplit_once(':').ok_or_else(invalid)?;
+            let source_line = source_line.parse().map_err(|_| invalid())?;
+
+            let offset = addr.checked_sub(START).ok_or_else(invalid)?;
+            if let Some(bytes) = rom.get(offset..offset + OPCODE_SIZE) {
+                if Coverage::is_skip(u16::from_be_bytes([bytes[0], bytes[1]])) {
+                    map.skips.insert(addr);
+                }
+            }
+            map.files
+                .entry(file.to_string())
+                .or_default()
+                .entry(source_line)
+                .or_default()
+                .push(addr);
+        }
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+#[path = "./coverage_test.rs"]
+mod coverage_test;
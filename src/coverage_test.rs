@@ -0,0 +1,59 @@
+use super::*;
+use crate::{HeadlessKeypad, Processor};
+
+// 200: LD V0, 1 / 202: SE V0, 1 / 204: JP 204 / 206: SNE V0, 1 / 208: JP 208
+const ROM: [u8; 10] = [0x60, 0x01, 0x30, 0x01, 0x12, 0x04, 0x40, 0x01, 0x12, 0x08];
+
+fn run() -> Coverage {
+    let mut processor = Processor::new();
+    let mut keypad = HeadlessKeypad::new();
+    processor.load(&ROM);
+    processor.set_coverage(Some(Coverage::new()));
+    for _ in 0..5 {
+        processor.emulate_cycle(&mut keypad);
+    }
+    processor.set_coverage(None).unwrap()
+}
+
+#[test]
+fn test_record() {
+    let coverage = run();
+    assert_eq!(coverage.count(0x200), 1);
+    assert_eq!(coverage.count(0x204), 0);
+    assert_eq!(coverage.count(0x208), 2);
+    assert_eq!(coverage.branch(0x202), [0, 1]);
+    assert_eq!(coverage.branch(0x206), [1, 0]);
+}
+
+#[test]
+fn test_annotated() {
+    let mut out = Vec::new();
+    run().write_annotated(&ROM, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines[1], "         1: 200  6001  LD V0, 0x01");
+    assert_eq!(
+        lines[2],
+        "         1: 202  3001  SE V0, 0x01           ; next 0, skipped 1"
+    );
+    assert_eq!(lines[4], "     #####: 204  1204  JP 0x204");
+}
+
+#[test]
+fn test_lcov() {
+    let map = SourceMap::parse(
+        "# generated\n0x200 game.8o:3\n202 game.8o:4\n204 game.8o:5\n",
+        &ROM,
+    )
+    .unwrap();
+    let mut out = Vec::new();
+    run().write_lcov(&map, &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "TN:\nSF:game.8o\nDA:3,1\nDA:4,1\nBRDA:4,0,0,0\nBRDA:4,0,1,1\nDA:5,0\n\
+         BRF:2\nBRH:1\nLF:3\nLH:2\nend_of_record\n"
+    );
+
+    assert!(SourceMap::parse("0x200", &ROM).is_err());
+    assert!(SourceMap::parse("zz game.8o:1", &ROM).is_err());
+}
@@ -0,0 +1,288 @@
+use std::ops::RangeInclusive;
+
+use crate::condition::{Condition, Operand};
+use crate::smc::{SmcEvent, SmcTracker};
+use crate::symbols::Symbols;
+use crate::{Processor, OPCODE_SIZE, STACK_SIZE};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    pub fn matches(self, access: Access) -> bool {
+        self == Access::ReadWrite || self == access
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<usize>,
+    pub access: Access,
+}
+
+// Why the debugger stopped the target.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stop {
+    Step,
+    Interrupt,
+    Breakpoint(usize),
+    Watchpoint { addr: usize, access: Access },
+    RegisterChanged(Operand),
+    IndexOverflow,
+    SelfModifyingCode(SmcEvent),
+    // 00EE with an empty stack
+    StackUnderflow,
+    // 2nnn with all 16 entries in use
+    StackOverflow,
+    // a call nested deeper than the configured limit
+    StackDepth(usize),
+    // an opcode that isn't an instruction, it is skipped
+    InvalidOpcode(u16),
+}
+
+struct Breakpoint {
+    addr: usize,
+    condition: Option<Condition>,
+}
+
+// Breaks when a register changes or when a condition becomes true.
+struct Watch {
+    condition: Condition,
+    last: i64,
+}
+
+// Breakpoints and watches that are checked between cycles. Memory watchpoints live in the
+// `Processor` itself since they have to be checked on every access.
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watches: Vec<Watch>,
+    symbols: Symbols,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger::default()
+    }
+
+    // Lets commands take names instead of addresses.
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    pub fn add_breakpoint(&mut self, addr: usize, condition: Option<Condition>) {
+        self.remove_breakpoint(addr);
+        self.breakpoints.push(Breakpoint { addr, condition });
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: usize) {
+        self.breakpoints.retain(|bp| bp.addr != addr);
+    }
+
+    pub fn add_watch(&mut self, condition: Condition, processor: &Processor) {
+        let last = condition.eval(processor);
+        self.watches.push(Watch { condition, last });
+    }
+
+    pub fn clear(&mut self, processor: &mut Processor) {
+        self.breakpoints.clear();
+        self.watches.clear();
+        processor.clear_watchpoints();
+        // keep tracking, only stop breaking on it
+        if let Some(smc) = processor.smc_tracker_mut() {
+            smc.set_break(false);
+        }
+    }
+
+    // Checked after every cycle.
+    pub fn check(&mut self, processor: &mut Processor) -> Option<Stop> {
+        if let Some(stop) = processor.take_watch_hit() {
+            return Some(stop);
+        }
+
+        let mut stop = None;
+        for watch in self.watches.iter_mut() {
+            let value = watch.condition.eval(processor);
+            let changed = value != watch.last;
+            watch.last = value;
+            if !changed || stop.is_some() {
+                continue;
+            }
+            stop = match watch.condition.as_operand() {
+                Some(operand) => Some(Stop::RegisterChanged(operand)),
+                // conditions only stop when they become true
+                None if value != 0 => Some(Stop::Breakpoint(processor.pc())),
+                None => None,
+            };
+        }
+        if stop.is_some() {
+            return stop;
+        }
+
+        let pc = processor.pc();
+        let hit = self.breakpoints.iter().any(|bp| {
+            bp.addr == pc
+                && bp
+                    .condition
+                    .as_ref()
+                    .is_none_or(|condition| condition.is_true(processor))
+        });
+        hit.then_some(Stop::Breakpoint(pc))
+    }
+
+    // Runs a `monitor` command and returns its output.
+    pub fn command(&mut self, processor: &mut Processor, line: &str) -> Result<String, String> {
+        let line = line.trim();
+        let (cmd, args) = line.split_once(' ').unwrap_or((line, ""));
+        let args = args.trim();
+        match cmd {
+            "break" => {
+                let (addr, condition) = match args.split_once(" if ") {
+                    Some((addr, condition)) => (addr, Some(Condition::parse(condition)?)),
+                    None => (args, None),
+                };
+                let addr = self.symbols.resolve(addr)?;
+                self.add_breakpoint(addr, condition);
+                Ok(format!("Breakpoint at {:03X}\n", addr))
+            }
+            "delete" => {
+                self.remove_breakpoint(self.symbols.resolve(args)?);
+                Ok(String::new())
+            }
+            "watch" => {
+                let condition = Condition::parse(args)?;
+                let output = format!("Watching {}\n", condition.source());
+                self.add_watch(condition, processor);
+                Ok(output)
+            }
+            "rwatch" | "wwatch" | "awatch" => {
+                let access = match cmd {
+                    "rwatch" => Access::Read,
+                    "wwatch" => Access::Write,
+                    _ => Access::ReadWrite,
+                };
+                let range = self.symbols.resolve_range(args)?;
+                let output = format!("Watching {:03X}-{:03X}\n", range.start(), range.end());
+                processor.add_watchpoint(Watchpoint { range, access });
+                Ok(output)
+            }
+            "overflow" => {
+                let enabled = match args {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err("Usage: overflow on|off".to_string()),
+                };
+                processor.set_break_on_index_overflow(enabled);
+                Ok(String::new())
+            }
+            "smc" => {
+                match args {
+                    // tracking keeps what it has seen so far when switching to break
+                    "on" | "break" => {
+                        if processor.smc_tracker().is_none() {
+                            processor.set_smc_tracker(Some(SmcTracker::new()));
+                        }
+                        let smc = processor.smc_tracker_mut().unwrap();
+                        smc.set_break(args == "break");
+                    }
+                    "off" => processor.set_smc_tracker(None),
+                    "" => {}
+                    _ => return Err("Usage: smc [on|break|off]".to_string()),
+                }
+                let mut report = Vec::new();
+                if let Some(smc) = processor.smc_tracker().filter(|_| args.is_empty()) {
+                    smc.write_report(&mut report).map_err(|e| e.to_string())?;
+                }
+                Ok(String::from_utf8_lossy(&report).into_owned())
+            }
+            "bt" => Ok(self.backtrace(processor)),
+            "depth" => {
+                let limit = match args {
+                    "off" => None,
+                    _ => match args.parse() {
+                        Ok(limit) if limit > 0 => Some(limit),
+                        _ => return Err("Usage: depth N|off".to_string()),
+                    },
+                };
+                processor.set_stack_depth_limit(limit);
+                Ok(String::new())
+            }
+            "clear" => {
+                self.clear(processor);
+                Ok(String::new())
+            }
+            "info" => Ok(self.info(processor)),
+            _ => Err(format!("Unknown command {}", cmd)),
+        }
+    }
+
+    fn info(&self, processor: &Processor) -> String {
+        let mut output = String::new();
+        for bp in self.breakpoints.iter() {
+            output += &format!("break {:03X}", bp.addr);
+            if let Some(name) = self.symbols.name(bp.addr) {
+                output += &format!(" <{}>", name);
+            }
+            if let Some(condition) = &bp.condition {
+                output += &format!(" if {}", condition.source());
+            }
+            output += "\n";
+        }
+        for watch in self.watches.iter() {
+            output += &format!("watch {}\n", watch.condition.source());
+        }
+        for wp in processor.watchpoints() {
+            output += &format!(
+                "{:?} {:03X}-{:03X}\n",
+                wp.access,
+                wp.range.start(),
+                wp.range.end()
+            );
+        }
+        if processor.break_on_index_overflow() {
+            output += "overflow on\n";
+        }
+        if let Some(smc) = processor.smc_tracker() {
+            output += if smc.breaks() {
+                "smc break\n"
+            } else {
+                "smc on\n"
+            };
+        }
+        if let Some(limit) = processor.stack_depth_limit() {
+            output += &format!("depth {}\n", limit);
+        }
+        output
+    }
+
+    // The current PC followed by every active call, innermost first. Each frame shows the
+    // call instruction and where it returns to.
+    pub fn backtrace(&self, processor: &Processor) -> String {
+        let pc = processor.pc();
+        let mut output = format!("#0  {:03X} <{}>\n", pc, self.symbols.describe(pc));
+        for (n, &ret) in processor.stack().iter().rev().enumerate() {
+            let caller = ret.saturating_sub(OPCODE_SIZE);
+            output += &format!(
+                "#{:<2} {:03X} <{}>, returns to {:03X}\n",
+                n + 1,
+                caller,
+                self.symbols.describe(caller),
+                ret
+            );
+        }
+        output += &format!("depth {}/{}\n", processor.stack().len(), STACK_SIZE);
+        output
+    }
+}
+
+#[cfg(test)]
+#[path = "./debugger_test.rs"]
+mod debugger_test;
@@ -0,0 +1,143 @@
+use super::*;
+
+#[test]
+fn test_conditional_breakpoint() {
+    let mut debugger = Debugger::new();
+    let mut processor = Processor::new();
+    debugger
+        .command(&mut processor, "break 0x204 if v3 == 0x10 && i > 0x300")
+        .unwrap();
+
+    processor.set_pc(0x204);
+    assert_eq!(debugger.check(&mut processor), None);
+    processor.set_register(3, 0x10);
+    processor.set_i(0x301);
+    assert_eq!(
+        debugger.check(&mut processor),
+        Some(Stop::Breakpoint(0x204))
+    );
+
+    debugger.command(&mut processor, "delete 204").unwrap();
+    assert_eq!(debugger.check(&mut processor), None);
+}
+
+#[test]
+fn test_watch() {
+    let mut debugger = Debugger::new();
+    let mut processor = Processor::new();
+    debugger.command(&mut processor, "watch v2").unwrap();
+    debugger.command(&mut processor, "watch vf == 1").unwrap();
+
+    assert_eq!(debugger.check(&mut processor), None);
+    processor.set_register(2, 5);
+    assert_eq!(
+        debugger.check(&mut processor),
+        Some(Stop::RegisterChanged(Operand::V(2)))
+    );
+    assert_eq!(debugger.check(&mut processor), None);
+
+    // conditions only break when they become true
+    processor.set_register(0xF, 1);
+    assert_eq!(
+        debugger.check(&mut processor),
+        Some(Stop::Breakpoint(0x200))
+    );
+    processor.set_register(0xF, 0);
+    assert_eq!(debugger.check(&mut processor), None);
+}
+
+#[test]
+fn test_commands() {
+    let mut debugger = Debugger::new();
+    let mut processor = Processor::new();
+    debugger.command(&mut processor, "break 300").unwrap();
+    debugger.command(&mut processor, "awatch 400-40F").unwrap();
+    debugger.command(&mut processor, "overflow on").unwrap();
+    assert_eq!(
+        debugger.command(&mut processor, "info").unwrap(),
+        "break 300\nReadWrite 400-40F\noverflow on\n"
+    );
+
+    assert!(debugger.command(&mut processor, "break zz").is_err());
+    assert!(debugger.command(&mut processor, "watch v3 =").is_err());
+    assert!(debugger.command(&mut processor, "overflow maybe").is_err());
+
+    debugger.command(&mut processor, "clear").unwrap();
+    assert_eq!(debugger.command(&mut processor, "info").unwrap(), "");
+}
+
+#[test]
+fn test_smc_break() {
+    let mut debugger = Debugger::new();
+    let mut processor = Processor::new();
+    let mut keypad = crate::HeadlessKeypad::new();
+    // writes CLS over the JP at 208 and then runs into it
+    processor.load(&[0x60, 0x00, 0x61, 0xE0, 0xA2, 0x08, 0xF1, 0x55, 0x12, 0x08]);
+    debugger.command(&mut processor, "smc break").unwrap();
+    assert_eq!(
+        debugger.command(&mut processor, "info").unwrap(),
+        "smc break\n"
+    );
+
+    let mut stops = Vec::new();
+    for _ in 0..5 {
+        processor.emulate_cycle(&mut keypad);
+        stops.extend(debugger.check(&mut processor));
+    }
+    assert_eq!(stops.len(), 1);
+    match stops[0] {
+        Stop::SelfModifyingCode(event) => {
+            assert_eq!(event.kind, crate::smc::SmcKind::ExecuteWritten);
+            assert_eq!(event.pc, 0x208);
+        }
+        stop => panic!("unexpected stop {:?}", stop),
+    }
+    assert!(debugger
+        .command(&mut processor, "smc")
+        .unwrap()
+        .contains("executes written 208"));
+}
+
+#[test]
+fn test_symbols() {
+    let mut debugger = Debugger::new();
+    let mut processor = Processor::new();
+    debugger.set_symbols(Symbols::parse("2A0 draw\n300 sprite\n").unwrap());
+    assert_eq!(
+        debugger.command(&mut processor, "break draw").unwrap(),
+        "Breakpoint at 2A0\n"
+    );
+    debugger
+        .command(&mut processor, "awatch sprite-30F")
+        .unwrap();
+    assert_eq!(
+        debugger.command(&mut processor, "info").unwrap(),
+        "break 2A0 <draw>\nReadWrite 300-30F\n"
+    );
+    assert!(debugger.command(&mut processor, "break nowhere").is_err());
+}
+
+#[test]
+fn test_backtrace() {
+    let mut debugger = Debugger::new();
+    let mut processor = Processor::new();
+    let mut keypad = crate::HeadlessKeypad::new();
+    debugger.set_symbols(Symbols::parse("200 main\n20A draw\n").unwrap());
+    // 200: CALL 20A / 202: JP 202 / ... / 20A: CALL 20E / 20C: RET / 20E: RET
+    processor.load(&[
+        0x22, 0x0A, 0x12, 0x02, 0, 0, 0, 0, 0, 0, 0x22, 0x0E, 0x00, 0xEE, 0x00, 0xEE,
+    ]);
+    processor.emulate_cycle(&mut keypad);
+    processor.emulate_cycle(&mut keypad);
+    assert_eq!(
+        debugger.command(&mut processor, "bt").unwrap(),
+        "#0  20E <draw+4>\n#1  20A <draw>, returns to 20C\n#2  200 <main>, returns to 202\ndepth 2/16\n"
+    );
+
+    debugger.command(&mut processor, "depth 1").unwrap();
+    assert_eq!(
+        debugger.command(&mut processor, "info").unwrap(),
+        "depth 1\n"
+    );
+    assert!(debugger.command(&mut processor, "depth 0").is_err());
+}
@@ -0,0 +1,85 @@
+// Mnemonics follow Cowgod's Chip-8 Technical Reference.
+// http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#3.1
+
+use crate::symbols::Symbols;
+
+pub fn disassemble(opcode: u16) -> String {
+    disassemble_with(opcode, |addr| format!("0x{:03X}", addr))
+}
+
+// Same as `disassemble` but shows the address operands of JP, CALL and LD I by name.
+pub fn disassemble_symbolic(opcode: u16, symbols: &Symbols) -> String {
+    disassemble_with(opcode, |addr| match symbols.name(addr) {
+        Some(name) => name.to_string(),
+        None => format!("0x{:03X}", addr),
+    })
+}
+
+// `addr` formats the address operands.
+pub fn disassemble_with(opcode: u16, addr: impl Fn(usize) -> String) -> String {
+    let x = (opcode & 0x0F00) >> 8;
+    let y = (opcode & 0x00F0) >> 4;
+    let n = opcode & 0x000F;
+    let kk = opcode & 0x00FF;
+    let nnn = (opcode & 0x0FFF) as usize;
+
+    match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00E0 => "CLS".to_string(),
+            0x00EE => "RET".to_string(),
+            _ => format!("SYS 0x{:03X}", nnn),
+        },
+        0x1000 => format!("JP {}", addr(nnn)),
+        0x2000 => format!("CALL {}", addr(nnn)),
+        0x3000 => format!("SE V{:X}, 0x{:02X}", x, kk),
+        0x4000 => format!("SNE V{:X}, 0x{:02X}", x, kk),
+        0x5000 if n == 0 => format!("SE V{:X}, V{:X}", x, y),
+        0x6000 => format!("LD V{:X}, 0x{:02X}", x, kk),
+        0x7000 => format!("ADD V{:X}, 0x{:02X}", x, kk),
+        0x8000 => match n {
+            0x0 => format!("LD V{:X}, V{:X}", x, y),
+            0x1 => format!("OR V{:X}, V{:X}", x, y),
+            0x2 => format!("AND V{:X}, V{:X}", x, y),
+            0x3 => format!("XOR V{:X}, V{:X}", x, y),
+            0x4 => format!("ADD V{:X}, V{:X}", x, y),
+            0x5 => format!("SUB V{:X}, V{:X}", x, y),
+            0x6 => format!("SHR V{:X}, V{:X}", x, y),
+            0x7 => format!("SUBN V{:X}, V{:X}", x, y),
+            0xE => format!("SHL V{:X}, V{:X}", x, y),
+            _ => unknown(opcode),
+        },
+        0x9000 if n == 0 => format!("SNE V{:X}, V{:X}", x, y),
+        0xA000 => format!("LD I, {}", addr(nnn)),
+        0xB000 => format!("JP V0, {}", addr(nnn)),
+        0xC000 => format!("RND V{:X}, 0x{:02X}", x, kk),
+        0xD000 => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        0xE000 => match kk {
+            0x9E => format!("SKP V{:X}", x),
+            0xA1 => format!("SKNP V{:X}", x),
+            _ => unknown(opcode),
+        },
+        0xF000 => match kk {
+            0x07 => format!("LD V{:X}, DT", x),
+            0x0A => format!("LD V{:X}, K", x),
+            0x15 => format!("LD DT, V{:X}", x),
+            0x18 => format!("LD ST, V{:X}", x),
+            0x1E => format!("ADD I, V{:X}", x),
+            0x29 => format!("LD F, V{:X}", x),
+            0x33 => format!("LD B, V{:X}", x),
+            0x55 => format!("LD [I], V{:X}", x),
+            0x65 => format!("LD V{:X}, [I]", x),
+            0x75 => format!("LD R, V{:X}", x),
+            0x85 => format!("LD V{:X}, R", x),
+            _ => unknown(opcode),
+        },
+        _ => unknown(opcode),
+    }
+}
+
+fn unknown(opcode: u16) -> String {
+    format!("DW 0x{:04X}", opcode)
+}
+
+#[cfg(test)]
+#[path = "./disassembler_test.rs"]
+mod disassembler_test;
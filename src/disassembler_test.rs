@@ -0,0 +1,33 @@
+use super::*;
+
+#[test]
+fn test_disassemble() {
+    assert_eq!(disassemble(0x00E0), "CLS");
+    assert_eq!(disassemble(0x00EE), "RET");
+    assert_eq!(disassemble(0x0123), "SYS 0x123");
+    assert_eq!(disassemble(0x1ABC), "JP 0xABC");
+    assert_eq!(disassemble(0x3A10), "SE VA, 0x10");
+    assert_eq!(disassemble(0x8126), "SHR V1, V2");
+    assert_eq!(disassemble(0xB300), "JP V0, 0x300");
+    assert_eq!(disassemble(0xD125), "DRW V1, V2, 5");
+    assert_eq!(disassemble(0xF355), "LD [I], V3");
+    assert_eq!(disassemble(0xF365), "LD V3, [I]");
+    assert_eq!(disassemble(0xF775), "LD R, V7");
+    assert_eq!(disassemble(0xF785), "LD V7, R");
+}
+
+#[test]
+fn test_disassemble_invalid() {
+    assert_eq!(disassemble(0x5121), "DW 0x5121");
+    assert_eq!(disassemble(0x8128), "DW 0x8128");
+    assert_eq!(disassemble(0xE1FF), "DW 0xE1FF");
+    assert_eq!(disassemble(0xF1FF), "DW 0xF1FF");
+}
+
+#[test]
+fn test_disassemble_symbolic() {
+    let symbols = Symbols::parse("2A0 draw\n300 sprite\n").unwrap();
+    assert_eq!(disassemble_symbolic(0x22A0, &symbols), "CALL draw");
+    assert_eq!(disassemble_symbolic(0xA300, &symbols), "LD I, sprite");
+    assert_eq!(disassemble_symbolic(0x1202, &symbols), "JP 0x202");
+}
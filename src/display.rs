@@ -1,13 +1,25 @@
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
+use sdl2::render::BlendMode;
 use sdl2::{render::Canvas, video::Window};
 
+use crate::bitmap::Bitmap;
+use crate::framebuffer::Framebuffer;
+use crate::overlay::{Overlay, GLYPH_HEIGHT, GLYPH_WIDTH};
 use crate::CHIP8_SCREEN_HEIGHT;
 use crate::CHIP8_SCREEN_WIDTH;
 use crate::SCALE_FACTOR;
 
+// size of an overlay font pixel in window pixels
+const OVERLAY_SCALE: usize = 2;
+
 pub struct Display {
     canvas: Canvas<Window>,
+    background: Color,
+    foreground: Color,
+    // last rendered screen, so that the overlay can be redrawn on its own
+    frame: Framebuffer,
+    overlay: Overlay,
 }
 
 impl Display {
@@ -27,17 +39,53 @@ impl Display {
         let mut canvas = window.into_canvas().build().unwrap();
         canvas.set_draw_color(Color::RGB(0, 0, 0));
         canvas.clear();
+        canvas.set_blend_mode(BlendMode::Blend);
+
+        Display {
+            canvas,
+            background: Color::RGB(0, 0, 0),
+            foreground: Color::RGB(255, 255, 255),
+            frame: Framebuffer::new(),
+            overlay: Overlay::new(),
+        }
+    }
 
-        Display { canvas }
+    pub fn overlay(&self) -> &Overlay {
+        &self.overlay
+    }
+
+    pub fn overlay_mut(&mut self) -> &mut Overlay {
+        &mut self.overlay
+    }
+
+    // Colors as 0xRRGGBB, e.g. from the ROM database.
+    pub fn set_palette(&mut self, background: u32, foreground: u32) {
+        self.background = Color::RGB(
+            (background >> 16) as u8,
+            (background >> 8) as u8,
+            background as u8,
+        );
+        self.foreground = Color::RGB(
+            (foreground >> 16) as u8,
+            (foreground >> 8) as u8,
+            foreground as u8,
+        );
     }
 
     // TODO: try without &mut for self
-    pub fn render(&mut self, vram: &[[u8; CHIP8_SCREEN_WIDTH]; CHIP8_SCREEN_HEIGHT]) {
+    pub fn render(&mut self, vram: &Framebuffer) {
+        self.frame = *vram;
+        self.refresh();
+    }
+
+    // Draws the last frame again, e.g. after the overlay changed.
+    pub fn refresh(&mut self) {
         let scale = SCALE_FACTOR as usize;
         // TODO: try with &row
-        for (y, row) in vram.iter().enumerate() {
-            for (x, &col) in row.iter().enumerate() {
-                self.canvas.set_draw_color(Display::get_color(col));
+        for y in 0..CHIP8_SCREEN_HEIGHT {
+            for x in 0..CHIP8_SCREEN_WIDTH {
+                self.canvas
+                    .set_draw_color(self.get_color(self.frame.pixel(x, y)));
                 let x = (x * scale) as i32;
                 let y = (y * scale) as i32;
                 self.canvas
@@ -45,21 +93,79 @@ impl Display {
                     .unwrap();
             }
         }
+        if self.overlay.is_active() {
+            self.draw_overlay();
+        }
+        self.canvas.present();
+    }
+
+    // Draws a screen of any size scaled to the window, e.g. the launcher.
+    pub fn render_bitmap(&mut self, bitmap: &Bitmap) {
+        let scale = (CHIP8_SCREEN_WIDTH * SCALE_FACTOR as usize / bitmap.width).max(1);
+        self.canvas.set_draw_color(self.background);
+        self.canvas.clear();
+        let mut pixels = Vec::new();
+        for y in 0..bitmap.height {
+            for x in 0..bitmap.width {
+                if bitmap.get(x, y) {
+                    let (x, y) = ((x * scale) as i32, (y * scale) as i32);
+                    pixels.push(Rect::new(x, y, scale as u32, scale as u32));
+                }
+            }
+        }
+        self.canvas.set_draw_color(self.foreground);
+        self.canvas.fill_rects(&pixels).unwrap();
         self.canvas.present();
     }
 
     // TODO: try without &mut for self
     pub fn clear(&mut self) {
-        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.frame.clear();
+        self.canvas.set_draw_color(self.background);
         self.canvas.clear();
+        if self.overlay.is_active() {
+            self.draw_overlay();
+        }
         self.canvas.present();
     }
 
-    fn get_color(pixel: u8) -> Color {
-        if pixel == 0 {
-            Color::RGB(0, 0, 0)
+    // Each line of text goes on a translucent box so that it stays readable on any screen.
+    fn draw_overlay(&mut self) {
+        let line_height = (GLYPH_HEIGHT + 2) * OVERLAY_SCALE;
+        for (n, line) in self.overlay.lines().iter().enumerate() {
+            let top = n * line_height;
+            let width = line.chars().count() * (GLYPH_WIDTH + 1) + 1;
+            self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+            self.canvas
+                .fill_rect(Rect::new(
+                    0,
+                    top as i32,
+                    (width * OVERLAY_SCALE) as u32,
+                    line_height as u32,
+                ))
+                .unwrap();
+
+            self.canvas.set_draw_color(Color::RGB(255, 255, 0));
+            for (x, y) in Overlay::text_pixels(line) {
+                let x = (x + 1) * OVERLAY_SCALE;
+                let y = top + (y + 1) * OVERLAY_SCALE;
+                self.canvas
+                    .fill_rect(Rect::new(
+                        x as i32,
+                        y as i32,
+                        OVERLAY_SCALE as u32,
+                        OVERLAY_SCALE as u32,
+                    ))
+                    .unwrap();
+            }
+        }
+    }
+
+    fn get_color(&self, pixel: bool) -> Color {
+        if pixel {
+            self.foreground
         } else {
-            Color::RGB(255, 255, 255)
+            self.background
         }
     }
 }
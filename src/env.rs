@@ -0,0 +1,310 @@
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::batch::Batch;
+use crate::bitmap::Bitmap;
+use crate::condition::Condition;
+use crate::scheduler::Scheduler;
+use crate::{HeadlessKeypad, Processor, Quirks};
+
+// Gym style environment for training agents on CHIP-8 games: `reset` starts an episode and
+// `step` holds a set of keys for a few frames and returns the screen, the reward and whether
+// the episode is over.
+//
+// What counts as reward and as the end of an episode is described per ROM with the same
+// expressions as conditional breakpoints (see `Condition`), e.g. for a score kept as BCD
+// digits at 0x3F0 and a lives counter in V5:
+//
+// {"score": "[0x3F0] * 100 + [0x3F1] * 10 + [0x3F2]", "done": "v5 == 0", "frame_skip": 4}
+//
+// The reward of a step is how much `score` went up (or down) during it.
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSpec {
+    quirks: Option<String>,
+    ips: Option<u32>,
+    frame_skip: Option<u32>,
+    score: Option<String>,
+    done: Option<String>,
+    max_steps: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EnvSpec {
+    pub quirks: Quirks,
+    pub ips: u32,
+    // 60 Hz frames per step, all with the same keys held
+    pub frame_skip: u32,
+    pub score: Option<Condition>,
+    pub done: Option<Condition>,
+    // episodes also end after this many steps
+    pub max_steps: Option<u64>,
+}
+
+impl Default for EnvSpec {
+    fn default() -> Self {
+        EnvSpec {
+            quirks: Quirks::default(),
+            ips: 1000,
+            frame_skip: 1,
+            score: None,
+            done: None,
+            max_steps: None,
+        }
+    }
+}
+
+impl EnvSpec {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        EnvSpec::parse(&json)
+    }
+
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let raw: RawSpec =
+            serde_json::from_str(json).map_err(|e| format!("Invalid environment: {}", e))?;
+        let mut spec = EnvSpec::default();
+        if let Some(profile) = raw.quirks {
+            spec.quirks = Quirks::profile(&profile)
+                .ok_or_else(|| format!("Unknown quirks profile {}", profile))?;
+        }
+        spec.ips = raw.ips.unwrap_or(spec.ips);
+        spec.frame_skip = raw.frame_skip.unwrap_or(spec.frame_skip).max(1);
+        spec.score = raw.score.as_deref().map(Condition::parse).transpose()?;
+        spec.done = raw.done.as_deref().map(Condition::parse).transpose()?;
+        spec.max_steps = raw.max_steps;
+        Ok(spec)
+    }
+}
+
+pub struct Env {
+    spec: EnvSpec,
+    processor: Processor,
+    keypad: HeadlessKeypad,
+    episode: Episode,
+}
+
+impl Env {
+    pub fn new(rom: &[u8], spec: EnvSpec) -> Self {
+        let mut processor = Processor::new();
+        processor.set_quirks(spec.quirks);
+        processor.set_block_cache(true);
+        processor.load(rom);
+        let mut env = Env {
+            spec,
+            processor,
+            keypad: HeadlessKeypad::new(),
+            episode: Episode::default(),
+        };
+        env.reset(0);
+        env
+    }
+
+    // Starts a new episode, the same seed gives the same random numbers (Cxkk) every time.
+    pub fn reset(&mut self, seed: u64) -> Bitmap {
+        self.processor.reset();
+        self.processor.seed_rng(seed);
+        self.keypad.release_all();
+        self.episode = Episode::start(&self.spec, &self.processor);
+        self.observation()
+    }
+
+    // Holds `keys` (CHIP-8 keys 0x0 - 0xF) for `frame_skip` frames. Once the episode is over
+    // nothing runs until the next `reset`.
+    //
+    // Panics on keys above 0xF, those are a bug in the agent's action space and would
+    // otherwise press a different key.
+    pub fn step(&mut self, keys: &[u8]) -> (Bitmap, f64, bool) {
+        let (reward, done) =
+            self.episode
+                .step(&self.spec, &mut self.processor, &mut self.keypad, keys);
+        (self.observation(), reward, done)
+    }
+
+    pub fn observation(&self) -> Bitmap {
+        Bitmap::from_vram(self.processor.vram())
+    }
+
+    pub fn score(&self) -> i64 {
+        self.episode.score
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.episode.steps
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.episode.done
+    }
+
+    pub fn spec(&self) -> &EnvSpec {
+        &self.spec
+    }
+
+    pub fn processor(&self) -> &Processor {
+        &self.processor
+    }
+}
+
+// Many copies of the same game stepped together on all cores, the processors are run by a
+// `Batch`.
+pub struct VecEnv {
+    spec: EnvSpec,
+    batch: Batch,
+    episodes: Vec<Episode>,
+}
+
+impl VecEnv {
+    pub fn from_rom(rom: &[u8], spec: &EnvSpec, count: usize) -> Self {
+        let mut envs = VecEnv {
+            spec: spec.clone(),
+            batch: Batch::new(count, rom, spec.quirks, spec.ips),
+            episodes: vec![Episode::default(); count],
+        };
+        envs.reset(0);
+        envs
+    }
+
+    pub fn len(&self) -> usize {
+        self.episodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.episodes.is_empty()
+    }
+
+    // e.g. for all screens in one buffer with `batch().screens()`
+    pub fn batch(&self) -> &Batch {
+        &self.batch
+    }
+
+    pub fn spec(&self) -> &EnvSpec {
+        &self.spec
+    }
+
+    pub fn score(&self, index: usize) -> i64 {
+        self.episodes[index].score
+    }
+
+    pub fn steps(&self, index: usize) -> u64 {
+        self.episodes[index].steps
+    }
+
+    pub fn is_done(&self, index: usize) -> bool {
+        self.episodes[index].done
+    }
+
+    // Resets every environment, the n-th one with `seed + n`.
+    pub fn reset(&mut self, seed: u64) -> Vec<Bitmap> {
+        self.batch.reset();
+        self.batch.seed(seed);
+        let spec = &self.spec;
+        let items = vec![(); self.episodes.len()];
+        let (episodes, observations) = self
+            .batch
+            .map(items, |processor, keypad, ()| {
+                keypad.release_all();
+                let episode = Episode::start(spec, processor);
+                (episode, Bitmap::from_vram(processor.vram()))
+            })
+            .into_iter()
+            .unzip();
+        self.episodes = episodes;
+        observations
+    }
+
+    // Resets a single environment, e.g. once it's done.
+    pub fn reset_one(&mut self, index: usize, seed: u64) -> Bitmap {
+        let processor = &mut self.batch.processors_mut()[index];
+        processor.reset();
+        processor.seed_rng(seed);
+        self.batch.keypad_mut(index).release_all();
+        let processor = &self.batch.processors()[index];
+        self.episodes[index] = Episode::start(&self.spec, processor);
+        Bitmap::from_vram(processor.vram())
+    }
+
+    // One set of keys per environment, the results are in the same order. See `Env::step`.
+    pub fn step<A: AsRef<[u8]> + Sync>(&mut self, actions: &[A]) -> Vec<(Bitmap, f64, bool)> {
+        assert_eq!(actions.len(), self.len(), "one action per environment");
+        let spec = &self.spec;
+        let items: Vec<_> = self.episodes.iter_mut().zip(actions).collect();
+        self.batch.map(items, |processor, keypad, (episode, keys)| {
+            let (reward, done) = episode.step(spec, processor, keypad, keys.as_ref());
+            (Bitmap::from_vram(processor.vram()), reward, done)
+        })
+    }
+}
+
+// Score and progress of the running episode, the same for `Env` and `VecEnv`.
+#[derive(Clone, Copy, Default)]
+struct Episode {
+    // value of `spec.score` after the last step
+    score: i64,
+    steps: u64,
+    done: bool,
+}
+
+impl Episode {
+    // After the processor was reset.
+    fn start(spec: &EnvSpec, processor: &Processor) -> Self {
+        Episode {
+            score: Episode::eval_score(spec, processor),
+            steps: 0,
+            done: false,
+        }
+    }
+
+    fn step(
+        &mut self,
+        spec: &EnvSpec,
+        processor: &mut Processor,
+        keypad: &mut HeadlessKeypad,
+        keys: &[u8],
+    ) -> (f64, bool) {
+        if let Some(key) = keys.iter().find(|&&key| key > 0xF) {
+            panic!("Invalid key {:#X}, keys are 0x0 - 0xF", key);
+        }
+        if self.done {
+            return (0.0, true);
+        }
+
+        keypad.release_all();
+        for &key in keys {
+            keypad.set_key(key, true);
+        }
+        let cycles = Scheduler::cycles_per_frame(spec.ips);
+        for _ in 0..spec.frame_skip {
+            processor.run_frame(keypad, cycles);
+            if Episode::is_over(spec, processor) {
+                self.done = true;
+                break;
+            }
+        }
+        self.steps += 1;
+        self.done |= spec.max_steps.is_some_and(|max| self.steps >= max);
+
+        let score = Episode::eval_score(spec, processor);
+        let reward = (score - self.score) as f64;
+        self.score = score;
+        (reward, self.done)
+    }
+
+    fn eval_score(spec: &EnvSpec, processor: &Processor) -> i64 {
+        spec.score.as_ref().map_or(0, |score| score.eval(processor))
+    }
+
+    fn is_over(spec: &EnvSpec, processor: &Processor) -> bool {
+        spec.done
+            .as_ref()
+            .is_some_and(|done| done.is_true(processor))
+    }
+}
+
+#[cfg(test)]
+#[path = "./env_test.rs"]
+mod env_test;
@@ -0,0 +1,119 @@
+use super::*;
+
+// ADD V0, 1 while key 5 is held:
+// LD V1, 5; SKNP V1; ADD V0, 1; JP 202
+const ROM: [u8; 8] = [0x61, 0x05, 0xE1, 0xA1, 0x70, 0x01, 0x12, 0x02];
+
+fn spec() -> EnvSpec {
+    EnvSpec::parse(r#"{"ips": 180, "score": "v0", "done": "v0 >= 6", "frame_skip": 2}"#).unwrap()
+}
+
+#[test]
+fn test_parse_spec() {
+    let spec = spec();
+    assert_eq!(spec.ips, 180);
+    assert_eq!(spec.frame_skip, 2);
+    assert_eq!(spec.score.unwrap().source(), "v0");
+    assert_eq!(spec.max_steps, None);
+
+    let spec = EnvSpec::parse(r#"{"quirks": "schip", "max_steps": 10}"#).unwrap();
+    assert_eq!(spec.quirks, Quirks::schip());
+    assert_eq!(spec.max_steps, Some(10));
+    assert!(EnvSpec::parse(r#"{"quirks": "nes"}"#).is_err());
+    assert!(EnvSpec::parse(r#"{"done": "v0 = 1"}"#).is_err());
+    assert!(EnvSpec::parse(r#"{"reward": "v0"}"#).is_err());
+}
+
+#[test]
+fn test_step() {
+    let mut env = Env::new(&ROM, spec());
+    let (_, reward, done) = env.step(&[]);
+    assert_eq!((reward, done), (0.0, false));
+
+    // 3 instructions per frame and 2 frames per step, the loop is 3 instructions long
+    let (_, reward, done) = env.step(&[5]);
+    assert_eq!((reward, done), (2.0, false));
+    let (_, reward, done) = env.step(&[5, 7]);
+    assert_eq!((reward, done), (2.0, false));
+    let (_, reward, done) = env.step(&[5]);
+    assert_eq!((reward, done), (2.0, true));
+    assert_eq!(env.score(), 6);
+    assert_eq!(env.step(&[5]).1, 0.0);
+
+    let observation = env.reset(1);
+    assert_eq!((observation.width, observation.height), (64, 32));
+    assert_eq!((env.score(), env.steps(), env.is_done()), (0, 0, false));
+}
+
+#[test]
+#[should_panic(expected = "Invalid key 0x10")]
+fn test_invalid_key() {
+    Env::new(&ROM, spec()).step(&[5, 0x10]);
+}
+
+#[test]
+fn test_max_steps() {
+    let mut spec = spec();
+    spec.max_steps = Some(2);
+    let mut env = Env::new(&ROM, spec);
+    assert!(!env.step(&[]).2);
+    assert!(env.step(&[]).2);
+}
+
+#[test]
+fn test_seed() {
+    // RND V0, 0xFF; JP 200
+    let rom = [0xC0, 0xFF, 0x12, 0x00];
+    let spec = EnvSpec::parse(r#"{"ips": 60, "score": "v0"}"#).unwrap();
+    let mut env = Env::new(&rom, spec);
+    let run = |env: &mut Env, seed| {
+        env.reset(seed);
+        env.step(&[]);
+        env.score()
+    };
+    let first = run(&mut env, 42);
+    assert_eq!(run(&mut env, 42), first);
+    assert!((0..8).any(|seed| run(&mut env, seed) != first));
+}
+
+#[test]
+fn test_vec_env() {
+    let mut envs = VecEnv::from_rom(&ROM, &spec(), 5);
+    assert_eq!(envs.reset(0).len(), 5);
+    let actions: Vec<Vec<u8>> = (0..5)
+        .map(|n| if n % 2 == 0 { vec![5] } else { vec![] })
+        .collect();
+    let results = envs.step(&actions);
+
+    let mut env = Env::new(&ROM, spec());
+    for (keys, result) in actions.iter().zip(&results) {
+        env.reset(0);
+        assert_eq!(env.step(keys), *result);
+    }
+    assert!(results[0].1 > 0.0);
+    assert_eq!(results[1].1, 0.0);
+
+    // the batch holds the same screens
+    for (index, (bitmap, _, _)) in results.iter().enumerate() {
+        let screen: Vec<bool> = envs.batch().screen(index).iter().map(|&p| p != 0).collect();
+        assert_eq!(bitmap.pixels, screen);
+    }
+}
+
+#[test]
+fn test_vec_env_reset_one() {
+    let mut envs = VecEnv::from_rom(&ROM, &spec(), 2);
+    envs.step(&[vec![5], vec![5]]);
+    assert_eq!(envs.steps(0), 1);
+
+    let observation = envs.reset_one(0, 0);
+    assert_eq!(envs.steps(0), 0);
+    assert_eq!(envs.score(0), 0);
+    assert_eq!(envs.steps(1), 1);
+    assert_eq!(observation, Env::new(&ROM, spec()).observation());
+
+    let results = envs.step(&[vec![5], vec![]]);
+    assert!(results[0].1 > 0.0);
+    assert_eq!(envs.steps(0), 1);
+    assert_eq!(envs.steps(1), 2);
+}
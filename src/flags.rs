@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// SUPER-CHIP games keep high scores in the HP-48's "RPL user flags" with Fx75 (save V0 - Vx)
+// and Fx85 (restore them). SUPER-CHIP has 8 of them and XO-CHIP 16, so 16 are kept for every
+// ROM. They outlive the emulator by going through a `FlagStore`, keyed by the ROM's SHA-1.
+
+pub const RPL_FLAGS: usize = 16;
+
+pub type RplFlags = [u8; RPL_FLAGS];
+
+pub trait FlagStore: Send {
+    // The flags saved for a ROM, `None` if there are none yet.
+    fn load(&mut self, key: &str) -> io::Result<Option<RplFlags>>;
+    fn save(&mut self, key: &str, flags: &RplFlags) -> io::Result<()>;
+}
+
+// Keeps the flags for as long as it lives, for tests and headless runs. Clones share the same
+// flags so one can be kept to look at what a processor saved.
+#[derive(Clone, Default)]
+pub struct MemoryFlagStore {
+    flags: Arc<Mutex<HashMap<String, RplFlags>>>,
+}
+
+impl MemoryFlagStore {
+    pub fn new() -> Self {
+        MemoryFlagStore::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<RplFlags> {
+        self.flags.lock().unwrap().get(key).copied()
+    }
+}
+
+impl FlagStore for MemoryFlagStore {
+    fn load(&mut self, key: &str) -> io::Result<Option<RplFlags>> {
+        Ok(self.get(key))
+    }
+
+    fn save(&mut self, key: &str, flags: &RplFlags) -> io::Result<()> {
+        self.flags.lock().unwrap().insert(key.to_string(), *flags);
+        Ok(())
+    }
+}
+
+// One `<sha1>.rpl` file with the raw flags per ROM in a directory that is created when the
+// first flags are saved.
+pub struct FileFlagStore {
+    dir: PathBuf,
+}
+
+impl FileFlagStore {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        FileFlagStore {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.rpl", key))
+    }
+}
+
+impl FlagStore for FileFlagStore {
+    fn load(&mut self, key: &str) -> io::Result<Option<RplFlags>> {
+        let data = match fs::read(self.path(key)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        // shorter files, e.g. only the 8 SUPER-CHIP flags, are padded with zeros
+        if data.len() > RPL_FLAGS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not an RPL flags file", self.path(key).display()),
+            ));
+        }
+        let mut flags = [0; RPL_FLAGS];
+        flags[..data.len()].copy_from_slice(&data);
+        Ok(Some(flags))
+    }
+
+    fn save(&mut self, key: &str, flags: &RplFlags) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.path(key), flags)
+    }
+}
+
+#[cfg(test)]
+#[path = "./flags_test.rs"]
+mod flags_test;
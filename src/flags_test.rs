@@ -0,0 +1,31 @@
+use super::*;
+
+#[test]
+fn test_file_store() {
+    let dir = std::env::temp_dir().join(format!("chip8-flags-{}", std::process::id()));
+    let mut store = FileFlagStore::new(&dir);
+    assert_eq!(store.load("abc").unwrap(), None);
+
+    let mut flags = [0; RPL_FLAGS];
+    flags[..3].copy_from_slice(&[1, 2, 3]);
+    store.save("abc", &flags).unwrap();
+    assert_eq!(FileFlagStore::new(&dir).load("abc").unwrap(), Some(flags));
+
+    // only the SUPER-CHIP flags
+    fs::write(store.path("def"), [9; 8]).unwrap();
+    let flags = store.load("def").unwrap().unwrap();
+    assert_eq!(flags[..9], [9, 9, 9, 9, 9, 9, 9, 9, 0]);
+
+    fs::write(store.path("ghi"), [0; 17]).unwrap();
+    assert!(store.load("ghi").is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_memory_store_is_shared() {
+    let store = MemoryFlagStore::new();
+    let mut clone = store.clone();
+    clone.save("abc", &[7; RPL_FLAGS]).unwrap();
+    assert_eq!(store.get("abc"), Some([7; RPL_FLAGS]));
+    assert_eq!(store.get("def"), None);
+}
@@ -0,0 +1,73 @@
+use crate::{CHIP8_SCREEN_HEIGHT, CHIP8_SCREEN_WIDTH};
+
+// The screen with one bit per pixel, a row is a u64 with the leftmost pixel in the most
+// significant bit. 256 bytes instead of one byte per pixel keeps processors small when
+// thousands of them run at once.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Framebuffer {
+    rows: [u64; CHIP8_SCREEN_HEIGHT],
+}
+
+impl Framebuffer {
+    pub fn new() -> Self {
+        Framebuffer::default()
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.rows[y] & Framebuffer::mask(x) != 0
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, on: bool) {
+        if on {
+            self.rows[y] |= Framebuffer::mask(x);
+        } else {
+            self.rows[y] &= !Framebuffer::mask(x);
+        }
+    }
+
+    // Flips a pixel and returns whether it was on, i.e. whether drawing it collided.
+    pub fn toggle(&mut self, x: usize, y: usize) -> bool {
+        let was_on = self.pixel(x, y);
+        self.rows[y] ^= Framebuffer::mask(x);
+        was_on
+    }
+
+    // XORs a whole row of pixels onto row `y`, returns whether any lit pixel was turned off.
+    pub fn draw_row(&mut self, y: usize, pixels: u64) -> bool {
+        let collision = self.rows[y] & pixels != 0;
+        self.rows[y] ^= pixels;
+        collision
+    }
+
+    pub fn clear(&mut self) {
+        self.rows = [0; CHIP8_SCREEN_HEIGHT];
+    }
+
+    pub fn rows(&self) -> &[u64; CHIP8_SCREEN_HEIGHT] {
+        &self.rows
+    }
+
+    // Every pixel from the top left, row by row.
+    pub fn pixels(&self) -> impl Iterator<Item = bool> + '_ {
+        self.rows
+            .iter()
+            .flat_map(|&row| (0..CHIP8_SCREEN_WIDTH).map(move |x| row & Framebuffer::mask(x) != 0))
+    }
+
+    // One byte (0 or 1) per pixel into `out`, which holds `CHIP8_SCREEN_WIDTH *
+    // CHIP8_SCREEN_HEIGHT` bytes.
+    pub fn write_bytes(&self, out: &mut [u8]) {
+        for (byte, on) in out.iter_mut().zip(self.pixels()) {
+            *byte = on as u8;
+        }
+    }
+
+    fn mask(x: usize) -> u64 {
+        1 << (CHIP8_SCREEN_WIDTH - 1 - x)
+    }
+}
+
+#[cfg(test)]
+#[path = "./framebuffer_test.rs"]
+mod framebuffer_test;
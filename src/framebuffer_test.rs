@@ -0,0 +1,27 @@
+use super::*;
+
+#[test]
+fn test_pixels() {
+    let mut vram = Framebuffer::new();
+    vram.set(0, 0, true);
+    vram.set(63, 31, true);
+    assert!(vram.pixel(0, 0));
+    assert!(!vram.pixel(1, 0));
+    assert_eq!(vram.rows()[0], 1 << 63);
+    assert_eq!(vram.rows()[31], 1);
+
+    assert!(vram.toggle(0, 0));
+    assert!(!vram.toggle(2, 1));
+    assert!(!vram.pixel(0, 0));
+    assert!(vram.pixel(2, 1));
+    vram.set(2, 1, false);
+    assert!(!vram.pixel(2, 1));
+
+    let mut bytes = [0xFF; CHIP8_SCREEN_WIDTH * CHIP8_SCREEN_HEIGHT];
+    vram.write_bytes(&mut bytes);
+    assert_eq!(bytes.iter().filter(|&&byte| byte == 1).count(), 1);
+    assert_eq!(bytes[bytes.len() - 1], 1);
+
+    vram.clear();
+    assert_eq!(vram, Framebuffer::new());
+}
@@ -0,0 +1,438 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::debugger::{Access, Debugger, Stop, Watchpoint};
+use crate::smc::SmcKind;
+use crate::symbols::Symbols;
+use crate::Processor;
+use crate::CHIP8_RAM;
+
+// GDB Remote Serial Protocol stub.
+// https://sourceware.org/gdb/onlinedocs/gdb/Remote-Protocol.html
+//
+// There is no CHIP-8 target description in GDB, so the register file is laid out as:
+// * 0x00 - 0x0F: V0 - VF (8-bit)
+// * 0x10: I (16-bit, little endian)
+// * 0x11: PC (16-bit, little endian)
+// * 0x12: SP (8-bit)
+// * 0x13: DT (8-bit)
+// * 0x14: ST (8-bit)
+
+const REG_I: usize = 0x10;
+const REG_PC: usize = 0x11;
+const REG_SP: usize = 0x12;
+const REG_DT: usize = 0x13;
+const REG_ST: usize = 0x14;
+const REG_COUNT: usize = 0x15;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum State {
+    Halted,
+    Running,
+    Stepping,
+    Detached,
+}
+
+pub struct GdbStub {
+    stream: TcpStream,
+    debugger: Debugger,
+    state: State,
+    last_stop: Stop,
+}
+
+impl GdbStub {
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        println!("Waiting for GDB on 127.0.0.1:{}", port);
+        GdbStub::accept(&listener)
+    }
+
+    pub fn accept(listener: &TcpListener) -> io::Result<Self> {
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        Ok(GdbStub {
+            stream,
+            debugger: Debugger::new(),
+            state: State::Halted,
+            last_stop: Stop::Step,
+        })
+    }
+
+    // Lets `monitor` commands take symbol names instead of addresses.
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.debugger.set_symbols(symbols);
+    }
+
+    // Called before every cycle. While the target is halted this blocks serving the debugger
+    // until it asks to continue or step. Returns false once the debugger killed the target.
+    pub fn should_step(&mut self, processor: &mut Processor) -> io::Result<bool> {
+        match self.state {
+            State::Detached | State::Stepping => return Ok(true),
+            State::Running => {
+                if !self.poll_interrupt()? {
+                    return Ok(true);
+                }
+                self.stop(Stop::Interrupt)?;
+            }
+            State::Halted => {}
+        }
+
+        while self.state == State::Halted {
+            let packet = match self.read_packet()? {
+                Some(packet) => packet,
+                None => continue,
+            };
+            if packet == "k" {
+                return Ok(false);
+            }
+            let reply = self.handle_packet(&packet, processor);
+            if let Some(reply) = reply {
+                self.send_packet(&reply)?;
+            }
+        }
+        Ok(true)
+    }
+
+    // Called after every cycle to report single steps, breakpoint and watchpoint hits.
+    pub fn stepped(&mut self, processor: &mut Processor) -> io::Result<()> {
+        if self.state == State::Detached {
+            return Ok(());
+        }
+        match self.debugger.check(processor) {
+            Some(stop) => self.stop(stop),
+            None if self.state == State::Stepping => self.stop(Stop::Step),
+            None => Ok(()),
+        }
+    }
+
+    fn stop(&mut self, stop: Stop) -> io::Result<()> {
+        self.state = State::Halted;
+        self.last_stop = stop;
+        self.send_packet(&GdbStub::stop_reply(stop))
+    }
+
+    fn stop_reply(stop: Stop) -> String {
+        match stop {
+            Stop::Interrupt => format!("S{:02x}", SIGINT),
+            Stop::Breakpoint(_) => format!("T{:02x}swbreak:;", SIGTRAP),
+            Stop::Watchpoint { addr, access } => {
+                let kind = match access {
+                    Access::Read => "rwatch",
+                    Access::Write => "watch",
+                    Access::ReadWrite => "awatch",
+                };
+                format!("T{:02x}{}:{:x};", SIGTRAP, kind, addr)
+            }
+            Stop::SelfModifyingCode(event) if event.kind == SmcKind::WriteToCode => {
+                format!("T{:02x}watch:{:x};", SIGTRAP, event.addr)
+            }
+            Stop::Step
+            | Stop::RegisterChanged(_)
+            | Stop::IndexOverflow
+            | Stop::SelfModifyingCode(_)
+            | Stop::StackDepth(_) => format!("S{:02x}", SIGTRAP),
+            Stop::StackUnderflow | Stop::StackOverflow => {
+                format!("S{:02x}", SIGSEGV)
+            }
+            Stop::InvalidOpcode(_) => format!("S{:02x}", SIGILL),
+        }
+    }
+
+    // Returns None for packets that change the run state and must not be answered right away.
+    fn handle_packet(&mut self, packet: &str, processor: &mut Processor) -> Option<String> {
+        if packet.is_empty() || !packet.is_char_boundary(1) {
+            return Some(String::new());
+        }
+        let (cmd, args) = packet.split_at(1);
+        let reply = match cmd {
+            "?" => GdbStub::stop_reply(self.last_stop),
+            "g" => (0..REG_COUNT)
+                .map(|reg| GdbStub::read_register(processor, reg))
+                .collect(),
+            "G" => GdbStub::write_registers(processor, args),
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(reg) if reg < REG_COUNT => GdbStub::read_register(processor, reg),
+                _ => "E01".to_string(),
+            },
+            "P" => GdbStub::write_register_packet(processor, args),
+            "m" => GdbStub::read_memory(processor, args),
+            "M" => GdbStub::write_memory(processor, args),
+            "Z" | "z" => self.update_breakpoint(processor, cmd == "Z", args),
+            "c" | "s" => {
+                match GdbStub::parse_hex(args) {
+                    // the next fetch would read past the end of RAM
+                    Some(addr) if addr >= CHIP8_RAM - 1 => return Some("E02".to_string()),
+                    Some(addr) => processor.set_pc(addr),
+                    None => {}
+                }
+                self.state = if cmd == "c" {
+                    State::Running
+                } else {
+                    State::Stepping
+                };
+                return None;
+            }
+            "D" => {
+                self.debugger.clear(processor);
+                self.state = State::Detached;
+                "OK".to_string()
+            }
+            "H" => "OK".to_string(),
+            "q" if args.starts_with("Supported") => "PacketSize=4000;swbreak+;hwbreak+".to_string(),
+            "q" if args == "Attached" => "1".to_string(),
+            "q" if args.starts_with("Rcmd,") => self.monitor(processor, &args[5..]),
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    fn read_register(processor: &Processor, reg: usize) -> String {
+        match reg {
+            REG_I => GdbStub::encode_u16(processor.i() as u16),
+            REG_PC => GdbStub::encode_u16(processor.pc() as u16),
+            REG_SP => format!("{:02x}", processor.sp() as u8),
+            REG_DT => format!("{:02x}", processor.delay_timer()),
+            REG_ST => format!("{:02x}", processor.sound_timer()),
+            _ => format!("{:02x}", processor.register(reg)),
+        }
+    }
+
+    fn write_register(processor: &mut Processor, reg: usize, bytes: &[u8]) -> bool {
+        let word = || bytes[0] as usize | (*bytes.get(1).unwrap_or(&0) as usize) << 8;
+        match reg {
+            REG_I if word() < CHIP8_RAM => processor.set_i(word()),
+            REG_PC if word() < CHIP8_RAM - 1 => processor.set_pc(word()),
+            // the stack only has 16 entries
+            REG_SP if bytes[0] <= 16 => processor.set_sp(bytes[0] as usize),
+            REG_DT => processor.set_delay_timer(bytes[0]),
+            REG_ST => processor.set_sound_timer(bytes[0]),
+            0x00..=0x0F => processor.set_register(reg, bytes[0]),
+            _ => return false,
+        }
+        true
+    }
+
+    fn register_size(reg: usize) -> usize {
+        if reg == REG_I || reg == REG_PC {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn write_registers(processor: &mut Processor, args: &str) -> String {
+        let bytes = match GdbStub::decode_hex(args) {
+            Some(bytes) => bytes,
+            None => return "E01".to_string(),
+        };
+        let mut offset = 0;
+        let mut ok = true;
+        for reg in 0..REG_COUNT {
+            let size = GdbStub::register_size(reg);
+            if offset + size > bytes.len() {
+                break;
+            }
+            ok &= GdbStub::write_register(processor, reg, &bytes[offset..offset + size]);
+            offset += size;
+        }
+        if ok {
+            "OK".to_string()
+        } else {
+            "E02".to_string()
+        }
+    }
+
+    fn write_register_packet(processor: &mut Processor, args: &str) -> String {
+        let (reg, value) = match args.split_once('=') {
+            Some(split) => split,
+            None => return "E01".to_string(),
+        };
+        let reg = GdbStub::parse_hex(reg);
+        let value = GdbStub::decode_hex(value);
+        match (reg, value) {
+            (Some(reg), Some(value)) if reg < REG_COUNT && !value.is_empty() => {
+                if GdbStub::write_register(processor, reg, &value) {
+                    "OK".to_string()
+                } else {
+                    "E02".to_string()
+                }
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn parse_range(args: &str) -> Option<(usize, usize)> {
+        let (addr, len) = args.split_once(',')?;
+        let (addr, len) = (GdbStub::parse_hex(addr)?, GdbStub::parse_hex(len)?);
+        addr.checked_add(len)
+            .filter(|&end| end <= CHIP8_RAM)
+            .map(|_| (addr, len))
+    }
+
+    fn read_memory(processor: &Processor, args: &str) -> String {
+        match GdbStub::parse_range(args) {
+            Some((addr, len)) => processor.ram()[addr..addr + len]
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+            None => "E01".to_string(),
+        }
+    }
+
+    fn write_memory(processor: &mut Processor, args: &str) -> String {
+        let (range, data) = match args.split_once(':') {
+            Some(split) => split,
+            None => return "E01".to_string(),
+        };
+        match (GdbStub::parse_range(range), GdbStub::decode_hex(data)) {
+            (Some((addr, len)), Some(data)) if data.len() == len => {
+                processor.ram_mut()[addr..addr + len].copy_from_slice(&data);
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn update_breakpoint(&mut self, processor: &mut Processor, insert: bool, args: &str) -> String {
+        // Z0 = software breakpoint, Z1 = hardware breakpoint. Both behave the same here.
+        // Z2, Z3 and Z4 are write, read and access watchpoints where kind is the length.
+        let mut parts = args.split(',');
+        let (ty, addr, len) = match (
+            parts.next(),
+            parts.next().and_then(GdbStub::parse_hex),
+            parts.next().and_then(GdbStub::parse_hex),
+        ) {
+            (Some(ty), Some(addr), Some(len)) => (ty, addr, len.max(1)),
+            _ => return "E01".to_string(),
+        };
+
+        let access = match ty {
+            "0" | "1" => {
+                if insert {
+                    self.debugger.add_breakpoint(addr, None);
+                } else {
+                    self.debugger.remove_breakpoint(addr);
+                }
+                return "OK".to_string();
+            }
+            "2" => Access::Write,
+            "3" => Access::Read,
+            "4" => Access::ReadWrite,
+            _ => return String::new(),
+        };
+        let end = match addr.checked_add(len - 1) {
+            Some(end) => end,
+            None => return "E01".to_string(),
+        };
+        let watchpoint = Watchpoint {
+            range: addr..=end,
+            access,
+        };
+        if insert {
+            processor.add_watchpoint(watchpoint);
+        } else {
+            processor.remove_watchpoint(&watchpoint);
+        }
+        "OK".to_string()
+    }
+
+    // `monitor` commands are hex encoded and so is their output.
+    fn monitor(&mut self, processor: &mut Processor, args: &str) -> String {
+        let line = match GdbStub::decode_hex(args).and_then(|line| String::from_utf8(line).ok()) {
+            Some(line) => line,
+            None => return "E01".to_string(),
+        };
+        let output = match self.debugger.command(processor, &line) {
+            Ok(output) if output.is_empty() => return "OK".to_string(),
+            Ok(output) => output,
+            Err(e) => format!("{}\n", e),
+        };
+        output.bytes().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn poll_interrupt(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut byte = [0u8; 1];
+        let result = self.stream.read(&mut byte);
+        self.stream.set_nonblocking(false)?;
+        match result {
+            Ok(0) => Err(ErrorKind::UnexpectedEof.into()),
+            Ok(_) => Ok(byte[0] == 0x03),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0u8; 1];
+        self.stream.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    // Packets look like `$data#cs`. Acks (`+`/`-`) from the debugger are ignored.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                b'$' => break,
+                0x03 => return Ok(Some("?".to_string())),
+                _ => {}
+            }
+        }
+
+        let mut data = Vec::new();
+        loop {
+            match self.read_byte()? {
+                b'#' => break,
+                byte => data.push(byte),
+            }
+        }
+        let mut checksum = [0u8; 2];
+        self.stream.read_exact(&mut checksum)?;
+
+        let expected = std::str::from_utf8(&checksum)
+            .ok()
+            .and_then(|cs| u8::from_str_radix(cs, 16).ok());
+        if expected != Some(GdbStub::checksum(&data)) {
+            self.stream.write_all(b"-")?;
+            return Ok(None);
+        }
+        self.stream.write_all(b"+")?;
+        Ok(Some(String::from_utf8_lossy(&data).into_owned()))
+    }
+
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, GdbStub::checksum(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes())
+    }
+
+    fn checksum(data: &[u8]) -> u8 {
+        data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+    }
+
+    fn encode_u16(val: u16) -> String {
+        format!("{:02x}{:02x}", val & 0xFF, val >> 8)
+    }
+
+    fn parse_hex(s: &str) -> Option<usize> {
+        usize::from_str_radix(s, 16).ok()
+    }
+
+    fn decode_hex(s: &str) -> Option<Vec<u8>> {
+        if !s.len().is_multiple_of(2) {
+            return None;
+        }
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+#[path = "./gdb_test.rs"]
+mod gdb_test;
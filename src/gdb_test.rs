@@ -0,0 +1,241 @@
+use super::*;
+use crate::HeadlessKeypad;
+use std::thread;
+
+// Minimal GDB client talking to the stub over loopback.
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn send(&mut self, data: &str) -> String {
+        let packet = format!("${}#{:02x}", data, GdbStub::checksum(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes()).unwrap();
+        let mut ack = [0u8; 1];
+        self.stream.read_exact(&mut ack).unwrap();
+        assert_eq!(ack[0], b'+');
+        self.recv()
+    }
+
+    fn send_no_reply(&mut self, data: &str) {
+        let packet = format!("${}#{:02x}", data, GdbStub::checksum(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes()).unwrap();
+        let mut ack = [0u8; 1];
+        self.stream.read_exact(&mut ack).unwrap();
+        assert_eq!(ack[0], b'+');
+    }
+
+    fn recv(&mut self) -> String {
+        let mut byte = [0u8; 1];
+        loop {
+            self.stream.read_exact(&mut byte).unwrap();
+            if byte[0] == b'$' {
+                break;
+            }
+        }
+        let mut data = Vec::new();
+        loop {
+            self.stream.read_exact(&mut byte).unwrap();
+            if byte[0] == b'#' {
+                break;
+            }
+            data.push(byte[0]);
+        }
+        let mut checksum = [0u8; 2];
+        self.stream.read_exact(&mut checksum).unwrap();
+        String::from_utf8(data).unwrap()
+    }
+}
+
+fn connect() -> (GdbStub, Client) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = thread::spawn(move || TcpStream::connect(addr).unwrap());
+    let stub = GdbStub::accept(&listener).unwrap();
+    let stream = client.join().unwrap();
+    (stub, Client { stream })
+}
+
+#[test]
+fn test_read_registers() {
+    let (mut stub, mut client) = connect();
+    let mut processor = Processor::new();
+    processor.set_register(0x3, 0x10);
+    processor.set_register(0xF, 0x01);
+    processor.set_i(0x345);
+    processor.set_delay_timer(0x20);
+
+    let client = thread::spawn(move || {
+        let regs = client.send("g");
+        client.send_no_reply("c");
+        regs
+    });
+    assert!(stub.should_step(&mut processor).unwrap());
+
+    let regs = client.join().unwrap();
+    assert_eq!(regs.len(), 2 * (16 + 2 + 2 + 3));
+    assert_eq!(&regs[6..8], "10");
+    assert_eq!(&regs[30..32], "01");
+    // I and PC are little endian
+    assert_eq!(&regs[32..36], "4503");
+    assert_eq!(&regs[36..40], "0002");
+    assert_eq!(&regs[42..44], "20");
+}
+
+#[test]
+fn test_write_register() {
+    let (mut stub, mut client) = connect();
+    let mut processor = Processor::new();
+
+    let client = thread::spawn(move || {
+        assert_eq!(client.send("P5=42"), "OK");
+        assert_eq!(client.send("P11=0003"), "OK");
+        assert_eq!(client.send("p5"), "42");
+        assert_eq!(client.send("P12=20"), "E02");
+        assert_eq!(client.send("P30=00"), "E01");
+        // PC and I have to stay inside RAM
+        assert_eq!(client.send("P11=ff0f"), "E02");
+        assert_eq!(client.send("P10=0010"), "E02");
+        assert_eq!(client.send("cffff"), "E02");
+        client.send_no_reply("c");
+    });
+    stub.should_step(&mut processor).unwrap();
+    client.join().unwrap();
+
+    assert_eq!(processor.register(5), 0x42);
+    assert_eq!(processor.pc(), 0x300);
+}
+
+#[test]
+fn test_memory() {
+    let (mut stub, mut client) = connect();
+    let mut processor = Processor::new();
+    processor.load(&[0x12, 0x34]);
+
+    let client = thread::spawn(move || {
+        assert_eq!(client.send("m200,2"), "1234");
+        assert_eq!(client.send("M300,3:abcdef"), "OK");
+        assert_eq!(client.send("m300,3"), "abcdef");
+        assert_eq!(client.send("mfff,2"), "E01");
+        assert_eq!(client.send("M300,2:ab"), "E01");
+        assert_eq!(client.send("m1,ffffffffffffffff"), "E01");
+        client.send_no_reply("c");
+    });
+    stub.should_step(&mut processor).unwrap();
+    client.join().unwrap();
+
+    assert_eq!(processor.ram()[0x300..0x303], [0xAB, 0xCD, 0xEF]);
+    // fontset is readable as memory too
+    assert_eq!(processor.ram()[0], 0xF0);
+}
+
+#[test]
+fn test_breakpoint() {
+    let (mut stub, mut client) = connect();
+    let mut processor = Processor::new();
+
+    let client = thread::spawn(move || {
+        assert_eq!(client.send("Z0,204,2"), "OK");
+        client.send_no_reply("c");
+        let stop = client.recv();
+        assert_eq!(client.send("z0,204,2"), "OK");
+        client.send_no_reply("s");
+        (client, stop)
+    });
+    assert!(stub.should_step(&mut processor).unwrap());
+
+    // no stop reply before reaching the breakpoint
+    processor.set_pc(0x202);
+    stub.stepped(&mut processor).unwrap();
+    assert!(stub.should_step(&mut processor).unwrap());
+    processor.set_pc(0x204);
+    stub.stepped(&mut processor).unwrap();
+    assert!(stub.should_step(&mut processor).unwrap());
+
+    let (mut client, stop) = client.join().unwrap();
+    assert_eq!(stop, "T05swbreak:;");
+
+    // single step reports back after one cycle
+    processor.set_pc(0x206);
+    stub.stepped(&mut processor).unwrap();
+    assert_eq!(client.recv(), "S05");
+}
+
+#[test]
+fn test_watchpoint() {
+    let (mut stub, mut client) = connect();
+    let mut processor = Processor::new();
+    let mut keypad = HeadlessKeypad::new();
+    // LD I, 0x300; LD B, V0
+    processor.load(&[0xA3, 0x00, 0xF0, 0x33]);
+
+    let client = thread::spawn(move || {
+        assert_eq!(client.send("Z2,301,1"), "OK");
+        assert_eq!(client.send("Z2,ffffffffffffffff,2"), "E01");
+        client.send_no_reply("c");
+        client.recv()
+    });
+    assert!(stub.should_step(&mut processor).unwrap());
+    for _ in 0..2 {
+        processor.emulate_cycle(&mut keypad);
+        stub.stepped(&mut processor).unwrap();
+    }
+    assert_eq!(client.join().unwrap(), "T05watch:301;");
+}
+
+#[test]
+fn test_monitor() {
+    let (mut stub, mut client) = connect();
+    let mut processor = Processor::new();
+    let hex = |s: &str| s.bytes().map(|b| format!("{:02x}", b)).collect::<String>();
+
+    let client = thread::spawn(move || {
+        let output = client.send(&format!("qRcmd,{}", hex("break 210 if v3 == 0x10")));
+        assert_eq!(client.send(&format!("qRcmd,{}", hex("clear"))), "OK");
+        let error = client.send(&format!("qRcmd,{}", hex("bogus")));
+        client.send_no_reply("c");
+        (output, error)
+    });
+    assert!(stub.should_step(&mut processor).unwrap());
+    let (output, error) = client.join().unwrap();
+    assert_eq!(output, hex("Breakpoint at 210\n"));
+    assert_eq!(error, hex("Unknown command bogus\n"));
+}
+
+#[test]
+fn test_interrupt_and_kill() {
+    let (mut stub, mut client) = connect();
+    let mut processor = Processor::new();
+
+    let client = thread::spawn(move || {
+        client.send_no_reply("c");
+        client
+    });
+    assert!(stub.should_step(&mut processor).unwrap());
+    let mut client = client.join().unwrap();
+
+    client.stream.write_all(&[0x03]).unwrap();
+    let client = thread::spawn(move || {
+        let stop = client.recv();
+        client.send_no_reply("k");
+        stop
+    });
+    assert!(!stub.should_step(&mut processor).unwrap());
+    assert_eq!(client.join().unwrap(), "S02");
+}
+
+#[test]
+fn test_bad_checksum() {
+    let (mut stub, mut client) = connect();
+    let mut processor = Processor::new();
+
+    let client = thread::spawn(move || {
+        client.stream.write_all(b"$g#00").unwrap();
+        let mut nack = [0u8; 1];
+        client.stream.read_exact(&mut nack).unwrap();
+        client.send_no_reply("D");
+        (nack[0], client.recv())
+    });
+    assert!(stub.should_step(&mut processor).unwrap());
+    assert_eq!(client.join().unwrap(), (b'-', "OK".to_string()));
+}
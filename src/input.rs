@@ -0,0 +1,38 @@
+// What the processor needs from a keypad. Keys are the CHIP-8 keys 0x0 - 0xF.
+pub trait Input {
+    fn is_key_down(&self, key: u8) -> bool;
+
+    // Used by Fx0A. Either blocks until a key is pressed or returns None, in which case the
+    // instruction is retried on the next cycle.
+    fn wait_key(&mut self) -> Option<u8>;
+}
+
+// Keypad for running without a window, keys are pressed and released by the caller.
+#[derive(Clone, Copy, Default)]
+pub struct HeadlessKeypad {
+    keys: [bool; 16],
+}
+
+impl HeadlessKeypad {
+    pub fn new() -> Self {
+        HeadlessKeypad::default()
+    }
+
+    pub fn set_key(&mut self, key: u8, down: bool) {
+        self.keys[key as usize & 0xF] = down;
+    }
+
+    pub fn release_all(&mut self) {
+        self.keys = [false; 16];
+    }
+}
+
+impl Input for HeadlessKeypad {
+    fn is_key_down(&self, key: u8) -> bool {
+        self.keys.get(key as usize).copied().unwrap_or(false)
+    }
+
+    fn wait_key(&mut self) -> Option<u8> {
+        self.keys.iter().position(|&down| down).map(|key| key as u8)
+    }
+}
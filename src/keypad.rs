@@ -1,27 +1,53 @@
+use std::collections::HashMap;
+
 use sdl2::event::Event;
 use sdl2::keyboard::Scancode;
 
+use crate::input::Input;
+
 pub struct Keypad {
     event_pump: sdl2::EventPump,
+    // extra keys on top of the default layout, e.g. arrow keys from the ROM database
+    bindings: Vec<(Scancode, u8)>,
+    // keys that were down when Fx0A last asked for one, one bit per key
+    held: u16,
 }
 
 impl Keypad {
     pub fn new(sdl_context: &sdl2::Sdl) -> Self {
         Keypad {
             event_pump: sdl_context.event_pump().unwrap(),
+            bindings: Vec::new(),
+            held: 0,
         }
     }
 
+    // Binds the ROM database key names (up, down, left, right, a, b) to the arrow keys,
+    // space and left shift.
+    pub fn set_bindings(&mut self, keys: &HashMap<String, u8>) {
+        self.bindings = keys
+            .iter()
+            .filter_map(|(name, &key)| {
+                let scancode = match name.as_str() {
+                    "up" => Scancode::Up,
+                    "down" => Scancode::Down,
+                    "left" => Scancode::Left,
+                    "right" => Scancode::Right,
+                    "a" => Scancode::Space,
+                    "b" => Scancode::LShift,
+                    _ => return None,
+                };
+                Some((scancode, key))
+            })
+            .collect();
+    }
+
     pub fn is_pressed(&self, keycode: Scancode) -> bool {
         self.event_pump
             .keyboard_state()
             .is_scancode_pressed(keycode)
     }
 
-    pub fn wait_key_press(&mut self) -> Event {
-        self.event_pump.wait_event()
-    }
-
     pub fn wait_key_press_until(&mut self, timeout: u32) -> Option<Event> {
         self.event_pump.wait_event_timeout(timeout)
     }
@@ -70,3 +96,24 @@ impl Keypad {
         }
     }
 }
+
+impl Input for Keypad {
+    fn is_key_down(&self, key: u8) -> bool {
+        Keypad::unmap_key(key).is_some_and(|scancode| self.is_pressed(scancode))
+            || self
+                .bindings
+                .iter()
+                .any(|&(scancode, bound)| bound == key && self.is_pressed(scancode))
+    }
+
+    // Doesn't block so that the frame loop keeps handling events and ticking the timers while
+    // Fx0A waits. A key counts once it goes down, holding it doesn't repeat it.
+    fn wait_key(&mut self) -> Option<u8> {
+        let down = (0..16u8)
+            .filter(|&key| self.is_key_down(key))
+            .fold(0u16, |keys, key| keys | 1 << key);
+        let pressed = down & !self.held;
+        self.held = down;
+        (pressed != 0).then(|| pressed.trailing_zeros() as u8)
+    }
+}
@@ -0,0 +1,345 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::bitmap::Bitmap;
+use crate::loader::{extension_quirks, file_path, read_rom};
+use crate::overlay::Overlay;
+use crate::pack::{RomPack, ROM_EXTENSIONS};
+use crate::processor::FONTSET;
+use crate::romdb::{RomDatabase, RomInfo};
+use crate::scheduler::Scheduler;
+use crate::{HeadlessKeypad, Processor, CHIP8_SCREEN_HEIGHT, CHIP8_SCREEN_WIDTH};
+
+// Start screen for when no ROM is given: recently played ROMs followed by the ones in a
+// directory (including the contents of zip archives), with the database entry and a running
+// preview of the selected one. Text uses the CHIP-8 font for 0-9 and A-F and the overlay
+// font, padded to the same width, for everything else.
+
+pub const WIDTH: usize = 320;
+pub const HEIGHT: usize = 160;
+
+const CELL_WIDTH: usize = 5;
+const LINE_HEIGHT: usize = 7;
+const LIST_CHARS: usize = 36;
+// the last line is the help text
+const LIST_ROWS: usize = HEIGHT / LINE_HEIGHT - 1;
+const PREVIEW_SCALE: usize = 2;
+const PREVIEW_X: usize = WIDTH - CHIP8_SCREEN_WIDTH * PREVIEW_SCALE - 4;
+const PREVIEW_Y: usize = 3;
+const INFO_CHARS: usize = CHIP8_SCREEN_WIDTH * PREVIEW_SCALE / CELL_WIDTH;
+// speed of previews without a database entry
+const PREVIEW_IPS: u32 = 1000;
+const MAX_RECENT: usize = 10;
+
+pub struct Entry {
+    // anything `read_rom` accepts
+    pub path: String,
+    pub recent: bool,
+    pub info: Option<RomInfo>,
+}
+
+impl Entry {
+    // The database title, or the file name.
+    pub fn name(&self) -> String {
+        match &self.info {
+            Some(info) if !info.title.is_empty() => info.title.clone(),
+            _ => {
+                let name = self.path.rsplit(['/', '#']).next().unwrap_or(&self.path);
+                name.to_string()
+            }
+        }
+    }
+}
+
+pub struct Launcher {
+    entries: Vec<Entry>,
+    selected: usize,
+    // first visible entry
+    scroll: usize,
+    preview: Option<(Processor, usize)>,
+    keypad: HeadlessKeypad,
+    // why the selected ROM can't be previewed
+    error: Option<String>,
+}
+
+impl Launcher {
+    pub fn new(entries: Vec<Entry>) -> Self {
+        let mut launcher = Launcher {
+            entries,
+            selected: 0,
+            scroll: 0,
+            preview: None,
+            keypad: HeadlessKeypad::new(),
+            error: None,
+        };
+        launcher.start_preview();
+        launcher
+    }
+
+    // Recent files that still exist, then the ROMs in `dir` sorted by path.
+    pub fn scan(dir: &Path, recent: &[String], db: Option<&RomDatabase>) -> Self {
+        let mut paths: Vec<(String, bool)> = recent
+            .iter()
+            .filter(|path| Path::new(file_path(path)).exists())
+            .map(|path| (path.clone(), true))
+            .collect();
+
+        let mut files: Vec<PathBuf> = fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| Some(entry.ok()?.path()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        files.sort();
+        for file in files {
+            let name = file.display().to_string();
+            let extension = file
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+                .unwrap_or_default();
+            match extension.as_str() {
+                "zip" => {
+                    if let Ok(pack) = RomPack::open(&file) {
+                        for rom in pack.roms() {
+                            paths.push((format!("{}#{}", name, rom), false));
+                        }
+                    }
+                }
+                // only cartridges, not every image
+                "gif" if read_rom(&name).is_ok() => paths.push((name, false)),
+                _ if ROM_EXTENSIONS.contains(&extension.as_str()) => paths.push((name, false)),
+                _ => {}
+            }
+        }
+
+        let mut entries: Vec<Entry> = Vec::new();
+        for (path, recent) in paths {
+            if entries.iter().any(|entry| entry.path == path) {
+                continue;
+            }
+            let info = read_rom(&path)
+                .ok()
+                .and_then(|(rom, info)| info.or_else(|| db?.lookup(&rom)));
+            entries.push(Entry { path, recent, info });
+        }
+        Launcher::new(entries)
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn selected(&self) -> Option<&Entry> {
+        self.entries.get(self.selected)
+    }
+
+    // Moves the selection up (negative) or down and restarts the preview.
+    pub fn move_selection(&mut self, delta: isize) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let last = self.entries.len() - 1;
+        let selected = self.selected.saturating_add_signed(delta).min(last);
+        if selected == self.selected {
+            return;
+        }
+        self.selected = selected;
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + LIST_ROWS {
+            self.scroll = self.selected + 1 - LIST_ROWS;
+        }
+        self.start_preview();
+    }
+
+    pub fn page_size(&self) -> isize {
+        LIST_ROWS as isize
+    }
+
+    fn start_preview(&mut self) {
+        self.preview = None;
+        self.error = None;
+        let Some(entry) = self.entries.get(self.selected) else {
+            return;
+        };
+        match read_rom(&entry.path) {
+            Ok((rom, _)) => {
+                let mut processor = Processor::new();
+                let info = entry.info.as_ref();
+                processor.set_quirks(
+                    info.map(|info| info.quirks)
+                        .or_else(|| extension_quirks(&entry.path))
+                        .unwrap_or_default(),
+                );
+                processor.load(&rom);
+                let ips = info.and_then(|info| info.ips).unwrap_or(PREVIEW_IPS);
+                self.preview = Some((processor, Scheduler::cycles_per_frame(ips)));
+            }
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    // Runs one 60 Hz frame of the preview, without any keys pressed.
+    pub fn run_preview_frame(&mut self) {
+        if let Some((processor, cycles)) = self.preview.as_mut() {
+            processor.run_frame(&mut self.keypad, *cycles);
+        }
+    }
+
+    pub fn preview(&self) -> Option<&Processor> {
+        self.preview.as_ref().map(|(processor, _)| processor)
+    }
+
+    pub fn render(&self) -> Bitmap {
+        let mut screen = Bitmap::new(WIDTH, HEIGHT);
+
+        if self.entries.is_empty() {
+            Launcher::draw_text(&mut screen, 1, 1, "NO ROMS FOUND", true, LIST_CHARS);
+        }
+        let visible = self.entries.iter().enumerate().skip(self.scroll);
+        for (row, (index, entry)) in visible.take(LIST_ROWS).enumerate() {
+            let y = row * LINE_HEIGHT;
+            let selected = index == self.selected;
+            if selected {
+                Launcher::fill(&mut screen, 0, y, LIST_CHARS * CELL_WIDTH + 1, LINE_HEIGHT);
+            }
+            let marker = if entry.recent { "+" } else { " " };
+            let text = format!("{}{}", marker, entry.name());
+            Launcher::draw_text(&mut screen, 1, y + 1, &text, !selected, LIST_CHARS);
+        }
+
+        // preview with a frame around it
+        let (width, height) = (
+            CHIP8_SCREEN_WIDTH * PREVIEW_SCALE,
+            CHIP8_SCREEN_HEIGHT * PREVIEW_SCALE,
+        );
+        Launcher::fill(
+            &mut screen,
+            PREVIEW_X - 1,
+            PREVIEW_Y - 1,
+            width + 2,
+            height + 2,
+        );
+        for y in 0..height {
+            for x in 0..width {
+                let on = self.preview().is_some_and(|processor| {
+                    processor.vram().pixel(x / PREVIEW_SCALE, y / PREVIEW_SCALE)
+                });
+                screen.set(PREVIEW_X + x, PREVIEW_Y + y, on);
+            }
+        }
+
+        let mut lines = Vec::new();
+        if let Some(entry) = self.selected() {
+            lines.push(entry.name());
+            if let Some(info) = &entry.info {
+                if !info.authors.is_empty() {
+                    lines.push(format!("BY {}", info.authors.join(", ")));
+                }
+                if let Some(platform) = &info.platform {
+                    lines.push(platform.clone());
+                }
+                if let Some(ips) = info.ips {
+                    lines.push(format!("{} IPS", ips));
+                }
+            }
+            if let Some(error) = &self.error {
+                lines.push(error.clone());
+            }
+        }
+        let top = PREVIEW_Y + height + 4;
+        for (n, line) in lines.iter().enumerate() {
+            let y = top + n * LINE_HEIGHT;
+            Launcher::draw_text(&mut screen, PREVIEW_X, y, line, true, INFO_CHARS);
+        }
+
+        let help = "ENTER/A: START  UP/DOWN: SELECT  ESC/B: QUIT";
+        let y = HEIGHT - LINE_HEIGHT + 1;
+        Launcher::draw_text(&mut screen, 1, y, help, true, WIDTH / CELL_WIDTH);
+        screen
+    }
+
+    // Rows of a glyph, 4 pixels wide with the leftmost one in bit 3.
+    pub fn glyph(c: char) -> [u8; 5] {
+        match c.to_digit(16) {
+            Some(digit) => {
+                let start = digit as usize * 5;
+                let mut rows = [0; 5];
+                for (row, byte) in rows.iter_mut().zip(&FONTSET[start..start + 5]) {
+                    *row = byte >> 4;
+                }
+                rows
+            }
+            None => Overlay::glyph(c).map(|row| row << 1),
+        }
+    }
+
+    fn draw_text(screen: &mut Bitmap, x: usize, y: usize, text: &str, on: bool, max: usize) {
+        for (i, c) in text.chars().take(max).enumerate() {
+            for (dy, row) in Launcher::glyph(c).iter().enumerate() {
+                for dx in 0..4 {
+                    if row & (0b1000 >> dx) != 0 {
+                        screen.set(x + i * CELL_WIDTH + dx, y + dy, on);
+                    }
+                }
+            }
+        }
+    }
+
+    fn fill(screen: &mut Bitmap, x: usize, y: usize, width: usize, height: usize) {
+        for y in y..y + height {
+            for x in x..x + width {
+                screen.set(x, y, true);
+            }
+        }
+    }
+}
+
+// The last ROMs that were started, most recent first, one path per line.
+pub struct RecentFiles {
+    path: PathBuf,
+    files: Vec<String>,
+}
+
+impl RecentFiles {
+    // A missing file is the same as an empty one.
+    pub fn load(path: &Path) -> Self {
+        let files = fs::read_to_string(path)
+            .map(|data| {
+                data.lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(|line| line.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        RecentFiles {
+            path: path.to_path_buf(),
+            files,
+        }
+    }
+
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    pub fn add(&mut self, rom: &str) {
+        self.files.retain(|file| file != rom);
+        self.files.insert(0, rom.to_string());
+        self.files.truncate(MAX_RECENT);
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut data = self.files.join("\n");
+        data.push('\n');
+        fs::write(&self.path, data)
+    }
+}
+
+#[cfg(test)]
+#[path = "./launcher_test.rs"]
+mod launcher_test;
@@ -0,0 +1,96 @@
+use super::*;
+use std::io::Write;
+
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+// draws the 0 digit: LD I, 0; DRW V0, V0, 5; JP 204
+const DRAW: [u8; 6] = [0xA0, 0x00, 0xD0, 0x05, 0x12, 0x04];
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("chip8-launcher-{}-{}", std::process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_scan() {
+    let dir = temp_dir("scan");
+    fs::write(dir.join("b.ch8"), DRAW).unwrap();
+    fs::write(dir.join("a.sc8"), [0x12, 0x00]).unwrap();
+    fs::write(dir.join("notes.txt"), "not a ROM").unwrap();
+    fs::write(dir.join("screenshot.gif"), "not a cartridge").unwrap();
+    let mut zip = ZipWriter::new(fs::File::create(dir.join("pack.zip")).unwrap());
+    zip.start_file("games/c.xo8", FileOptions::default())
+        .unwrap();
+    zip.write_all(&DRAW).unwrap();
+    zip.finish().unwrap();
+
+    let recent = dir.join("b.ch8").display().to_string();
+    let missing = dir.join("gone.ch8").display().to_string();
+    let mut launcher = Launcher::scan(&dir, &[recent.clone(), missing], None);
+    let names: Vec<(String, bool)> = launcher
+        .entries()
+        .iter()
+        .map(|entry| (entry.name(), entry.recent))
+        .collect();
+    let expected = [("b.ch8", true), ("a.sc8", false), ("c.xo8", false)];
+    assert_eq!(
+        names,
+        expected.map(|(name, recent)| (name.to_string(), recent))
+    );
+    assert_eq!(launcher.selected().unwrap().path, recent);
+
+    // the preview runs the selected ROM
+    launcher.run_preview_frame();
+    assert!(launcher.preview().unwrap().vram().pixel(0, 0));
+
+    launcher.move_selection(10);
+    assert!(launcher
+        .selected()
+        .unwrap()
+        .path
+        .ends_with("pack.zip#games/c.xo8"));
+    assert_eq!(
+        launcher.preview().unwrap().quirks(),
+        crate::Quirks::xochip()
+    );
+    launcher.move_selection(-1);
+    assert!(launcher.selected().unwrap().path.ends_with("a.sc8"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_render() {
+    assert_eq!(Launcher::glyph('0'), [0xF, 0x9, 0x9, 0x9, 0xF]);
+    assert_eq!(
+        Launcher::glyph('K'),
+        [0b1010, 0b1010, 0b1100, 0b1010, 0b1010]
+    );
+
+    let empty = Launcher::new(Vec::new());
+    assert!(empty.selected().is_none());
+    let screen = empty.render();
+    assert_eq!((screen.width, screen.height), (WIDTH, HEIGHT));
+    // the "N" of "NO ROMS FOUND"
+    assert!(screen.get(1, 1) && screen.get(1, 5) && !screen.get(3, 1));
+}
+
+#[test]
+fn test_recent_files() {
+    let dir = temp_dir("recent");
+    let path = dir.join("chip8").join("recent");
+    let mut recent = RecentFiles::load(&path);
+    assert!(recent.files().is_empty());
+    for n in 0..12 {
+        recent.add(&format!("{}.ch8", n));
+    }
+    recent.add("5.ch8");
+    recent.save().unwrap();
+
+    let recent = RecentFiles::load(&path);
+    assert_eq!(recent.files().len(), MAX_RECENT);
+    assert_eq!(recent.files()[..3], ["5.ch8", "11.ch8", "10.ch8"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,54 @@
+#[cfg(feature = "sdl")]
+pub use display::Display;
+pub use input::{HeadlessKeypad, Input};
+#[cfg(feature = "sdl")]
+pub use keypad::Keypad;
+pub use processor::Processor;
+pub use quirks::Quirks;
+
+pub mod analyzer;
+pub mod batch;
+pub mod bitmap;
+pub mod blocks;
+pub mod cartridge;
+pub mod condition;
+pub mod coverage;
+pub mod debugger;
+pub mod disassembler;
+#[cfg(feature = "sdl")]
+pub mod display;
+pub mod env;
+pub mod flags;
+pub mod framebuffer;
+pub mod gdb;
+pub mod input;
+#[cfg(feature = "sdl")]
+pub mod keypad;
+pub mod launcher;
+pub mod loader;
+pub mod octo;
+pub mod overlay;
+pub mod pack;
+pub mod processor;
+pub mod profiler;
+pub mod quirks;
+pub mod record;
+pub mod rewind;
+pub mod romdb;
+pub mod scheduler;
+pub mod smc;
+pub mod symbols;
+pub mod testrom;
+pub mod trace;
+pub mod watch;
+
+// TODO: try using static
+pub const OPCODE_SIZE: usize = 2;
+pub const CHIP8_RAM: usize = 4096;
+// programs are loaded at 0x200
+pub const CHIP8_ROM_SIZE: usize = CHIP8_RAM - 0x200;
+// nesting depth of subroutine calls
+pub const STACK_SIZE: usize = 16;
+pub const SCALE_FACTOR: u32 = 10;
+pub const CHIP8_SCREEN_WIDTH: usize = 64;
+pub const CHIP8_SCREEN_HEIGHT: usize = 32;
@@ -0,0 +1,57 @@
+use std::fs::{self, File};
+use std::path::Path;
+
+use crate::cartridge::Cartridge;
+use crate::pack::RomPack;
+use crate::romdb::RomInfo;
+use crate::Quirks;
+
+// Reads plain ROM files, ROMs in a zip archive (`pack.zip#game.ch8`) and Octo cartridges,
+// which bring their own settings.
+pub fn read_rom(path: &str) -> Result<(Vec<u8>, Option<RomInfo>), String> {
+    if let Some((archive, entry)) = RomPack::<File>::split(path) {
+        let mut pack = RomPack::open(Path::new(archive))?;
+        let roms = pack.roms();
+        let entry = match (entry, &roms[..]) {
+            (Some(entry), _) => entry,
+            // nothing to choose from
+            (None, [only]) => only,
+            (None, _) => {
+                let names: Vec<String> = roms.iter().map(|rom| format!("  {}", rom)).collect();
+                return Err(format!(
+                    "{} contains {} ROMs, pick one with {}#NAME:\n{}",
+                    archive,
+                    roms.len(),
+                    archive,
+                    names.join("\n")
+                ));
+            }
+        };
+        return Ok((pack.read(entry)?, None));
+    }
+    if Cartridge::is_cartridge(Path::new(path)) {
+        let cartridge = Cartridge::load(Path::new(path))?;
+        return Ok((cartridge.rom, Some(cartridge.info)));
+    }
+    let rom = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    Ok((rom, None))
+}
+
+// The file on disk, the archive for ROMs in a zip archive.
+pub fn file_path(rom: &str) -> &str {
+    RomPack::<File>::split(rom).map_or(rom, |(archive, _)| archive)
+}
+
+// The ROM's name within an archive, or the path of plain files.
+pub fn rom_name(rom: &str) -> &str {
+    match RomPack::<File>::split(rom) {
+        Some((_, Some(entry))) => entry,
+        _ => rom,
+    }
+}
+
+// Quirks for the platform the file extension stands for, see `Quirks::for_extension`.
+pub fn extension_quirks(rom: &str) -> Option<Quirks> {
+    let extension = Path::new(rom_name(rom)).extension()?;
+    Quirks::for_extension(extension.to_str()?)
+}
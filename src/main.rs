@@ -1,58 +1,648 @@
+use sdl2::controller::{Button, GameController};
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use std::fs::File;
-use std::io::Read;
+use sdl2::keyboard::{Keycode, Scancode};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
 
-use display::Display;
-use keypad::Keypad;
-use processor::Processor;
+use chip8::coverage::{Coverage, SourceMap};
+use chip8::debugger::Stop;
+use chip8::flags::FileFlagStore;
+use chip8::gdb::GdbStub;
+use chip8::launcher::{Launcher, RecentFiles};
+use chip8::loader::{extension_quirks, file_path, read_rom};
+use chip8::pack::RomPack;
+use chip8::profiler::Profiler;
+use chip8::record::Recorder;
+use chip8::rewind::Rewind;
+use chip8::romdb::RomDatabase;
+use chip8::scheduler::{Scheduler, FRAMES_PER_SECOND};
+use chip8::smc::SmcTracker;
+use chip8::symbols::Symbols;
+use chip8::trace::Tracer;
+use chip8::watch::FileWatcher;
+use chip8::{Display, Keypad, Processor};
+use options::Options;
 
-mod display;
-mod keypad;
-mod processor;
+mod options;
 
-// TODO: try using static
-const OPCODE_SIZE: usize = 2;
-const CHIP8_RAM: usize = 4096;
-const SCALE_FACTOR: u32 = 10;
-const CHIP8_SCREEN_WIDTH: usize = 64;
-const CHIP8_SCREEN_HEIGHT: usize = 32;
+const STATE_SLOTS: usize = 10;
+// a snapshot every 2 frames, enough for 15 seconds of rewinding
+const REWIND_INTERVAL: usize = 2;
+const REWIND_CAPACITY: usize = 450;
+// used when neither --ips nor the ROM database say otherwise
+const DEFAULT_IPS: u32 = 1000;
+// pixel size in recordings
+const RECORD_SCALE: usize = 4;
 
 fn main() {
+    let options = match Options::parse(std::env::args()) {
+        Ok(options) => options,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+
+    if options.list {
+        if let Err(e) = list_roms(&options) {
+            println!("{}", e);
+        }
+        return;
+    }
+
+    // a ROM from the command line is read before opening the window so that errors show up
+    // right away
+    let loaded = match &options.rom {
+        Some(path) => match read_rom(path) {
+            Ok(loaded) => Some((path.clone(), loaded)),
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        },
+        None => None,
+    };
+
     let sdl_context = sdl2::init().unwrap();
     let mut display = Display::new(&sdl_context);
     let mut keypad = Keypad::new(&sdl_context);
+    let mut recent = RecentFiles::load(&Options::data_dir().join("recent"));
+    let (rom_path, (mut rom, cartridge)) = match loaded {
+        Some(loaded) => loaded,
+        None => {
+            let Some(path) = launch(&sdl_context, &mut display, &mut keypad, &options, &recent)
+            else {
+                return;
+            };
+            match read_rom(&path) {
+                Ok(loaded) => (path, loaded),
+                Err(e) => {
+                    println!("{}", e);
+                    return;
+                }
+            }
+        }
+    };
+    recent.add(&rom_path);
+    if let Err(e) = recent.save() {
+        println!("Failed to save the recent files: {}", e);
+    }
+
     let mut processor = Processor::new();
+    processor.set_block_cache(true);
+    let flags = FileFlagStore::new(Options::data_dir().join("flags"));
+    processor.set_flag_store(Some(Box::new(flags)));
+    processor.load(&rom);
+
+    // settings from the command line win over the ones in the cartridge or the database
+    let info = cartridge.or_else(|| load_romdb(&options).and_then(|db| db.lookup(&rom)));
+    if let Some(info) = &info {
+        let authors = match info.authors.is_empty() {
+            true => String::new(),
+            false => format!(" by {}", info.authors.join(", ")),
+        };
+        println!(
+            "{}{} ({})",
+            info.title,
+            authors,
+            info.platform.as_deref().unwrap_or("unknown platform")
+        );
+        keypad.set_bindings(&info.keys);
+        if let Some((background, foreground)) = info.colors {
+            display.set_palette(background, foreground);
+        }
+    }
+    processor.set_quirks(
+        options
+            .quirks
+            .or(info.as_ref().map(|info| info.quirks))
+            .or_else(|| extension_quirks(&rom_path))
+            .unwrap_or_default(),
+    );
+    let ips = options
+        .ips
+        .or(info.as_ref().and_then(|info| info.ips))
+        .unwrap_or(DEFAULT_IPS);
 
-    let mut rom =
-        File::open("/home/jedi/.local/src/chip8/rust-chip8/data/PONG").expect("File not found!");
-    let mut buf = [0u8; 3584]; // 0x1000 - 0x200 = 3584
+    let symbols = match &options.symbols {
+        Some(path) => Symbols::load(Path::new(path)).expect("Failed to load symbols"),
+        None => Symbols::new(),
+    };
 
-    if rom.read(&mut buf).is_ok() {
-        processor.load(&buf);
-    } else {
-        println!("Failed to read file");
+    if let Some(path) = &options.trace {
+        let tracer = Tracer::open(path)
+            .expect("Failed to open trace file")
+            .with_ranges(options.trace_ranges.clone())
+            .with_classes(options.trace_classes.clone())
+            .with_symbols(symbols.clone());
+        processor.set_tracer(Some(tracer));
     }
 
-    loop {
-        let (vram, display_flag, clear_flag) = processor.emulate_cycle(&mut keypad);
-        if display_flag {
-            display.render(vram);
-        } else if clear_flag {
-            display.clear();
+    if options.smc {
+        processor.set_smc_tracker(Some(SmcTracker::new()));
+    }
+    if options.profile.is_some() {
+        processor.set_profiler(Some(Profiler::new()));
+    }
+    // read the source map up front so that a typo doesn't cost a whole play session
+    let source_map = options.source_map.as_ref().map(|path| {
+        let data = fs::read_to_string(path).expect("Failed to read source map");
+        SourceMap::parse(&data, &rom).expect("Invalid source map")
+    });
+    if options.coverage.is_some() {
+        processor.set_coverage(Some(Coverage::new()));
+    }
+
+    let mut gdb = options.gdb_port.map(|port| {
+        let mut stub = GdbStub::listen(port).expect("Failed to start GDB stub");
+        stub.set_symbols(symbols.clone());
+        stub
+    });
+
+    let mut rewind = Rewind::new(REWIND_INTERVAL, REWIND_CAPACITY);
+    let mut watcher = options
+        .watch
+        .then(|| FileWatcher::new(file_path(&rom_path)));
+
+    let palette = info
+        .as_ref()
+        .and_then(|info| info.colors)
+        .unwrap_or((0x000000, 0xFFFFFF));
+    // F10 records to the --record file, or next to the ROM without one
+    let record_path = options.record.clone().unwrap_or_else(|| {
+        let path = Path::new(&local_path(&rom_path)).with_extension("rec.gif");
+        path.display().to_string()
+    });
+    let mut recorder = options.record.as_ref().map(|path| {
+        Recorder::create(path, palette, RECORD_SCALE).expect("Failed to start recording")
+    });
+    let mut slot = 0;
+
+    // the scheduler decides how many frames are due, each frame runs a fixed number of
+    // instructions and then ticks the timers once
+    let mut scheduler = Scheduler::new();
+    if let Some(speed) = options.fast_forward {
+        scheduler.set_fast_forward_speed(speed);
+    }
+    let cycles_per_frame = Scheduler::cycles_per_frame(ips);
+    let frame_time = Duration::from_secs_f64(1.0 / FRAMES_PER_SECOND);
+    let mut last = Instant::now();
+    // screen updates and cycles since the overlay status was last updated
+    let mut stats = (Instant::now(), 0, 0);
+
+    'main: loop {
+        let rewinding = keypad.is_pressed(Scancode::Backspace);
+        scheduler.set_fast_forward(keypad.is_pressed(Scancode::Tab));
+        let now = Instant::now();
+        let frames = scheduler.frames_due(now.duration_since(last));
+        last = now;
+        if rewinding {
+            // one snapshot per frame that is due, so the game plays backwards in time
+            let mut stepped = false;
+            for _ in 0..frames {
+                match rewind.step_back(&mut processor) {
+                    Ok(true) => stepped = true,
+                    Ok(false) => break,
+                    Err(e) => {
+                        notify(&mut display, format!("Failed to rewind: {}", e));
+                        rewind.clear();
+                        break;
+                    }
+                }
+                if now.elapsed() >= frame_time {
+                    break;
+                }
+            }
+            if stepped {
+                display.render(processor.vram());
+                stats.1 += 1;
+            }
+        } else {
+            for _ in 0..frames {
+                let changed = match gdb.as_mut() {
+                    Some(stub) => {
+                        match debug_frame(stub, &mut processor, &mut keypad, cycles_per_frame) {
+                            Ok(Some(changed)) => changed,
+                            Ok(None) => break 'main,
+                            Err(e) => {
+                                println!("GDB connection lost: {}", e);
+                                break 'main;
+                            }
+                        }
+                    }
+                    None => {
+                        let changed = processor.run_frame(&mut keypad, cycles_per_frame);
+                        // without a debugger attached faults are only reported
+                        if let Some(fault) = processor.take_watch_hit().and_then(describe_fault) {
+                            notify(&mut display, fault);
+                        }
+                        changed
+                    }
+                };
+                stats.2 += cycles_per_frame as u64;
+                rewind.capture(&processor);
+                if let Some(active) = recorder.as_mut() {
+                    if let Err(e) = active.frame(processor.vram(), processor.sound_timer() > 0) {
+                        notify(&mut display, format!("Recording failed: {}", e));
+                        recorder = None;
+                    }
+                }
+                if let Some(profiler) = processor.profiler_mut() {
+                    profiler.end_frame();
+                }
+                if changed {
+                    display.render(processor.vram());
+                    stats.1 += 1;
+                }
+                // fast forward runs as many frames as fit, but still handles input in time
+                if now.elapsed() >= frame_time {
+                    break;
+                }
+            }
+        }
+
+        if watcher
+            .as_mut()
+            .and_then(|watcher| watcher.poll(now))
+            .is_some()
+        {
+            match read_rom(&rom_path) {
+                Ok((data, _)) => {
+                    rom = data;
+                    processor.reload(&rom);
+                    rewind.clear();
+                    display.render(processor.vram());
+                    notify(&mut display, "Reloaded ROM".to_string());
+                }
+                Err(e) => notify(&mut display, format!("Failed to reload: {}", e)),
+            }
         }
-        // processor.pretty_print();
 
-        let event = keypad.wait_key_press_until(1);
+        let elapsed = stats.0.elapsed();
+        if elapsed.as_secs() >= 1 {
+            let per_second = |count: u64| (count as f64 / elapsed.as_secs_f64()).round();
+            let mut state = match scheduler.label() {
+                _ if rewinding => "  REWIND".to_string(),
+                label if label.is_empty() => label,
+                label => format!("  {}", label),
+            };
+            if recorder.is_some() {
+                state.push_str("  REC");
+            }
+            display.overlay_mut().set_status(format!(
+                "{} FPS  {} IPS{}",
+                per_second(stats.1),
+                per_second(stats.2),
+                state
+            ));
+            stats = (Instant::now(), 0, 0);
+            if display.overlay().is_visible() {
+                display.refresh();
+            }
+        }
+        if display.overlay_mut().expire(Instant::now()) {
+            display.refresh();
+        }
+
+        // input is handled while waiting for the next frame
+        let wait = scheduler.next_frame_in().unwrap_or_default();
+        let event = keypad.wait_key_press_until((wait.as_secs_f64() * 1000.0).ceil() as u32);
         if let Some(event) = event {
             match event {
                 Event::Quit { .. }
                 | Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
-                } => break,
+                } => break 'main,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    ..
+                } => {
+                    processor.reset();
+                    rewind.clear();
+                    display.render(processor.vram());
+                    notify(&mut display, "Reset".to_string());
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => {
+                    let path = state_path(&rom_path, slot);
+                    match fs::write(&path, processor.save_state()) {
+                        Ok(()) => notify(&mut display, format!("Saved state to slot {}", slot)),
+                        Err(e) => notify(&mut display, format!("Failed to save {}: {}", path, e)),
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    ..
+                } => {
+                    let path = state_path(&rom_path, slot);
+                    let loaded = fs::read(&path)
+                        .map_err(|e| e.to_string())
+                        .and_then(|state| processor.load_state(&state));
+                    match loaded {
+                        Ok(()) => {
+                            rewind.clear();
+                            display.render(processor.vram());
+                            notify(&mut display, format!("Loaded state from slot {}", slot));
+                        }
+                        Err(e) => {
+                            notify(&mut display, format!("Failed to load slot {}: {}", slot, e))
+                        }
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    ..
+                } => {
+                    slot = (slot + STATE_SLOTS - 1) % STATE_SLOTS;
+                    notify(&mut display, format!("Selected slot {}", slot));
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F7),
+                    ..
+                } => {
+                    slot = (slot + 1) % STATE_SLOTS;
+                    notify(&mut display, format!("Selected slot {}", slot));
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    ..
+                } => {
+                    scheduler.toggle_pause();
+                    let state = if scheduler.is_paused() {
+                        "Paused"
+                    } else {
+                        "Resumed"
+                    };
+                    notify(&mut display, state.to_string());
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::N),
+                    ..
+                } => scheduler.advance(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::M),
+                    ..
+                } => {
+                    scheduler.toggle_slow_motion();
+                    let state = if scheduler.is_slow_motion() {
+                        "on"
+                    } else {
+                        "off"
+                    };
+                    notify(&mut display, format!("Slow motion {}", state));
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F10),
+                    ..
+                } => match recorder.take() {
+                    Some(active) => stop_recording(&mut display, active),
+                    None => match Recorder::create(&record_path, palette, RECORD_SCALE) {
+                        Ok(active) => {
+                            notify(&mut display, format!("Recording to {}", record_path));
+                            recorder = Some(active);
+                        }
+                        Err(e) => notify(
+                            &mut display,
+                            format!("Failed to record {}: {}", record_path, e),
+                        ),
+                    },
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::F1),
+                    ..
+                } => {
+                    display.overlay_mut().toggle();
+                    display.refresh();
+                }
                 _ => {}
             }
         }
     }
+
+    if let Some(active) = recorder.take() {
+        stop_recording(&mut display, active);
+    }
+    if let Some(smc) = processor.smc_tracker() {
+        smc.write_report(&mut std::io::stdout().lock())
+            .expect("Failed to write report");
+    }
+    if let (Some(profiler), Some(path)) = (processor.profiler(), &options.profile) {
+        profiler
+            .write_report(&mut std::io::stdout().lock())
+            .expect("Failed to write report");
+        let written = File::create(path).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            profiler.write_json(&mut out)?;
+            out.flush()
+        });
+        if let Err(e) = written {
+            println!("Failed to write {}: {}", path, e);
+        }
+    }
+    if let (Some(coverage), Some(path)) = (processor.coverage(), &options.coverage) {
+        let written = File::create(path).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            match &source_map {
+                Some(map) => coverage.write_lcov(map, &mut out)?,
+                None => coverage.write_annotated(&rom, &mut out)?,
+            }
+            out.flush()
+        });
+        if let Err(e) = written {
+            println!("Failed to write {}: {}", path, e);
+        }
+    }
+}
+
+// Shown on the overlay and printed, for anything the player should know about.
+fn notify(display: &mut Display, message: String) {
+    println!("{}", message);
+    display.overlay_mut().notify(message);
+    display.refresh();
+}
+
+// Start screen when there's no ROM on the command line, returns the chosen ROM or None to
+// quit.
+fn launch(
+    sdl_context: &sdl2::Sdl,
+    display: &mut Display,
+    keypad: &mut Keypad,
+    options: &Options,
+    recent: &RecentFiles,
+) -> Option<String> {
+    let db = load_romdb(options);
+    let mut launcher = Launcher::scan(&options.roms, recent.files(), db.as_ref());
+
+    // gamepads stay open for as long as they are in the list
+    let controllers = sdl_context.game_controller().ok();
+    let mut pads: Vec<GameController> = Vec::new();
+    if let Some(controllers) = &controllers {
+        for index in 0..controllers.num_joysticks().unwrap_or(0) {
+            if let Ok(pad) = controllers.open(index) {
+                pads.push(pad);
+            }
+        }
+    }
+
+    loop {
+        launcher.run_preview_frame();
+        display.render_bitmap(&launcher.render());
+        let Some(event) = keypad.wait_key_press_until(16) else {
+            continue;
+        };
+        let page = launcher.page_size();
+        match event {
+            Event::Quit { .. }
+            | Event::KeyDown {
+                keycode: Some(Keycode::Escape),
+                ..
+            }
+            | Event::ControllerButtonDown {
+                button: Button::B | Button::Back,
+                ..
+            } => return None,
+            Event::KeyDown {
+                keycode: Some(Keycode::Up),
+                ..
+            }
+            | Event::ControllerButtonDown {
+                button: Button::DPadUp,
+                ..
+            } => launcher.move_selection(-1),
+            Event::KeyDown {
+                keycode: Some(Keycode::Down),
+                ..
+            }
+            | Event::ControllerButtonDown {
+                button: Button::DPadDown,
+                ..
+            } => launcher.move_selection(1),
+            Event::KeyDown {
+                keycode: Some(Keycode::PageUp),
+                ..
+            }
+            | Event::ControllerButtonDown {
+                button: Button::LeftShoulder,
+                ..
+            } => launcher.move_selection(-page),
+            Event::KeyDown {
+                keycode: Some(Keycode::PageDown),
+                ..
+            }
+            | Event::ControllerButtonDown {
+                button: Button::RightShoulder,
+                ..
+            } => launcher.move_selection(page),
+            // ROMs that failed to load show the error instead of a preview
+            Event::KeyDown {
+                keycode: Some(Keycode::Return),
+                ..
+            }
+            | Event::ControllerButtonDown {
+                button: Button::A | Button::Start,
+                ..
+            } if launcher.preview().is_some() => {
+                return launcher.selected().map(|entry| entry.path.clone());
+            }
+            Event::ControllerDeviceAdded { which, .. } => {
+                if let Some(pad) = controllers.as_ref().and_then(|c| c.open(which).ok()) {
+                    pads.push(pad);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// The database from --romdb, or from the default location when there is one. Only a missing
+// default database goes without an error message.
+fn load_romdb(options: &Options) -> Option<RomDatabase> {
+    let path = options.romdb.clone().unwrap_or_else(Options::default_romdb);
+    match RomDatabase::load(&path) {
+        Ok(db) => Some(db),
+        Err(e) if options.romdb.is_none() && e.kind() == ErrorKind::NotFound => None,
+        Err(e) => {
+            println!("Failed to load the ROM database {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+// The ROMs in a zip archive with their titles from the ROM database.
+fn list_roms(options: &Options) -> Result<(), String> {
+    let rom = options.rom.as_deref().unwrap_or_default();
+    let (archive, _) = RomPack::<File>::split(rom).ok_or("--list expects a zip archive")?;
+    let mut pack = RomPack::open(Path::new(archive))?;
+    let db = load_romdb(options);
+    for name in pack.roms() {
+        let rom = pack.read(&name)?;
+        let title = match db.as_ref().and_then(|db| db.lookup(&rom)) {
+            Some(info) => info.title,
+            None => "-".to_string(),
+        };
+        println!("{:<40} {}", format!("{}#{}", archive, name), title);
+    }
+    Ok(())
+}
+
+// Base for files that belong to a ROM (save states, recordings), next to the ROM or archive.
+fn local_path(rom: &str) -> String {
+    match RomPack::<File>::split(rom) {
+        Some((archive, Some(entry))) => format!(
+            "{}.{}",
+            archive.trim_end_matches(".zip"),
+            entry.replace('/', "_")
+        ),
+        _ => rom.to_string(),
+    }
+}
+
+fn stop_recording(display: &mut Display, recorder: Recorder) {
+    let path = recorder.path().display().to_string();
+    let frames = recorder.frames();
+    match recorder.finish() {
+        Ok(()) => notify(display, format!("Saved {} ({} frames)", path, frames)),
+        Err(e) => notify(display, format!("Failed to write {}: {}", path, e)),
+    }
+}
+
+// A frame with the GDB stub deciding before every instruction whether it runs. Returns None
+// once the debugger killed the target.
+fn debug_frame(
+    stub: &mut GdbStub,
+    processor: &mut Processor,
+    keypad: &mut Keypad,
+    cycles: usize,
+) -> io::Result<Option<bool>> {
+    let mut changed = false;
+    for _ in 0..cycles {
+        if !stub.should_step(processor)? {
+            return Ok(None);
+        }
+        let (_, display_flag, clear_flag) = processor.step(keypad);
+        changed |= display_flag || clear_flag;
+        stub.stepped(processor)?;
+    }
+    processor.tick_timers();
+    Ok(Some(changed))
+}
+
+// Faults the processor reports instead of crashing.
+fn describe_fault(stop: Stop) -> Option<String> {
+    match stop {
+        Stop::StackUnderflow => Some("Return with an empty stack".to_string()),
+        Stop::StackOverflow => Some("Stack overflow".to_string()),
+        Stop::IndexOverflow => Some("I past the end of RAM".to_string()),
+        Stop::InvalidOpcode(opcode) => Some(format!("Invalid opcode {:04X}", opcode)),
+        _ => None,
+    }
+}
+
+fn state_path(rom: &str, slot: usize) -> String {
+    format!("{}.state{}", local_path(rom), slot)
 }
@@ -0,0 +1,1043 @@
+use std::collections::{HashMap, VecDeque};
+
+// Assembler for Octo, the language that Octo cartridges carry their program in.
+// https://github.com/JohnEarnest/Octo/blob/gh-pages/docs/Manual.md
+//
+// Covers the statements of the manual (CHIP-8, SUPER-CHIP and XO-CHIP), structured
+// `if`/`loop`, labels with forward references, `:const`, `:alias`, `:unpack`, `:next`, `:org`,
+// `:byte`, `:pointer`, `:call`, `:macro`, `:calc`, `:stringmode` and `:assert`. Debugger
+// directives (`:breakpoint`, `:monitor`) are skipped.
+//
+// Like Octo the program starts with a jump to `main` at 0x200, everything else follows at
+// 0x202. The output is the same as Octo's, so `<`, `>`, `<=` and `>=` compile to `8xy5`/`8xy7`
+// into VF and expect VF to hold the flag afterwards, which the processor here doesn't do (it
+// writes VF before the result).
+//
+// Calc expressions have no precedence and are evaluated right to left, `{ 2 * 3 + 1 }` is 8.
+
+const START: usize = 0x200;
+const MAX_ADDR: usize = 0xFFFF;
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    // "quoted", where `text` is the unescaped contents
+    string: bool,
+    line: usize,
+}
+
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+    calls: usize,
+}
+
+struct StringMode {
+    alphabet: Vec<char>,
+    body: Vec<Token>,
+}
+
+// How a label that wasn't defined yet is filled in once it is.
+#[derive(Clone, Copy)]
+enum Fixup {
+    // low 12 bits of the instruction at the address
+    Addr(usize),
+    // 16 bits at the address (`i := long`, `:pointer`)
+    Long(usize),
+    // `:unpack`, the nibble and the high 4 bits or the high byte of `long`, and the low byte
+    UnpackHi(usize, Option<u8>),
+    UnpackLo(usize),
+}
+
+enum Control {
+    // `if ... begin`, the jump over the block
+    If(usize),
+    // `else`, the jump over the else block
+    Else(usize),
+    // `loop`, the `while` jumps out of it
+    Loop(usize, Vec<usize>),
+}
+
+enum Value {
+    Known(i64),
+    Forward(String),
+}
+
+// Skips that run the next instruction only if the condition is false or true.
+struct Condition {
+    setup: Vec<u16>,
+    unless: u16,
+    when: u16,
+}
+
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    let mut assembler = Assembler::new(tokenize(source)?);
+    assembler
+        .run()
+        .map_err(|e| format!("Line {}: {}", assembler.line, e))?;
+    Ok(assembler.rom)
+}
+
+fn tokenize(source: &str) -> Result<VecDeque<Token>, String> {
+    let mut tokens = VecDeque::new();
+    let mut chars = source.chars().peekable();
+    let mut line = 1;
+    while let Some(&c) = chars.peek() {
+        if c == '\n' {
+            line += 1;
+        }
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '#' {
+            while chars.next_if(|&c| c != '\n').is_some() {}
+        } else if c == '"' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => text.push(match chars.next() {
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('v') => '\x0B',
+                        Some('0') => '\0',
+                        Some(c) => c,
+                        None => return Err(format!("Line {}: Unterminated string", line)),
+                    }),
+                    Some(c) => {
+                        if c == '\n' {
+                            line += 1;
+                        }
+                        text.push(c);
+                    }
+                    None => return Err(format!("Line {}: Unterminated string", line)),
+                }
+            }
+            tokens.push_back(Token {
+                text,
+                string: true,
+                line,
+            });
+        } else {
+            let mut text = String::new();
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                text.push(c);
+            }
+            tokens.push_back(Token {
+                text,
+                string: false,
+                line,
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix(['v', 'V'])?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    // line of the token that is being assembled
+    line: usize,
+    rom: Vec<u8>,
+    written: Vec<bool>,
+    here: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    string_modes: HashMap<String, Vec<StringMode>>,
+    fixups: Vec<(String, Fixup, usize)>,
+    control: Vec<Control>,
+}
+
+impl Assembler {
+    fn new(tokens: VecDeque<Token>) -> Self {
+        Assembler {
+            tokens,
+            line: 1,
+            rom: Vec::new(),
+            written: Vec::new(),
+            here: START,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            string_modes: HashMap::new(),
+            fixups: Vec::new(),
+            control: Vec::new(),
+        }
+    }
+
+    fn run(&mut self) -> Result<(), String> {
+        self.jump_to_label(0x1000, "main")?;
+        while !self.tokens.is_empty() {
+            self.statement()?;
+        }
+
+        match self.control.last() {
+            Some(Control::Loop(..)) => return Err("Missing 'again'".to_string()),
+            Some(_) => return Err("Missing 'end'".to_string()),
+            None => {}
+        }
+        for (name, fixup, line) in std::mem::take(&mut self.fixups) {
+            self.line = line;
+            let addr = *self
+                .labels
+                .get(&name)
+                .ok_or_else(|| format!("Undefined name '{}'", name))?;
+            self.fix(fixup, addr)?;
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self
+            .tokens
+            .pop_front()
+            .ok_or("Unexpected end of the program")?;
+        self.line = token.line;
+        Ok(token)
+    }
+
+    fn next_word(&mut self) -> Result<String, String> {
+        let token = self.next()?;
+        if token.string {
+            return Err(format!("Unexpected string \"{}\"", token.text));
+        }
+        Ok(token.text)
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens
+            .front()
+            .is_some_and(|token| !token.string && token.text == text)
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        let word = self.next_word()?;
+        if word != text {
+            return Err(format!("Expected '{}', got '{}'", text, word));
+        }
+        Ok(())
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let name = self.next_word()?;
+        if parse_number(&name).is_some() || parse_register(&name).is_some() {
+            return Err(format!("'{}' can't be used as a name", name));
+        }
+        Ok(name)
+    }
+
+    fn statement(&mut self) -> Result<(), String> {
+        let token = self.next()?;
+        if token.string {
+            return Err(format!("Unexpected string \"{}\"", token.text));
+        }
+        let word = token.text;
+        if let Some(x) = self.register(&word) {
+            return self.register_statement(x);
+        }
+        match word.as_str() {
+            ":" => {
+                let name = self.name()?;
+                self.define_label(name, self.here)
+            }
+            ":next" => {
+                let name = self.name()?;
+                self.define_label(name, self.here + 1)
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.next_word()?;
+                let x = self
+                    .register(&register)
+                    .ok_or_else(|| format!("Expected a register, got '{}'", register))?;
+                self.aliases.insert(name, x);
+                Ok(())
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.known_value()?;
+                self.constants.insert(name, value as f64);
+                Ok(())
+            }
+            ":calc" => {
+                let name = self.name()?;
+                self.expect("{")?;
+                let value = self.calc_block()?;
+                self.constants.insert(name, value);
+                Ok(())
+            }
+            ":org" => {
+                let addr = self.known_value()?;
+                if !(START as i64..=MAX_ADDR as i64).contains(&addr) {
+                    return Err(format!("Address {:#X} is out of range", addr));
+                }
+                self.here = addr as usize;
+                Ok(())
+            }
+            ":byte" => {
+                let value = self.known_value()?;
+                self.emit_byte(value)
+            }
+            ":pointer" => match self.value()? {
+                Value::Known(addr) => self.emit(Assembler::check(addr, MAX_ADDR)? as u16),
+                Value::Forward(name) => self.emit_fixup(name, Fixup::Long(self.here), 0),
+            },
+            ":call" => self.jump_to_value(0x2000),
+            ":unpack" => self.unpack(),
+            ":macro" => self.define_macro(),
+            ":stringmode" => self.define_string_mode(),
+            ":assert" => {
+                let message = match self.tokens.front() {
+                    Some(token) if token.string => Some(self.next()?.text),
+                    _ => None,
+                };
+                self.expect("{")?;
+                if self.calc_block()? == 0.0 {
+                    return Err(message.unwrap_or_else(|| "Assertion failed".to_string()));
+                }
+                Ok(())
+            }
+            ":breakpoint" | ":proto" => self.next().map(drop),
+            ":monitor" => {
+                self.next()?;
+                self.next().map(drop)
+            }
+            ";" | "return" => self.emit(0x00EE),
+            "clear" => self.emit(0x00E0),
+            "exit" => self.emit(0x00FD),
+            "lores" => self.emit(0x00FE),
+            "hires" => self.emit(0x00FF),
+            "scroll-down" => self.emit_nibble(0x00C0, 0),
+            "scroll-up" => self.emit_nibble(0x00D0, 0),
+            "scroll-right" => self.emit(0x00FB),
+            "scroll-left" => self.emit(0x00FC),
+            "audio" => self.emit(0xF002),
+            "plane" => self.emit_nibble(0xF001, 8),
+            "bcd" => self.register_op(0xF033),
+            "saveflags" => self.register_op(0xF075),
+            "loadflags" => self.register_op(0xF085),
+            "save" => self.save_load(0xF055, 0x5002),
+            "load" => self.save_load(0xF065, 0x5003),
+            "sprite" => {
+                let x = self.expect_register()?;
+                let y = self.expect_register()?;
+                let n = Assembler::check(self.known_value()?, 0xF)?;
+                self.emit(0xD000 | (x as u16) << 8 | (y as u16) << 4 | n as u16)
+            }
+            "jump" => self.jump_to_value(0x1000),
+            "jump0" => self.jump_to_value(0xB000),
+            "native" => self.jump_to_value(0x0000),
+            "i" => self.index_statement(),
+            "delay" => self.assign_from_register(0xF015),
+            "buzzer" => self.assign_from_register(0xF018),
+            "pitch" => self.assign_from_register(0xF03A),
+            "if" => self.if_statement(),
+            "else" => match self.control.pop() {
+                Some(Control::If(jump)) => {
+                    let skip = self.here;
+                    self.emit(0x1000)?;
+                    self.patch_jump(jump, self.here)?;
+                    self.control.push(Control::Else(skip));
+                    Ok(())
+                }
+                _ => Err("'else' without 'if ... begin'".to_string()),
+            },
+            "end" => match self.control.pop() {
+                Some(Control::If(jump)) | Some(Control::Else(jump)) => {
+                    self.patch_jump(jump, self.here)
+                }
+                _ => Err("'end' without 'if ... begin'".to_string()),
+            },
+            "loop" => {
+                self.control.push(Control::Loop(self.here, Vec::new()));
+                Ok(())
+            }
+            "while" => {
+                let condition = self.condition()?;
+                for opcode in condition.setup {
+                    self.emit(opcode)?;
+                }
+                self.emit(condition.when)?;
+                let exit = self.here;
+                self.emit(0x1000)?;
+                match self
+                    .control
+                    .iter_mut()
+                    .rev()
+                    .find(|control| matches!(control, Control::Loop(..)))
+                {
+                    Some(Control::Loop(_, exits)) => {
+                        exits.push(exit);
+                        Ok(())
+                    }
+                    _ => Err("'while' outside of a loop".to_string()),
+                }
+            }
+            "again" => match self.control.pop() {
+                Some(Control::Loop(start, exits)) => {
+                    self.emit(0x1000 | Assembler::check(start as i64, 0xFFF)? as u16)?;
+                    for exit in exits {
+                        self.patch_jump(exit, self.here)?;
+                    }
+                    Ok(())
+                }
+                _ => Err("'again' without 'loop'".to_string()),
+            },
+            _ => self.word_statement(word),
+        }
+    }
+
+    // Macros, string modes, data bytes and calls.
+    fn word_statement(&mut self, word: String) -> Result<(), String> {
+        if let Some(value) = parse_number(&word) {
+            return self.emit_byte(value);
+        }
+        if let Some(&value) = self.constants.get(&word) {
+            return self.emit_byte(value as i64);
+        }
+        if self.macros.contains_key(&word) {
+            return self.expand_macro(&word);
+        }
+        if self.string_modes.contains_key(&word) {
+            return self.expand_string_mode(&word);
+        }
+        if word.starts_with(':') {
+            return Err(format!("Unknown directive '{}'", word));
+        }
+        self.jump_to_label(0x2000, &word)
+    }
+
+    fn register(&self, word: &str) -> Option<u8> {
+        parse_register(word).or_else(|| self.aliases.get(word).copied())
+    }
+
+    fn expect_register(&mut self) -> Result<u8, String> {
+        let word = self.next_word()?;
+        self.register(&word)
+            .ok_or_else(|| format!("Expected a register, got '{}'", word))
+    }
+
+    fn register_op(&mut self, opcode: u16) -> Result<(), String> {
+        let x = self.expect_register()?;
+        self.emit(opcode | (x as u16) << 8)
+    }
+
+    // `delay := vx` and the like
+    fn assign_from_register(&mut self, opcode: u16) -> Result<(), String> {
+        self.expect(":=")?;
+        self.register_op(opcode)
+    }
+
+    fn save_load(&mut self, single: u16, range: u16) -> Result<(), String> {
+        let x = self.expect_register()?;
+        if !self.peek_is("-") {
+            return self.emit(single | (x as u16) << 8);
+        }
+        self.next()?;
+        let y = self.expect_register()?;
+        self.emit(range | (x as u16) << 8 | (y as u16) << 4)
+    }
+
+    fn register_statement(&mut self, x: u8) -> Result<(), String> {
+        let op = self.next_word()?;
+        let rhs = self.next()?;
+        let y = (!rhs.string).then(|| self.register(&rhs.text)).flatten();
+        let vx = (x as u16) << 8;
+        let xy = |opcode: u16, y: u8| opcode | vx | (y as u16) << 4;
+        match (op.as_str(), y) {
+            (":=", Some(y)) => self.emit(xy(0x8000, y)),
+            ("|=", Some(y)) => self.emit(xy(0x8001, y)),
+            ("&=", Some(y)) => self.emit(xy(0x8002, y)),
+            ("^=", Some(y)) => self.emit(xy(0x8003, y)),
+            ("+=", Some(y)) => self.emit(xy(0x8004, y)),
+            ("-=", Some(y)) => self.emit(xy(0x8005, y)),
+            (">>=", Some(y)) => self.emit(xy(0x8006, y)),
+            ("=-", Some(y)) => self.emit(xy(0x8007, y)),
+            ("<<=", Some(y)) => self.emit(xy(0x800E, y)),
+            (":=", None) if rhs.text == "key" => self.emit(0xF00A | vx),
+            (":=", None) if rhs.text == "delay" => self.emit(0xF007 | vx),
+            (":=", None) if rhs.text == "random" => {
+                let mask = self.byte_value()?;
+                self.emit(0xC000 | vx | mask as u16)
+            }
+            (":=", None) => {
+                let kk = self.token_byte(rhs)?;
+                self.emit(0x6000 | vx | kk as u16)
+            }
+            ("+=", None) => {
+                let kk = self.token_byte(rhs)?;
+                self.emit(0x7000 | vx | kk as u16)
+            }
+            ("-=", None) => {
+                let kk = self.token_byte(rhs)?;
+                self.emit(0x7000 | vx | kk.wrapping_neg() as u16)
+            }
+            _ => Err(format!("Unknown operation 'v{:X} {} {}'", x, op, rhs.text)),
+        }
+    }
+
+    fn index_statement(&mut self) -> Result<(), String> {
+        let op = self.next_word()?;
+        match op.as_str() {
+            "+=" => self.register_op(0xF01E),
+            ":=" if self.peek_is("hex") => {
+                self.next()?;
+                self.register_op(0xF029)
+            }
+            ":=" if self.peek_is("bighex") => {
+                self.next()?;
+                self.register_op(0xF030)
+            }
+            ":=" if self.peek_is("long") => {
+                self.next()?;
+                self.emit(0xF000)?;
+                match self.value()? {
+                    Value::Known(addr) => self.emit(Assembler::check(addr, MAX_ADDR)? as u16),
+                    Value::Forward(name) => self.emit_fixup(name, Fixup::Long(self.here), 0),
+                }
+            }
+            ":=" => self.jump_to_value(0xA000),
+            _ => Err(format!("Unknown operation 'i {}'", op)),
+        }
+    }
+
+    fn if_statement(&mut self) -> Result<(), String> {
+        let condition = self.condition()?;
+        for &opcode in &condition.setup {
+            self.emit(opcode)?;
+        }
+        let word = self.next_word()?;
+        match word.as_str() {
+            "then" => self.emit(condition.unless),
+            "begin" => {
+                self.emit(condition.when)?;
+                self.control.push(Control::If(self.here));
+                self.emit(0x1000)
+            }
+            _ => Err(format!("Expected 'then' or 'begin', got '{}'", word)),
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let x = self.expect_register()?;
+        let vx = (x as u16) << 8;
+        let op = self.next_word()?;
+        match op.as_str() {
+            "key" => {
+                return Ok(Condition {
+                    setup: Vec::new(),
+                    unless: 0xE0A1 | vx,
+                    when: 0xE09E | vx,
+                })
+            }
+            "-key" => {
+                return Ok(Condition {
+                    setup: Vec::new(),
+                    unless: 0xE09E | vx,
+                    when: 0xE0A1 | vx,
+                })
+            }
+            _ => {}
+        }
+
+        let rhs = self.next()?;
+        let y = (!rhs.string).then(|| self.register(&rhs.text)).flatten();
+        // the skips for `==` and `!=`
+        let (eq, ne) = match y {
+            Some(y) => (0x5000 | vx | (y as u16) << 4, 0x9000 | vx | (y as u16) << 4),
+            None => {
+                let kk = self.token_byte(rhs)? as u16;
+                (0x3000 | vx | kk, 0x4000 | vx | kk)
+            }
+        };
+        let load_vf = match y {
+            Some(y) => 0x8F00 | (y as u16) << 4,
+            None => 0x6F00 | (eq & 0xFF),
+        };
+        // VF := rhs, then VF =- vx leaves VF = vx >= rhs and VF -= vx leaves VF = rhs >= vx
+        let compare = |subtract: u16, flag: u16| Condition {
+            setup: vec![load_vf, subtract | (x as u16) << 4],
+            unless: 0x4F00 | flag,
+            when: 0x3F00 | flag,
+        };
+        Ok(match op.as_str() {
+            "==" => Condition {
+                setup: Vec::new(),
+                unless: ne,
+                when: eq,
+            },
+            "!=" => Condition {
+                setup: Vec::new(),
+                unless: eq,
+                when: ne,
+            },
+            "<" => compare(0x8F07, 0),
+            ">=" => compare(0x8F07, 1),
+            ">" => compare(0x8F05, 0),
+            "<=" => compare(0x8F05, 1),
+            _ => return Err(format!("Unknown comparison '{}'", op)),
+        })
+    }
+
+    fn unpack(&mut self) -> Result<(), String> {
+        let nibble = if self.peek_is("long") {
+            self.next()?;
+            None
+        } else {
+            Some(Assembler::check(self.known_value()?, 0xF)? as u8)
+        };
+        let hi = self.aliases.get("unpack-hi").copied().unwrap_or(0) as u16;
+        let lo = self.aliases.get("unpack-lo").copied().unwrap_or(1) as u16;
+        let limit = if nibble.is_some() { 0xFFF } else { MAX_ADDR };
+        match self.value()? {
+            Value::Known(addr) => {
+                let addr = Assembler::check(addr, limit)? as u16;
+                let high = (nibble.unwrap_or(0) as u16) << 4 | addr >> 8;
+                self.emit(0x6000 | hi << 8 | high)?;
+                self.emit(0x6000 | lo << 8 | (addr & 0xFF))
+            }
+            Value::Forward(name) => {
+                let fixup = Fixup::UnpackHi(self.here, nibble);
+                self.emit_fixup(name.clone(), fixup, 0x6000 | hi << 8)?;
+                self.emit_fixup(name, Fixup::UnpackLo(self.here), 0x6000 | lo << 8)
+            }
+        }
+    }
+
+    fn define_label(&mut self, name: String, addr: usize) -> Result<(), String> {
+        if self.labels.contains_key(&name) || self.constants.contains_key(&name) {
+            return Err(format!("'{}' is already defined", name));
+        }
+        self.labels.insert(name, addr);
+        Ok(())
+    }
+
+    fn define_macro(&mut self) -> Result<(), String> {
+        let name = self.name()?;
+        let mut args = Vec::new();
+        while !self.peek_is("{") {
+            args.push(self.name()?);
+        }
+        self.next()?;
+        let body = self.block()?;
+        self.macros.insert(
+            name,
+            Macro {
+                args,
+                body,
+                calls: 0,
+            },
+        );
+        Ok(())
+    }
+
+    fn expand_macro(&mut self, name: &str) -> Result<(), String> {
+        let count = self.macros[name].args.len();
+        let mut values = Vec::new();
+        for _ in 0..count {
+            values.push(self.next()?);
+        }
+        let line = self.line;
+        let mac = self.macros.get_mut(name).unwrap();
+        let calls = mac.calls;
+        mac.calls += 1;
+        let expanded: Vec<Token> = mac
+            .body
+            .iter()
+            .map(|token| {
+                let arg = mac.args.iter().position(|arg| *arg == token.text);
+                match arg {
+                    Some(index) if !token.string => Token {
+                        line,
+                        ..values[index].clone()
+                    },
+                    _ if !token.string && token.text == "CALLS" => Token {
+                        text: calls.to_string(),
+                        string: false,
+                        line,
+                    },
+                    _ => Token {
+                        line,
+                        ..token.clone()
+                    },
+                }
+            })
+            .collect();
+        for token in expanded.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    fn define_string_mode(&mut self) -> Result<(), String> {
+        let name = self.name()?;
+        let alphabet = self.next()?;
+        if !alphabet.string {
+            return Err(format!("Expected a string, got '{}'", alphabet.text));
+        }
+        self.expect("{")?;
+        let body = self.block()?;
+        self.string_modes.entry(name).or_default().push(StringMode {
+            alphabet: alphabet.text.chars().collect(),
+            body,
+        });
+        Ok(())
+    }
+
+    fn expand_string_mode(&mut self, name: &str) -> Result<(), String> {
+        let text = self.next()?;
+        if !text.string {
+            return Err(format!("Expected a string, got '{}'", text.text));
+        }
+        let line = self.line;
+        let mut expanded = Vec::new();
+        for (index, c) in text.text.chars().enumerate() {
+            let (mode, value) = self.string_modes[name]
+                .iter()
+                .find_map(|mode| {
+                    let value = mode.alphabet.iter().position(|&a| a == c)?;
+                    Some((mode, value))
+                })
+                .ok_or_else(|| format!("'{}' isn't in the alphabet of '{}'", c, name))?;
+            expanded.extend(mode.body.iter().map(|token| {
+                let text = match token.text.as_str() {
+                    _ if token.string => token.text.clone(),
+                    "CHAR" => (c as u32).to_string(),
+                    "INDEX" => index.to_string(),
+                    "VALUE" => value.to_string(),
+                    _ => token.text.clone(),
+                };
+                Token {
+                    text,
+                    string: token.string,
+                    line,
+                }
+            }));
+        }
+        for token in expanded.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    // The tokens up to the matching `}`, which is dropped.
+    fn block(&mut self) -> Result<Vec<Token>, String> {
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = self.next().map_err(|_| "Missing '}'".to_string())?;
+            if !token.string && token.text == "{" {
+                depth += 1;
+            } else if !token.string && token.text == "}" {
+                if depth == 0 {
+                    return Ok(body);
+                }
+                depth -= 1;
+            }
+            body.push(token);
+        }
+    }
+
+    // A value that has to be known right away: a number, a constant, a defined label or a
+    // calc block.
+    fn known_value(&mut self) -> Result<i64, String> {
+        match self.value()? {
+            Value::Known(value) => Ok(value),
+            Value::Forward(name) => Err(format!("Undefined name '{}'", name)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        let token = self.next()?;
+        self.token_value(token)
+    }
+
+    fn token_value(&mut self, token: Token) -> Result<Value, String> {
+        if token.string {
+            return Err(format!("Unexpected string \"{}\"", token.text));
+        }
+        if token.text == "{" {
+            return Ok(Value::Known(self.calc_block()?.floor() as i64));
+        }
+        if let Some(value) = parse_number(&token.text) {
+            return Ok(Value::Known(value));
+        }
+        if let Some(&value) = self.constants.get(&token.text) {
+            return Ok(Value::Known(value.floor() as i64));
+        }
+        if let Some(&addr) = self.labels.get(&token.text) {
+            return Ok(Value::Known(addr as i64));
+        }
+        if self.register(&token.text).is_some() {
+            return Err(format!("Expected a value, got '{}'", token.text));
+        }
+        Ok(Value::Forward(token.text))
+    }
+
+    fn byte_value(&mut self) -> Result<u8, String> {
+        let token = self.next()?;
+        self.token_byte(token)
+    }
+
+    fn token_byte(&mut self, token: Token) -> Result<u8, String> {
+        match self.token_value(token)? {
+            Value::Known(value) if (-128..=255).contains(&value) => Ok(value as u8),
+            Value::Known(value) => Err(format!("{} doesn't fit into a byte", value)),
+            Value::Forward(name) => Err(format!("Undefined name '{}'", name)),
+        }
+    }
+
+    fn check(value: i64, max: usize) -> Result<usize, String> {
+        if value < 0 || value as usize > max {
+            return Err(format!("{:#X} doesn't fit into {:#X}", value, max));
+        }
+        Ok(value as usize)
+    }
+
+    fn jump_to_value(&mut self, opcode: u16) -> Result<(), String> {
+        match self.value()? {
+            Value::Known(addr) => self.emit(opcode | Assembler::check(addr, 0xFFF)? as u16),
+            Value::Forward(name) => self.jump_to_label(opcode, &name),
+        }
+    }
+
+    fn jump_to_label(&mut self, opcode: u16, name: &str) -> Result<(), String> {
+        match self.labels.get(name) {
+            Some(&addr) => self.emit(opcode | Assembler::check(addr as i64, 0xFFF)? as u16),
+            None => self.emit_fixup(name.to_string(), Fixup::Addr(self.here), opcode),
+        }
+    }
+
+    fn emit_fixup(&mut self, name: String, fixup: Fixup, opcode: u16) -> Result<(), String> {
+        self.fixups.push((name, fixup, self.line));
+        self.emit(opcode)
+    }
+
+    fn fix(&mut self, fixup: Fixup, addr: usize) -> Result<(), String> {
+        match fixup {
+            Fixup::Addr(at) => {
+                let addr = Assembler::check(addr as i64, 0xFFF)?;
+                self.rom[at - START] |= (addr >> 8) as u8;
+                self.rom[at + 1 - START] = addr as u8;
+            }
+            Fixup::Long(at) => {
+                self.rom[at - START] = (addr >> 8) as u8;
+                self.rom[at + 1 - START] = addr as u8;
+            }
+            Fixup::UnpackHi(at, Some(nibble)) => {
+                let addr = Assembler::check(addr as i64, 0xFFF)?;
+                self.rom[at + 1 - START] = nibble << 4 | (addr >> 8) as u8;
+            }
+            Fixup::UnpackHi(at, None) => self.rom[at + 1 - START] = (addr >> 8) as u8,
+            Fixup::UnpackLo(at) => self.rom[at + 1 - START] = addr as u8,
+        }
+        Ok(())
+    }
+
+    fn patch_jump(&mut self, at: usize, target: usize) -> Result<(), String> {
+        let target = Assembler::check(target as i64, 0xFFF)?;
+        self.rom[at - START] = 0x10 | (target >> 8) as u8;
+        self.rom[at + 1 - START] = target as u8;
+        Ok(())
+    }
+
+    fn emit_nibble(&mut self, opcode: u16, shift: u16) -> Result<(), String> {
+        let n = Assembler::check(self.known_value()?, 0xF)? as u16;
+        self.emit(opcode | n << shift)
+    }
+
+    fn emit(&mut self, word: u16) -> Result<(), String> {
+        self.write((word >> 8) as u8)?;
+        self.write(word as u8)
+    }
+
+    fn emit_byte(&mut self, value: i64) -> Result<(), String> {
+        if !(-128..=255).contains(&value) {
+            return Err(format!("{} doesn't fit into a byte", value));
+        }
+        self.write(value as u8)
+    }
+
+    fn write(&mut self, byte: u8) -> Result<(), String> {
+        if self.here > MAX_ADDR {
+            return Err("The program doesn't fit into 64 KiB".to_string());
+        }
+        let index = self.here - START;
+        if index >= self.rom.len() {
+            self.rom.resize(index + 1, 0);
+            self.written.resize(index + 1, false);
+        }
+        if self.written[index] {
+            return Err(format!("Address {:#X} is written twice", self.here));
+        }
+        self.rom[index] = byte;
+        self.written[index] = true;
+        self.here += 1;
+        Ok(())
+    }
+
+    // Evaluates the tokens up to the matching `}`.
+    fn calc_block(&mut self) -> Result<f64, String> {
+        let tokens = self.block()?;
+        let mut calc = Calc {
+            tokens: &tokens,
+            pos: 0,
+            assembler: self,
+        };
+        let value = calc.expr()?;
+        match tokens.get(calc.pos) {
+            Some(token) => Err(format!("Unexpected '{}' in calc expression", token.text)),
+            None => Ok(value),
+        }
+    }
+}
+
+struct Calc<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    assembler: &'a Assembler,
+}
+
+const BINARY: [&str; 19] = [
+    "-", "+", "*", "/", "%", "&", "|", "^", "<<", ">>", "pow", "min", "max", "<", "<=", "==", "!=",
+    ">=", ">",
+];
+
+impl Calc<'_> {
+    fn next(&mut self) -> Result<&Token, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .ok_or("Incomplete calc expression")?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    // term (op expr)?, so operators are applied right to left
+    fn expr(&mut self) -> Result<f64, String> {
+        let lhs = self.term()?;
+        let op = match self.tokens.get(self.pos) {
+            Some(token) if !token.string && BINARY.contains(&token.text.as_str()) => {
+                token.text.clone()
+            }
+            _ => return Ok(lhs),
+        };
+        self.pos += 1;
+        let rhs = self.expr()?;
+        let int = |value: f64| value as i64;
+        let truth = |value: bool| if value { 1.0 } else { 0.0 };
+        Ok(match op.as_str() {
+            "-" => lhs - rhs,
+            "+" => lhs + rhs,
+            "*" => lhs * rhs,
+            "/" => lhs / rhs,
+            "%" => lhs % rhs,
+            "&" => (int(lhs) & int(rhs)) as f64,
+            "|" => (int(lhs) | int(rhs)) as f64,
+            "^" => (int(lhs) ^ int(rhs)) as f64,
+            "<<" => int(lhs).checked_shl(int(rhs) as u32).unwrap_or(0) as f64,
+            ">>" => int(lhs).checked_shr(int(rhs) as u32).unwrap_or(0) as f64,
+            "pow" => lhs.powf(rhs),
+            "min" => lhs.min(rhs),
+            "max" => lhs.max(rhs),
+            "<" => truth(lhs < rhs),
+            "<=" => truth(lhs <= rhs),
+            "==" => truth(lhs == rhs),
+            "!=" => truth(lhs != rhs),
+            ">=" => truth(lhs >= rhs),
+            ">" => truth(lhs > rhs),
+            _ => unreachable!(),
+        })
+    }
+
+    fn term(&mut self) -> Result<f64, String> {
+        let token = self.next()?.clone();
+        if token.string {
+            return Err(format!("Unexpected string \"{}\"", token.text));
+        }
+        let unary = |calc: &mut Self, f: fn(f64) -> f64| calc.term().map(f);
+        match token.text.as_str() {
+            "(" => {
+                let value = self.expr()?;
+                match self.next()? {
+                    token if token.text == ")" => Ok(value),
+                    token => Err(format!("Expected ')', got '{}'", token.text)),
+                }
+            }
+            "-" => unary(self, |value| -value),
+            "~" => unary(self, |value| !(value as i64) as f64),
+            "!" => unary(self, |value| if value == 0.0 { 1.0 } else { 0.0 }),
+            "sin" => unary(self, f64::sin),
+            "cos" => unary(self, f64::cos),
+            "tan" => unary(self, f64::tan),
+            "exp" => unary(self, f64::exp),
+            "log" => unary(self, f64::ln),
+            "abs" => unary(self, f64::abs),
+            "sqrt" => unary(self, f64::sqrt),
+            "sign" => unary(self, f64::signum),
+            "ceil" => unary(self, f64::ceil),
+            "floor" => unary(self, f64::floor),
+            "@" => {
+                let addr = self.term()? as i64;
+                let rom = &self.assembler.rom;
+                let index = addr - START as i64;
+                Ok(usize::try_from(index)
+                    .ok()
+                    .and_then(|index| rom.get(index))
+                    .copied()
+                    .unwrap_or(0) as f64)
+            }
+            "strlen" => match self.next()? {
+                token if token.string => Ok(token.text.chars().count() as f64),
+                token => Err(format!("Expected a string, got '{}'", token.text)),
+            },
+            "HERE" => Ok(self.assembler.here as f64),
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            text => {
+                if let Some(value) = parse_number(text) {
+                    return Ok(value as f64);
+                }
+                if let Some(&value) = self.assembler.constants.get(text) {
+                    return Ok(value);
+                }
+                if let Some(&addr) = self.assembler.labels.get(text) {
+                    return Ok(addr as f64);
+                }
+                Err(format!("Undefined name '{}' in calc expression", text))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./octo_test.rs"]
+mod octo_test;
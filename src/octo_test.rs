@@ -0,0 +1,281 @@
+use super::*;
+use crate::input::HeadlessKeypad;
+use crate::processor::Processor;
+
+// Runs the program until it ends in a jump to itself and returns V0 - VF.
+fn run(source: &str) -> [u8; 16] {
+    let rom = assemble(source).unwrap();
+    let mut processor = Processor::new();
+    processor.load(&rom);
+    let mut keypad = HeadlessKeypad::new();
+    for _ in 0..1000 {
+        let pc = processor.pc();
+        processor.step(&mut keypad);
+        if processor.pc() == pc {
+            break;
+        }
+    }
+    std::array::from_fn(|x| processor.register(x))
+}
+
+#[test]
+fn test_assemble() {
+    let rom = assemble(
+        "# draws a sprite
+        : main
+            clear
+            i := sprite
+            v0 := 10 v1 += 0x20 v2 -= 1
+            sprite v0 v1 5
+            draw
+        : loop-forever
+            jump loop-forever
+        : draw ;
+        : sprite 0xF0 0b10010000 0x90 144 0xF0",
+    )
+    .unwrap();
+    assert_eq!(
+        rom,
+        vec![
+            0x12, 0x02, 0x00, 0xE0, 0xA2, 0x14, 0x60, 0x0A, 0x71, 0x20, 0x72, 0xFF, 0xD0, 0x15,
+            0x22, 0x12, 0x12, 0x10, 0x00, 0xEE, 0xF0, 0x90, 0x90, 0x90, 0xF0
+        ]
+    );
+
+    // the rest of the instructions
+    let rom = assemble(
+        ": main
+            v3 := v4 v3 |= v4 v3 &= v4 v3 ^= v4 v3 += v4 v3 -= v4 v3 >>= v4 v3 =- v4 v3 <<= v4
+            v5 := key v5 := delay v5 := random 0x3F delay := v5 buzzer := v5 pitch := v5
+            i := hex v6 i := bighex v6 i += v6 bcd v6 save v6 load v6 save v1 - v2 load v1 - v2
+            saveflags v7 loadflags v7 jump0 0x300 native 0x123 i := long 0x1234
+            hires lores scroll-down 4 scroll-up 2 scroll-left scroll-right exit plane 3 audio",
+    )
+    .unwrap();
+    let words: Vec<u16> = rom[2..]
+        .chunks(2)
+        .map(|word| u16::from_be_bytes([word[0], word[1]]))
+        .collect();
+    assert_eq!(
+        words,
+        vec![
+            0x8340, 0x8341, 0x8342, 0x8343, 0x8344, 0x8345, 0x8346, 0x8347, 0x834E, 0xF50A, 0xF507,
+            0xC53F, 0xF515, 0xF518, 0xF53A, 0xF629, 0xF630, 0xF61E, 0xF633, 0xF655, 0xF665, 0x5122,
+            0x5123, 0xF775, 0xF785, 0xB300, 0x0123, 0xF000, 0x1234, 0x00FF, 0x00FE, 0x00C4, 0x00D2,
+            0x00FC, 0x00FB, 0x00FD, 0xF301, 0xF002
+        ]
+    );
+}
+
+#[test]
+fn test_control_flow() {
+    let rom = assemble(
+        ": main
+            if v0 == 1 then v1 := 2
+            if v0 != v2 begin v1 := 3 else v1 := 4 end
+            loop
+                v0 += 1
+                while v0 -key
+            again",
+    )
+    .unwrap();
+    assert_eq!(
+        rom,
+        vec![
+            0x12, 0x02, 0x40, 0x01, 0x61, 0x02, 0x90, 0x20, 0x12, 0x0E, 0x61, 0x03, 0x12, 0x10,
+            0x61, 0x04, 0x70, 0x01, 0xE0, 0xA1, 0x12, 0x18, 0x12, 0x10
+        ]
+    );
+
+    let registers = run(": main
+            loop
+                v0 += 1
+                if v0 == 3 then v1 := v0
+                while v0 != 5
+            again
+            if v1 == 3 begin v2 := 1 else v2 := 2 end
+            if v1 != 3 begin v3 := 1 else v3 := 2 end
+        : done jump done");
+    assert_eq!(registers[..4], [5, 3, 1, 2]);
+}
+
+// Runs straight-line code with `v0 = a` and `v1 = b` the way Octo expects: VF is written after
+// the result and is 1 when there is no borrow. The processor here writes VF first, so the VF
+// comparisons can't be checked on it.
+fn run_octo(rom: &[u8], a: u8, b: u8) -> [u8; 16] {
+    let mut v = [0; 16];
+    (v[0], v[1]) = (a, b);
+    let mut pc = 2;
+    while pc + 1 < rom.len() {
+        let (x, y, kk) = (
+            rom[pc] as usize & 0xF,
+            rom[pc + 1] as usize >> 4,
+            rom[pc + 1],
+        );
+        if rom[pc] >> 4 == 0x1 {
+            pc = (x << 8 | kk as usize) - 0x200;
+            continue;
+        }
+        let skip = match (rom[pc] >> 4, rom[pc + 1] & 0xF) {
+            (0x3, _) => v[x] == kk,
+            (0x4, _) => v[x] != kk,
+            (0x5, 0) => v[x] == v[y],
+            (0x9, 0) => v[x] != v[y],
+            (0x6, _) => {
+                v[x] = kk;
+                false
+            }
+            (0x8, 0) => {
+                v[x] = v[y];
+                false
+            }
+            (0x8, 5) => {
+                (v[x], v[0xF]) = (v[x].wrapping_sub(v[y]), (v[x] >= v[y]) as u8);
+                false
+            }
+            (0x8, 7) => {
+                (v[x], v[0xF]) = (v[y].wrapping_sub(v[x]), (v[y] >= v[x]) as u8);
+                false
+            }
+            _ => panic!("unexpected opcode {:02X}{:02X}", rom[pc], rom[pc + 1]),
+        };
+        pc += if skip { 4 } else { 2 };
+    }
+    v
+}
+
+#[test]
+fn test_comparisons() {
+    // the same code as Octo
+    let rom = assemble(": main if v0 < v1 then v2 := 1 if v0 >= 5 begin v3 := 1 end").unwrap();
+    assert_eq!(
+        rom[2..],
+        [
+            0x8F, 0x10, 0x8F, 0x07, 0x4F, 0x00, 0x62, 0x01, 0x6F, 0x05, 0x8F, 0x07, 0x3F, 0x01,
+            0x12, 0x14, 0x63, 0x01
+        ]
+    );
+
+    let values = [0, 1, 2, 0x7F, 0x80, 0xFE, 0xFF];
+    for a in values {
+        for b in values {
+            let rom = assemble(&format!(
+                ": main
+                    if v0 < v1 then v2 := 1
+                    if v0 > v1 then v3 := 1
+                    if v0 <= v1 then v4 := 1
+                    if v0 >= v1 then v5 := 1
+                    if v0 < {b} begin v6 := 1 end
+                    if v0 > {b} begin v7 := 1 end
+                    if v0 <= {b} begin v8 := 1 end
+                    if v0 >= {b} begin v9 := 1 end"
+            ))
+            .unwrap();
+            let registers = run_octo(&rom, a, b);
+            let expected = [a < b, a > b, a <= b, a >= b].map(u8::from);
+            assert_eq!(registers[2..6], expected, "{} {}", a, b);
+            assert_eq!(registers[6..10], expected, "{} {}", a, b);
+        }
+    }
+
+    // the rest runs on the processor
+    for a in values {
+        for b in values {
+            let registers = run(&format!(
+                ": main
+                    v0 := {a} v1 := {b}
+                    if v0 == {b} then v2 := 1
+                    if v0 != v1 then v3 := 1
+                    if v0 == v1 begin v4 := 1 else v4 := 2 end
+                    if v0 != {b} begin v5 := 1 end
+                : done jump done"
+            ));
+            let expected = [a == b, a != b].map(u8::from);
+            assert_eq!(registers[2..4], expected, "{} {}", a, b);
+            assert_eq!(
+                registers[4..6],
+                [2 - expected[0], expected[1]],
+                "{} {}",
+                a,
+                b
+            );
+        }
+    }
+}
+
+#[test]
+fn test_directives() {
+    let rom = assemble(
+        ":alias x v4
+        :const SPEED 3
+        :calc DOUBLE { SPEED * 2 + 1 }
+        :macro add-twice reg n { reg += n reg += n :byte CALLS }
+        :stringmode text \"AB\" { :byte { VALUE + 0x10 } }
+        : main
+            x := SPEED
+            add-twice x DOUBLE
+            add-twice v1 1
+            :unpack 0xA data
+            :unpack long data
+        :next target
+            v2 := 0
+            i := long target
+            :pointer data
+            text \"BA\"
+        : data
+        :assert \"data is even\" { data % 2 == 0 }
+        :org 0x300
+            :byte { @ 0x203 }",
+    )
+    .unwrap();
+    assert_eq!(
+        rom[..40],
+        [
+            0x12, 0x02, 0x64, 0x03, 0x74, 0x09, 0x74, 0x09, 0x00, 0x71, 0x01, 0x71, 0x01, 0x01,
+            0x60, 0xA2, 0x61, 0x20, 0x60, 0x02, 0x61, 0x20, 0x62, 0x00, 0xF0, 0x00, 0x02, 0x17,
+            0x02, 0x20, 0x11, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
+        ][..]
+    );
+    assert_eq!(rom.len(), 0x101);
+    assert_eq!(rom[0x100], 0x03);
+
+    // calc evaluates right to left without precedence
+    let rom = assemble(":calc X { 2 * 3 + 1 } :calc Y { ( 2 * 3 ) + 1 } : main :byte X :byte Y");
+    assert_eq!(rom.unwrap()[2..], [8, 7]);
+    let rom = assemble(":calc X { -2 max 5 - 1 } : main :byte { X } :byte { strlen \"abc\" }");
+    assert_eq!(rom.unwrap()[2..], [4, 3]);
+}
+
+#[test]
+fn test_errors() {
+    let error = |source: &str| assemble(source).err().unwrap();
+    assert_eq!(error("clear"), "Line 1: Undefined name 'main'");
+    assert_eq!(
+        error(": main\n\n  call-me"),
+        "Line 3: Undefined name 'call-me'"
+    );
+    assert_eq!(error(": main\n: main"), "Line 2: 'main' is already defined");
+    assert_eq!(
+        error(": main\n v0 := 256"),
+        "Line 2: 256 doesn't fit into a byte"
+    );
+    assert_eq!(
+        error(": main\n v0 ** v1"),
+        "Line 2: Unknown operation 'v0 ** v1'"
+    );
+    assert_eq!(error(": main if v0 == 1 begin"), "Line 1: Missing 'end'");
+    assert_eq!(error(": main again"), "Line 1: 'again' without 'loop'");
+    assert_eq!(
+        error(": main :assert { 1 == 2 }"),
+        "Line 1: Assertion failed"
+    );
+    assert_eq!(
+        error(": main clear :org 0x202 clear"),
+        "Line 1: Address 0x202 is written twice"
+    );
+    assert_eq!(error(": main \"text"), "Line 1: Unterminated string");
+    assert_eq!(
+        error(": main :unknown"),
+        "Line 1: Unknown directive ':unknown'"
+    );
+}
@@ -0,0 +1,148 @@
+use std::env;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+use chip8::trace::Tracer;
+use chip8::Quirks;
+
+pub struct Options {
+    // the launcher lets the player pick one without it
+    pub rom: Option<String>,
+    // where the launcher looks for ROMs
+    pub roms: PathBuf,
+    // None when not given on the command line, the ROM database decides then
+    pub quirks: Option<Quirks>,
+    pub ips: Option<u32>,
+    // uncapped when None
+    pub fast_forward: Option<f64>,
+    // None when not given on the command line, `default_romdb` is used then
+    pub romdb: Option<PathBuf>,
+    pub gdb_port: Option<u16>,
+    pub trace: Option<String>,
+    pub trace_ranges: Vec<RangeInclusive<usize>>,
+    pub trace_classes: Vec<u8>,
+    pub smc: bool,
+    pub profile: Option<String>,
+    pub coverage: Option<String>,
+    pub source_map: Option<String>,
+    pub symbols: Option<String>,
+    pub watch: bool,
+    pub record: Option<String>,
+    pub list: bool,
+}
+
+impl Options {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options {
+            rom: None,
+            roms: Options::data_dir().join("roms"),
+            quirks: None,
+            ips: None,
+            fast_forward: None,
+            romdb: None,
+            gdb_port: None,
+            trace: None,
+            trace_ranges: Vec::new(),
+            trace_classes: Vec::new(),
+            smc: false,
+            profile: None,
+            coverage: None,
+            source_map: None,
+            symbols: None,
+            watch: false,
+            record: None,
+            list: false,
+        };
+
+        // skip the program name
+        args.next();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--quirks" => {
+                    let name = args.next().ok_or("--quirks expects a profile")?;
+                    let quirks = Quirks::profile(&name).ok_or(format!(
+                        "Unknown quirk profile {}, expected one of {}",
+                        name,
+                        Quirks::PROFILES.join(", ")
+                    ))?;
+                    options.quirks = Some(quirks);
+                }
+                "--ips" => {
+                    let ips = args.next().ok_or("--ips expects a number")?;
+                    match ips.parse() {
+                        Ok(ips) if ips > 0 => options.ips = Some(ips),
+                        _ => return Err(format!("Invalid value for --ips: {}", ips)),
+                    }
+                }
+                "--fast-forward" => {
+                    let speed = args.next().ok_or("--fast-forward expects a number")?;
+                    match speed.parse::<f64>() {
+                        Ok(speed) if speed >= 1.0 => options.fast_forward = Some(speed),
+                        _ => return Err(format!("Invalid value for --fast-forward: {}", speed)),
+                    }
+                }
+                "--roms" => {
+                    options.roms = args.next().ok_or("--roms expects a directory")?.into();
+                }
+                "--romdb" => {
+                    options.romdb = Some(args.next().ok_or("--romdb expects a directory")?.into());
+                }
+                "--gdb" => {
+                    let port = args.next().ok_or("--gdb expects a port")?;
+                    let port = port
+                        .parse()
+                        .map_err(|_| format!("Invalid port for --gdb: {}", port))?;
+                    options.gdb_port = Some(port);
+                }
+                "--trace" => {
+                    options.trace = Some(args.next().ok_or("--trace expects a file or -")?);
+                }
+                "--trace-range" => {
+                    let range = args.next().ok_or("--trace-range expects START-END")?;
+                    options.trace_ranges.push(Tracer::parse_range(&range)?);
+                }
+                "--trace-ops" => {
+                    let classes = args.next().ok_or("--trace-ops expects a list like 8,D,F")?;
+                    options
+                        .trace_classes
+                        .extend(Tracer::parse_classes(&classes)?);
+                }
+                "--smc" => options.smc = true,
+                "--watch" => options.watch = true,
+                "--list" => options.list = true,
+                "--record" => {
+                    options.record = Some(args.next().ok_or("--record expects a file")?);
+                }
+                "--symbols" => {
+                    options.symbols = Some(args.next().ok_or("--symbols expects a file")?);
+                }
+                "--coverage" => {
+                    options.coverage = Some(args.next().ok_or("--coverage expects a file")?);
+                }
+                "--source-map" => {
+                    options.source_map = Some(args.next().ok_or("--source-map expects a file")?);
+                }
+                "--profile" => {
+                    options.profile = Some(args.next().ok_or("--profile expects a file")?);
+                }
+                _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+                _ => options.rom = Some(arg),
+            }
+        }
+        Ok(options)
+    }
+
+    // chip-8-database checkout in $XDG_DATA_HOME/chip8/database
+    pub fn default_romdb() -> PathBuf {
+        Options::data_dir().join("database")
+    }
+
+    // $XDG_DATA_HOME/chip8
+    pub fn data_dir() -> PathBuf {
+        let data = env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+            .unwrap_or_default();
+        data.join("chip8")
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Text drawn by the frontend on top of the scaled screen: a status line (FPS, IPS, run state)
+// that can be toggled and short lived notifications that are shown either way. None of it
+// touches the emulated framebuffer.
+
+pub const GLYPH_WIDTH: usize = 3;
+pub const GLYPH_HEIGHT: usize = 5;
+
+const MESSAGE_TIME: Duration = Duration::from_secs(2);
+const MAX_MESSAGES: usize = 4;
+
+// 3x5 glyphs, one row per byte with the leftmost pixel in bit 2. Lowercase letters are shown
+// as uppercase and unknown characters as `?`.
+const GLYPHS: [(char, [u8; GLYPH_HEIGHT]); 51] = [
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('+', [0b000, 0b010, 0b111, 0b010, 0b000]),
+    ('=', [0b000, 0b111, 0b000, 0b111, 0b000]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('%', [0b101, 0b001, 0b010, 0b100, 0b101]),
+    ('(', [0b001, 0b010, 0b010, 0b010, 0b001]),
+    (')', [0b100, 0b010, 0b010, 0b010, 0b100]),
+    ('<', [0b001, 0b010, 0b100, 0b010, 0b001]),
+    ('>', [0b100, 0b010, 0b001, 0b010, 0b100]),
+    ('!', [0b010, 0b010, 0b010, 0b000, 0b010]),
+    ('?', [0b111, 0b001, 0b010, 0b000, 0b010]),
+];
+
+pub struct Overlay {
+    visible: bool,
+    status: String,
+    messages: VecDeque<(String, Instant)>,
+}
+
+impl Default for Overlay {
+    fn default() -> Self {
+        Overlay::new()
+    }
+}
+
+impl Overlay {
+    pub fn new() -> Self {
+        Overlay {
+            visible: false,
+            status: String::new(),
+            messages: VecDeque::new(),
+        }
+    }
+
+    // Shows or hides the status line.
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_status(&mut self, status: impl Into<String>) {
+        self.status = status.into();
+    }
+
+    pub fn notify(&mut self, message: impl Into<String>) {
+        if self.messages.len() == MAX_MESSAGES {
+            self.messages.pop_front();
+        }
+        self.messages.push_back((message.into(), Instant::now()));
+    }
+
+    // Drops old notifications, returns true if any were removed.
+    pub fn expire(&mut self, now: Instant) -> bool {
+        let before = self.messages.len();
+        self.messages
+            .retain(|(_, shown)| now.duration_since(*shown) < MESSAGE_TIME);
+        self.messages.len() != before
+    }
+
+    // Whether there is anything to draw.
+    pub fn is_active(&self) -> bool {
+        self.visible || !self.messages.is_empty()
+    }
+
+    // Lines to draw from the top: the status line (if visible) followed by notifications.
+    pub fn lines(&self) -> Vec<&str> {
+        let status = Some(self.status.as_str()).filter(|_| self.visible);
+        status
+            .into_iter()
+            .chain(self.messages.iter().map(|(message, _)| message.as_str()))
+            .collect()
+    }
+
+    pub fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+        let c = c.to_ascii_uppercase();
+        GLYPHS
+            .iter()
+            .find(|(glyph, _)| *glyph == c)
+            .or_else(|| GLYPHS.iter().find(|(glyph, _)| *glyph == '?'))
+            .map(|(_, rows)| *rows)
+            .unwrap()
+    }
+
+    // Pixels of a line of text in font units, with one column of spacing between glyphs.
+    pub fn text_pixels(text: &str) -> Vec<(usize, usize)> {
+        let mut pixels = Vec::new();
+        for (i, c) in text.chars().enumerate() {
+            for (y, row) in Overlay::glyph(c).iter().enumerate() {
+                for x in 0..GLYPH_WIDTH {
+                    if row & (0b100 >> x) != 0 {
+                        pixels.push((i * (GLYPH_WIDTH + 1) + x, y));
+                    }
+                }
+            }
+        }
+        pixels
+    }
+}
+
+#[cfg(test)]
+#[path = "./overlay_test.rs"]
+mod overlay_test;
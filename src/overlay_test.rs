@@ -0,0 +1,38 @@
+use super::*;
+
+#[test]
+fn test_lines() {
+    let mut overlay = Overlay::new();
+    overlay.set_status("60 FPS");
+    assert!(!overlay.is_active());
+    assert!(overlay.lines().is_empty());
+
+    // notifications show up even with the status line hidden
+    overlay.notify("Saved slot 1");
+    assert_eq!(overlay.lines(), vec!["Saved slot 1"]);
+    overlay.toggle();
+    assert_eq!(overlay.lines(), vec!["60 FPS", "Saved slot 1"]);
+
+    for n in 0..MAX_MESSAGES {
+        overlay.notify(format!("message {}", n));
+    }
+    assert_eq!(overlay.lines().len(), 1 + MAX_MESSAGES);
+    assert_eq!(overlay.lines()[1], "message 0");
+
+    assert!(!overlay.expire(Instant::now()));
+    assert!(overlay.expire(Instant::now() + MESSAGE_TIME));
+    assert_eq!(overlay.lines(), vec!["60 FPS"]);
+}
+
+#[test]
+fn test_text_pixels() {
+    assert_eq!(Overlay::glyph('a'), Overlay::glyph('A'));
+    assert_eq!(Overlay::glyph('~'), Overlay::glyph('?'));
+
+    // `1` then `.`, 4 font pixels apart
+    let pixels = Overlay::text_pixels("1.");
+    assert!(pixels.contains(&(1, 0)));
+    assert!(pixels.contains(&(0, 1)));
+    assert!(pixels.contains(&(5, 4)));
+    assert_eq!(pixels.len(), 9);
+}
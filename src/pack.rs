@@ -0,0 +1,75 @@
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use zip::ZipArchive;
+
+// ROM packs: zip archives of ROMs. `pack.zip` lists the ROMs in it and
+// `pack.zip#path/in/archive.ch8` loads one of them.
+
+// ROM files by platform, see `Quirks::for_extension`
+pub const ROM_EXTENSIONS: [&str; 3] = ["ch8", "sc8", "xo8"];
+
+pub struct RomPack<R> {
+    archive: ZipArchive<R>,
+}
+
+impl RomPack<File> {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file =
+            File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        RomPack::from_reader(file).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+impl<R: Read + Seek> RomPack<R> {
+    pub fn from_reader(reader: R) -> Result<Self, String> {
+        let archive = ZipArchive::new(reader).map_err(|e| format!("Invalid zip archive: {}", e))?;
+        Ok(RomPack { archive })
+    }
+
+    // Splits `pack.zip#game.ch8` into the archive and the entry, None for anything that isn't
+    // a zip archive.
+    pub fn split(path: &str) -> Option<(&str, Option<&str>)> {
+        let lower = path.to_ascii_lowercase();
+        match lower.find(".zip#") {
+            Some(end) => Some((&path[..end + 4], Some(&path[end + 5..]))),
+            None if lower.ends_with(".zip") => Some((path, None)),
+            None => None,
+        }
+    }
+
+    pub fn is_rom(name: &str) -> bool {
+        Path::new(name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ROM_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+    }
+
+    // Names of the ROMs in the archive, sorted.
+    pub fn roms(&self) -> Vec<String> {
+        let mut roms: Vec<String> = self
+            .archive
+            .file_names()
+            .filter(|name| RomPack::<R>::is_rom(name))
+            .map(|name| name.to_string())
+            .collect();
+        roms.sort();
+        roms
+    }
+
+    pub fn read(&mut self, name: &str) -> Result<Vec<u8>, String> {
+        let mut file = self
+            .archive
+            .by_name(name)
+            .map_err(|_| format!("No {} in the archive", name))?;
+        let mut rom = Vec::new();
+        file.read_to_end(&mut rom)
+            .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+        Ok(rom)
+    }
+}
+
+#[cfg(test)]
+#[path = "./pack_test.rs"]
+mod pack_test;
@@ -0,0 +1,46 @@
+use super::*;
+use std::io::{Cursor, Write};
+
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::Quirks;
+
+fn build_pack() -> Cursor<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, data) in [
+        ("readme.txt", &b"hello"[..]),
+        ("games/pong.ch8", &[0x00, 0xE0]),
+        ("demos/ball.XO8", &[0x12, 0x00]),
+    ] {
+        zip.start_file(name, options).unwrap();
+        zip.write_all(data).unwrap();
+    }
+    Cursor::new(zip.finish().unwrap().into_inner())
+}
+
+#[test]
+fn test_roms() {
+    let mut pack = RomPack::from_reader(build_pack()).unwrap();
+    assert_eq!(pack.roms(), vec!["demos/ball.XO8", "games/pong.ch8"]);
+    assert_eq!(pack.read("games/pong.ch8").unwrap(), vec![0x00, 0xE0]);
+    assert!(pack.read("games/tetris.ch8").is_err());
+    assert!(RomPack::from_reader(Cursor::new(b"not a zip".to_vec())).is_err());
+}
+
+#[test]
+fn test_split() {
+    type Pack = RomPack<File>;
+    assert_eq!(
+        Pack::split("roms/pack.zip#games/pong.ch8"),
+        Some(("roms/pack.zip", Some("games/pong.ch8")))
+    );
+    assert_eq!(Pack::split("roms/PACK.ZIP"), Some(("roms/PACK.ZIP", None)));
+    assert_eq!(Pack::split("roms/pong.ch8"), None);
+
+    assert_eq!(Quirks::for_extension("xo8"), Some(Quirks::xochip()));
+    assert_eq!(Quirks::for_extension("SC8"), Some(Quirks::schip()));
+    assert_eq!(Quirks::for_extension("ch8"), Some(Quirks::default()));
+    assert_eq!(Quirks::for_extension("bin"), None);
+}
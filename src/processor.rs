@@ -1,14 +1,26 @@
-use crate::Keypad;
-use rand::Rng;
-use sdl2::event::Event;
-use sdl2::keyboard::Scancode;
+use crate::blocks::{BlockCache, MicroOp};
+use crate::coverage::Coverage;
+use crate::debugger::{Access, Stop, Watchpoint};
+use crate::flags::{FlagStore, RplFlags, RPL_FLAGS};
+use crate::framebuffer::Framebuffer;
+use crate::input::Input;
+use crate::profiler::Profiler;
+use crate::quirks::Quirks;
+use crate::romdb::RomDatabase;
+use crate::smc::SmcTracker;
+use crate::trace::{CpuState, Tracer};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::Instant;
 
 use crate::CHIP8_RAM;
+use crate::CHIP8_ROM_SIZE;
 use crate::CHIP8_SCREEN_HEIGHT;
 use crate::CHIP8_SCREEN_WIDTH;
 use crate::OPCODE_SIZE;
+use crate::STACK_SIZE;
 
-static FONTSET: [u8; 80] = [
+pub(crate) static FONTSET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
     0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
@@ -27,6 +39,13 @@ static FONTSET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+// Save states start with a magic and a version so that stale files are rejected.
+const STATE_MAGIC: &[u8; 4] = b"C8ST";
+const STATE_VERSION: u8 = 1;
+// magic + version + V0-VF + stack + PC + SP + I + DT + ST + RAM + VRAM
+const STATE_SIZE: usize = 4 + 1 + 16 + 16 * 2 + 2 + 1 + 2 + 1 + 1 + CHIP8_RAM + VRAM_SIZE;
+const VRAM_SIZE: usize = CHIP8_SCREEN_WIDTH * CHIP8_SCREEN_HEIGHT;
+
 enum ProgramCounter {
     Next,
     Skip,
@@ -50,12 +69,13 @@ impl ProgramCounter {
 // * 1 x 8-bit delay timer (DT)
 // * 1 x 8-bit sound timer (ST)
 // * 4096 bytes of RAM
+// * 16 x 8-bit RPL user flags (SUPER-CHIP has 8, XO-CHIP 16), kept across resets
 
 pub struct Processor {
     reg: [u8; 16],
-    stack: [usize; 16],
+    stack: [usize; STACK_SIZE],
     ram: [u8; CHIP8_RAM],
-    vram: [[u8; CHIP8_SCREEN_WIDTH]; CHIP8_SCREEN_HEIGHT],
+    vram: Framebuffer,
     pc: usize,
     sp: usize,
     i: usize,
@@ -63,6 +83,28 @@ pub struct Processor {
     sound_timer: u8,
     display_flag: bool,
     clear_flag: bool,
+    quirks: Quirks,
+    tracer: Option<Tracer>,
+    watchpoints: Vec<Watchpoint>,
+    break_on_index_overflow: bool,
+    watch_hit: Option<Stop>,
+    smc: Option<SmcTracker>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    stack_depth_limit: Option<usize>,
+    // kept for `reset`
+    rom: Vec<u8>,
+    rpl: RplFlags,
+    flag_store: Option<Box<dyn FlagStore>>,
+    // for Cxkk
+    rng: StdRng,
+    blocks: Option<BlockCache>,
+}
+
+impl Default for Processor {
+    fn default() -> Self {
+        Processor::new()
+    }
 }
 
 impl Processor {
@@ -72,9 +114,9 @@ impl Processor {
 
         Processor {
             reg: [0; 16],
-            stack: [0; 16],
+            stack: [0; STACK_SIZE],
             ram,
-            vram: [[0; CHIP8_SCREEN_WIDTH]; CHIP8_SCREEN_HEIGHT],
+            vram: Framebuffer::new(),
             pc: 0x200,
             sp: 0,
             i: 0,
@@ -82,40 +124,632 @@ impl Processor {
             sound_timer: 0,
             display_flag: false,
             clear_flag: false,
+            quirks: Quirks::default(),
+            tracer: None,
+            watchpoints: Vec::new(),
+            break_on_index_overflow: false,
+            watch_hit: None,
+            smc: None,
+            profiler: None,
+            coverage: None,
+            stack_depth_limit: None,
+            rom: Vec::new(),
+            rpl: [0; RPL_FLAGS],
+            flag_store: None,
+            rng: StdRng::from_entropy(),
+            blocks: None,
         }
     }
 
+    // A ROM that doesn't fit into RAM is cut off, here and in `reload`.
     pub fn load(&mut self, rom: &[u8]) {
+        let rom = &rom[..rom.len().min(CHIP8_ROM_SIZE)];
         // rom.read_exact(&mut self.ram[0x200..]).expect("Unable to read file!");
         for (i, &byte) in rom.iter().enumerate() {
             self.ram[0x200 + i] = byte;
         }
+        self.rom = rom.to_vec();
+        self.clear_blocks();
+        self.load_flags();
+    }
+
+    // Soft reset: clears registers, stack, timers, RAM and the screen and loads the font and
+    // the last loaded ROM again. Quirks, watchpoints and attached tools are kept.
+    pub fn reset(&mut self) {
+        self.reg = [0; 16];
+        self.stack = [0; STACK_SIZE];
+        self.ram = [0; CHIP8_RAM];
+        self.ram[..FONTSET.len()].clone_from_slice(&FONTSET[..]);
+        self.vram.clear();
+        self.pc = 0x200;
+        self.sp = 0;
+        self.i = 0;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.display_flag = false;
+        self.clear_flag = true;
+        self.watch_hit = None;
+        self.ram[0x200..0x200 + self.rom.len()].copy_from_slice(&self.rom);
+        self.clear_blocks();
+    }
+
+    // Resets and starts a different ROM, e.g. a new build of the one that is running.
+    pub fn reload(&mut self, rom: &[u8]) {
+        self.rom = rom[..rom.len().min(CHIP8_ROM_SIZE)].to_vec();
+        self.reset();
+        self.load_flags();
+    }
+
+    pub fn register(&self, x: usize) -> u8 {
+        self.reg[x]
+    }
+
+    pub fn set_register(&mut self, x: usize, val: u8) {
+        self.reg[x] = val;
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    pub fn i(&self) -> usize {
+        self.i
+    }
+
+    pub fn set_i(&mut self, i: usize) {
+        self.i = i;
+    }
+
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    pub fn set_sp(&mut self, sp: usize) {
+        self.sp = sp;
+    }
+
+    // Return addresses of the active calls, the innermost last.
+    pub fn stack(&self) -> &[usize] {
+        &self.stack[..self.sp.min(self.stack.len())]
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn set_delay_timer(&mut self, val: u8) {
+        self.delay_timer = val;
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn set_sound_timer(&mut self, val: u8) {
+        self.sound_timer = val;
+    }
+
+    pub fn ram(&self) -> &[u8; CHIP8_RAM] {
+        &self.ram
+    }
+
+    // Drops the translated blocks as anything may change.
+    pub fn ram_mut(&mut self) -> &mut [u8; CHIP8_RAM] {
+        self.clear_blocks();
+        &mut self.ram
+    }
+
+    pub fn vram(&self) -> &Framebuffer {
+        &self.vram
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(STATE_SIZE);
+        state.extend_from_slice(STATE_MAGIC);
+        state.push(STATE_VERSION);
+        state.extend_from_slice(&self.reg);
+        for &addr in self.stack.iter() {
+            state.extend_from_slice(&(addr as u16).to_le_bytes());
+        }
+        state.extend_from_slice(&(self.pc as u16).to_le_bytes());
+        state.push(self.sp as u8);
+        state.extend_from_slice(&(self.i as u16).to_le_bytes());
+        state.push(self.delay_timer);
+        state.push(self.sound_timer);
+        state.extend_from_slice(&self.ram);
+        // one byte per pixel
+        state.extend(self.vram.pixels().map(|on| on as u8));
+        state
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        if state.len() != STATE_SIZE || &state[..4] != STATE_MAGIC {
+            return Err("Not a save state".to_string());
+        }
+        if state[4] != STATE_VERSION {
+            return Err(format!("Unsupported save state version {}", state[4]));
+        }
+
+        let mut state = &state[5..];
+        let mut take = |len: usize| {
+            let (head, tail) = state.split_at(len);
+            state = tail;
+            head
+        };
+        let word = |bytes: &[u8]| u16::from_le_bytes([bytes[0], bytes[1]]) as usize;
+
+        // everything is checked before anything is changed, a bad state leaves the
+        // processor as it was
+        let reg = take(16);
+        let mut stack = [0; STACK_SIZE];
+        for addr in stack.iter_mut() {
+            *addr = word(take(2));
+        }
+        let pc = word(take(2));
+        let sp = take(1)[0] as usize;
+        let i = word(take(2));
+        if sp > STACK_SIZE {
+            return Err(format!("Invalid stack pointer {}", sp));
+        }
+        // the program counter, return addresses and I can be any 16 bit value, fetches and
+        // accesses past the end of RAM wrap around
+
+        self.reg.copy_from_slice(reg);
+        self.stack.copy_from_slice(&stack);
+        self.pc = pc;
+        self.sp = sp;
+        self.i = i;
+        self.delay_timer = take(1)[0];
+        self.sound_timer = take(1)[0];
+        self.ram.copy_from_slice(take(CHIP8_RAM));
+        self.clear_blocks();
+        for y in 0..CHIP8_SCREEN_HEIGHT {
+            for (x, &pixel) in take(CHIP8_SCREEN_WIDTH).iter().enumerate() {
+                self.vram.set(x, y, pixel != 0);
+            }
+        }
+        self.display_flag = true;
+        Ok(())
+    }
+
+    // Where Fx75 saves the RPL flags, the flags of the loaded ROM are read from it right away
+    // and again whenever a ROM is loaded.
+    pub fn set_flag_store(&mut self, store: Option<Box<dyn FlagStore>>) {
+        self.flag_store = store;
+        self.load_flags();
+    }
+
+    pub fn rpl_flags(&self) -> &RplFlags {
+        &self.rpl
+    }
+
+    fn load_flags(&mut self) {
+        self.rpl = [0; RPL_FLAGS];
+        let Some(store) = self.flag_store.as_mut() else {
+            return;
+        };
+        match store.load(&RomDatabase::sha1(&self.rom)) {
+            Ok(flags) => self.rpl = flags.unwrap_or_default(),
+            Err(e) => println!("Failed to load the RPL flags: {}", e),
+        }
+    }
+
+    // Lets `run_frame` run translated blocks instead of decoding every instruction, see
+    // `BlockCache`. It falls back to `step` while any debugging or profiling tool is
+    // attached.
+    pub fn set_block_cache(&mut self, enabled: bool) {
+        self.blocks = enabled.then(BlockCache::new);
+    }
+
+    pub fn block_cache(&self) -> Option<&BlockCache> {
+        self.blocks.as_ref()
+    }
+
+    fn clear_blocks(&mut self) {
+        if let Some(blocks) = self.blocks.as_mut() {
+            blocks.clear();
+        }
+    }
+
+    // Makes Cxkk return the same numbers on every run with the same seed.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) {
+        self.watchpoints.retain(|wp| wp != watchpoint);
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+        self.break_on_index_overflow = false;
+        self.watch_hit = None;
+    }
+
+    // Stop when Fx1E moves I past the end of RAM.
+    pub fn set_break_on_index_overflow(&mut self, enabled: bool) {
+        self.break_on_index_overflow = enabled;
+    }
+
+    pub fn break_on_index_overflow(&self) -> bool {
+        self.break_on_index_overflow
+    }
+
+    // Stop when a call nests deeper than `limit`, to catch runaway recursion before the
+    // stack overflows.
+    pub fn set_stack_depth_limit(&mut self, limit: Option<usize>) {
+        self.stack_depth_limit = limit;
+    }
+
+    pub fn stack_depth_limit(&self) -> Option<usize> {
+        self.stack_depth_limit
+    }
+
+    // Returns the first watchpoint that triggered since the last call.
+    pub fn take_watch_hit(&mut self) -> Option<Stop> {
+        self.watch_hit.take()
+    }
+
+    // Tracks executed and written RAM to detect self-modifying code, see `SmcTracker`.
+    pub fn set_smc_tracker(&mut self, smc: Option<SmcTracker>) {
+        self.smc = smc;
+    }
+
+    pub fn smc_tracker(&self) -> Option<&SmcTracker> {
+        self.smc.as_ref()
+    }
+
+    pub fn smc_tracker_mut(&mut self) -> Option<&mut SmcTracker> {
+        self.smc.as_mut()
+    }
+
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.profiler = profiler;
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    pub fn profiler_mut(&mut self) -> Option<&mut Profiler> {
+        self.profiler.as_mut()
+    }
+
+    // Returns the coverage collected so far.
+    pub fn set_coverage(&mut self, coverage: Option<Coverage>) -> Option<Coverage> {
+        std::mem::replace(&mut self.coverage, coverage)
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    pub fn cpu_state(&self) -> CpuState {
+        CpuState {
+            reg: self.reg,
+            i: self.i,
+            sp: self.sp,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+        }
+    }
+
+    // One instruction followed by a timer tick. Frontends that run at a fixed 60 Hz frame rate
+    // use `run_frame` (or `step` and `tick_timers`) instead.
+    pub fn emulate_cycle(&mut self, keypad: &mut dyn Input) -> (&Framebuffer, bool, bool) {
+        self.step(keypad);
+        self.tick_timers();
+        (&self.vram, self.display_flag, self.clear_flag)
     }
 
-    pub fn emulate_cycle(
-        &mut self,
-        keypad: &mut Keypad,
-    ) -> (&[[u8; CHIP8_SCREEN_WIDTH]; CHIP8_SCREEN_HEIGHT], bool, bool) {
+    // Runs one instruction without touching the timers.
+    pub fn step(&mut self, keypad: &mut dyn Input) -> (&Framebuffer, bool, bool) {
         self.display_flag = false;
         self.clear_flag = false;
         let opcode = self.fetch_opcode();
+        if self.smc.is_some() {
+            self.track_execute(opcode);
+        }
 
-        self.execute_opcode(opcode, keypad);
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1;
+        let pc = self.pc;
+        if self.profiler.is_some() {
+            self.execute_profiled(opcode, keypad);
+        } else {
+            self.execute_opcode(opcode, keypad);
         }
-        if self.sound_timer > 0 {
-            self.sound_timer -= 1;
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record(pc, opcode, self.pc);
         }
 
         (&self.vram, self.display_flag, self.clear_flag)
     }
 
-    fn fetch_opcode(&self) -> u16 {
-        (self.ram[self.pc] as u16) << 8 | self.ram[self.pc + 1] as u16
+    // Called once per 60 Hz frame.
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    // `cycles` instructions followed by a single timer tick, returns whether the screen
+    // changed during the frame.
+    pub fn run_frame(&mut self, keypad: &mut dyn Input, cycles: usize) -> bool {
+        let mut changed = false;
+        if self.blocks.is_some() && !self.is_instrumented() {
+            changed = self.run_blocks(keypad, cycles);
+        } else {
+            for _ in 0..cycles {
+                let (_, display, clear) = self.step(keypad);
+                changed |= display || clear;
+            }
+        }
+        self.tick_timers();
+        changed
+    }
+
+    fn is_instrumented(&self) -> bool {
+        self.tracer.is_some()
+            || !self.watchpoints.is_empty()
+            || self.smc.is_some()
+            || self.profiler.is_some()
+            || self.coverage.is_some()
+    }
+
+    // `cycles` instructions like `step` would run them, but from translated blocks.
+    fn run_blocks(&mut self, keypad: &mut dyn Input, cycles: usize) -> bool {
+        let mut changed = false;
+        let mut ran = 0;
+        while ran < cycles {
+            self.pc %= CHIP8_RAM;
+            let start = self.pc;
+            let blocks = self.blocks.as_mut().unwrap();
+            let block = blocks.get(&self.ram, start);
+            let len = blocks.block(block).ops.len();
+            blocks.take_invalidated();
+            if len == 0 {
+                let (_, display, clear) = self.step(keypad);
+                changed |= display || clear;
+                ran += 1;
+                continue;
+            }
+            for index in 0..len.min(cycles - ran) {
+                let op = self.blocks.as_ref().unwrap().op(block, index);
+                ran += 1;
+                self.display_flag = false;
+                self.clear_flag = false;
+                self.execute_micro_op(op, keypad);
+                changed |= self.display_flag || self.clear_flag;
+                // the block may have overwritten itself
+                if self.blocks.as_mut().unwrap().take_invalidated() {
+                    break;
+                }
+            }
+        }
+        changed
+    }
+
+    fn execute_micro_op(&mut self, op: MicroOp, keypad: &mut dyn Input) {
+        let next = self.pc + OPCODE_SIZE;
+        match op {
+            MicroOp::Load(x, kk) => self.reg[x] = kk,
+            MicroOp::Add(x, kk) => self.reg[x] = self.reg[x].wrapping_add(kk),
+            MicroOp::Copy(x, y) => self.reg[x] = self.reg[y],
+            MicroOp::Or(x, y) => {
+                self.reg[x] |= self.reg[y];
+                self.reset_vf();
+            }
+            MicroOp::And(x, y) => {
+                self.reg[x] &= self.reg[y];
+                self.reset_vf();
+            }
+            MicroOp::Xor(x, y) => {
+                self.reg[x] ^= self.reg[y];
+                self.reset_vf();
+            }
+            MicroOp::AddReg(x, y) => {
+                let (result, carry) = self.reg[x].overflowing_add(self.reg[y]);
+                self.reg[x] = result;
+                self.reg[0x0F] = carry as u8;
+            }
+            // VF is written first like in op_8, which matters when x or y is F
+            MicroOp::Sub(x, y) => {
+                self.reg[0x0F] = (self.reg[x] > self.reg[y]) as u8;
+                self.reg[x] = self.reg[x].wrapping_sub(self.reg[y]);
+            }
+            MicroOp::SubN(x, y) => {
+                self.reg[0x0F] = (self.reg[y] > self.reg[x]) as u8;
+                self.reg[x] = self.reg[y].wrapping_sub(self.reg[x]);
+            }
+            MicroOp::Shr(x, y) => {
+                if self.quirks.shift_vy {
+                    self.reg[x] = self.reg[y];
+                }
+                self.reg[0x0F] = self.reg[x] & 0x01;
+                self.reg[x] >>= 1;
+            }
+            MicroOp::Shl(x, y) => {
+                if self.quirks.shift_vy {
+                    self.reg[x] = self.reg[y];
+                }
+                self.reg[0x0F] = self.reg[x] >> 7;
+                self.reg[x] <<= 1;
+            }
+            MicroOp::LoadI(addr) => self.i = addr,
+            MicroOp::Jump(addr) => {
+                self.pc = addr;
+                return;
+            }
+            MicroOp::SkipEq(x, kk) => {
+                self.pc = next + if self.reg[x] == kk { OPCODE_SIZE } else { 0 };
+                return;
+            }
+            MicroOp::SkipNe(x, kk) => {
+                self.pc = next + if self.reg[x] != kk { OPCODE_SIZE } else { 0 };
+                return;
+            }
+            MicroOp::SkipEqReg(x, y) => {
+                self.pc = next
+                    + if self.reg[x] == self.reg[y] {
+                        OPCODE_SIZE
+                    } else {
+                        0
+                    };
+                return;
+            }
+            MicroOp::SkipNeReg(x, y) => {
+                self.pc = next
+                    + if self.reg[x] != self.reg[y] {
+                        OPCODE_SIZE
+                    } else {
+                        0
+                    };
+                return;
+            }
+            MicroOp::Interpret(opcode) => {
+                self.dispatch(opcode, keypad);
+                return;
+            }
+        }
+        self.pc = next;
+    }
+
+    // All reads and writes of RAM done by instructions go through these two so that
+    // watchpoints can see them. Fetching opcodes doesn't count as a read.
+    fn read_ram(&mut self, addr: usize) -> u8 {
+        let addr = self.ram_addr(addr);
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(addr, Access::Read);
+        }
+        self.ram[addr]
+    }
+
+    fn write_ram(&mut self, addr: usize, val: u8) {
+        let addr = self.ram_addr(addr);
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(addr, Access::Write);
+        }
+        if let Some(blocks) = self.blocks.as_mut() {
+            blocks.invalidate(addr);
+        }
+        if self.smc.is_some() {
+            self.track_write(addr);
+        }
+        self.ram[addr] = val;
+    }
+
+    // I can be pushed past the end of RAM (Fx1E, Fx55 and Fx65 with the increment quirk),
+    // accesses there wrap around instead of crashing and count as an index overflow.
+    fn ram_addr(&mut self, addr: usize) -> usize {
+        if addr >= CHIP8_RAM && self.break_on_index_overflow {
+            self.watch_hit.get_or_insert(Stop::IndexOverflow);
+        }
+        addr % CHIP8_RAM
+    }
+
+    #[cold]
+    fn track_execute(&mut self, opcode: u16) {
+        let smc = self.smc.as_mut().unwrap();
+        let event = smc.on_execute(self.pc, opcode);
+        if let Some(event) = event.filter(|_| smc.breaks()) {
+            self.watch_hit.get_or_insert(Stop::SelfModifyingCode(event));
+        }
+    }
+
+    #[cold]
+    fn track_write(&mut self, addr: usize) {
+        let opcode = self.fetch_opcode();
+        let smc = self.smc.as_mut().unwrap();
+        let event = smc.on_write(addr, self.pc, opcode);
+        if let Some(event) = event.filter(|_| smc.breaks()) {
+            self.watch_hit.get_or_insert(Stop::SelfModifyingCode(event));
+        }
+    }
+
+    #[cold]
+    fn check_watchpoints(&mut self, addr: usize, access: Access) {
+        if self.watch_hit.is_some() {
+            return;
+        }
+        let hit = self
+            .watchpoints
+            .iter()
+            .find(|wp| wp.range.contains(&addr) && wp.access.matches(access));
+        if let Some(wp) = hit {
+            self.watch_hit = Some(Stop::Watchpoint {
+                addr,
+                access: wp.access,
+            });
+        }
+    }
+
+    // Jumps (Bnnn) and running off the end can take the program counter past the end of RAM,
+    // like data accesses the fetch wraps around.
+    fn fetch_opcode(&mut self) -> u16 {
+        self.pc %= CHIP8_RAM;
+        (self.ram[self.pc] as u16) << 8 | self.ram[(self.pc + 1) % CHIP8_RAM] as u16
+    }
+
+    fn execute_opcode(&mut self, opcode: u16, keypad: &mut dyn Input) {
+        match self.tracer.take() {
+            Some(tracer) => self.execute_traced(opcode, keypad, tracer),
+            None => self.dispatch(opcode, keypad),
+        }
+    }
+
+    #[cold]
+    fn execute_profiled(&mut self, opcode: u16, keypad: &mut dyn Input) {
+        let pc = self.pc;
+        let start = Instant::now();
+        self.execute_opcode(opcode, keypad);
+        let elapsed = start.elapsed();
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.record(pc, opcode, elapsed);
+        }
     }
 
-    fn execute_opcode(&mut self, opcode: u16, keypad: &mut Keypad) {
+    #[cold]
+    fn execute_traced(&mut self, opcode: u16, keypad: &mut dyn Input, mut tracer: Tracer) {
+        if !tracer.wants(self.pc, opcode) {
+            self.dispatch(opcode, keypad);
+            self.tracer = Some(tracer);
+            return;
+        }
+
+        let pc = self.pc;
+        let before = self.cpu_state();
+        self.dispatch(opcode, keypad);
+        match tracer.log(pc, opcode, &before, &self.cpu_state()) {
+            Ok(()) => self.tracer = Some(tracer),
+            Err(e) => println!("Failed to write trace, disabling it: {}", e),
+        }
+    }
+
+    fn dispatch(&mut self, opcode: u16, keypad: &mut dyn Input) {
         let pc_update = match opcode & 0xF000 {
             0x0000 => self.op_0(opcode),
             0x1000 => self.op_1(opcode),
@@ -134,7 +768,7 @@ impl Processor {
             0xE000 => self.op_e(opcode, keypad),
             0xF000 => self.op_f(opcode, keypad),
             _ => {
-                println!("Invalid OPCODE {}", opcode);
+                self.invalid_opcode(opcode);
                 ProgramCounter::Next
             }
         };
@@ -150,17 +784,22 @@ impl Processor {
         match Processor::get_0nn(opcode) {
             0x00E0 => {
                 // 0x00E0(CLS) = Clear the screen.
-                self.vram = [[0; CHIP8_SCREEN_WIDTH]; CHIP8_SCREEN_HEIGHT];
+                self.vram.clear();
                 self.clear_flag = true;
                 ProgramCounter::Next
             }
             0x00EE => {
                 // 0x00EE(RET) = Return from subroutine.
+                if self.sp == 0 || self.sp > self.stack.len() {
+                    // nowhere to return to, carry on so the debugger can show where it happened
+                    self.watch_hit.get_or_insert(Stop::StackUnderflow);
+                    return ProgramCounter::Next;
+                }
                 self.sp -= 1;
                 ProgramCounter::Jump(self.stack[self.sp])
             }
             _ => {
-                Processor::print_err(opcode);
+                self.invalid_opcode(opcode);
                 ProgramCounter::Next
             }
         }
@@ -178,8 +817,16 @@ impl Processor {
         0x2nnn(CALL addr) = Call subroutine at nnn.
         */
         // https://old.reddit.com/r/EmuDev/comments/5so1bo/chip8_emu_questions/ddibkkp/
+        if self.sp >= self.stack.len() {
+            // the call is dropped, carry on so the debugger can show where it happened
+            self.watch_hit.get_or_insert(Stop::StackOverflow);
+            return ProgramCounter::Next;
+        }
         self.stack[self.sp] = self.pc + OPCODE_SIZE;
         self.sp += 1;
+        if self.stack_depth_limit.is_some_and(|limit| self.sp > limit) {
+            self.watch_hit.get_or_insert(Stop::StackDepth(self.sp));
+        }
         ProgramCounter::Jump(Processor::get_nnn(opcode))
     }
 
@@ -236,16 +883,19 @@ impl Processor {
             0x01 => {
                 // 0x8xy1(OR Vx, Vy) = Set Vx = Vx OR Vy.
                 self.reg[x] |= self.reg[y];
+                self.reset_vf();
                 ProgramCounter::Next
             }
             0x02 => {
                 // 0x8xy2(AND Vx, Vy) = Set Vx = Vx AND vy.
                 self.reg[x] &= self.reg[y];
+                self.reset_vf();
                 ProgramCounter::Next
             }
             0x03 => {
                 // 0x8xy3(XOR Vx, Vy) = Set Vx = Vx XOR vy.
                 self.reg[x] ^= self.reg[y];
+                self.reset_vf();
                 ProgramCounter::Next
             }
             0x04 => {
@@ -265,6 +915,9 @@ impl Processor {
             }
             0x06 => {
                 // 0x8xy6(SHR Vx, Vy) = VF = Vx & 1. Set Vx = Vx SHR 1. (Shift Right)
+                if self.quirks.shift_vy {
+                    self.reg[x] = self.reg[y];
+                }
                 self.reg[0x0F] = self.reg[x] & 0x01;
                 self.reg[x] >>= 1;
                 ProgramCounter::Next
@@ -278,12 +931,15 @@ impl Processor {
             }
             0x0E => {
                 // 0x8xyE(SHL Vx, Vy) = VF = Vx & 255. Set Vx = Vx SHL 1. (Shift Left)
+                if self.quirks.shift_vy {
+                    self.reg[x] = self.reg[y];
+                }
                 self.reg[0x0F] = self.reg[x] >> 7;
                 self.reg[x] <<= 1;
                 ProgramCounter::Next
             }
             _ => {
-                Processor::print_err(opcode);
+                self.invalid_opcode(opcode);
                 ProgramCounter::Next
             }
         }
@@ -310,15 +966,20 @@ impl Processor {
         /*
         0xBnnn(JP V0, addr) = Jump to location nnn + V0.
         */
-        ProgramCounter::Jump(Processor::get_nnn(opcode) + self.reg[0] as usize)
+        // With the jump quirk this is Bxnn(JP Vx, addr) = Jump to location xnn + Vx.
+        let x = if self.quirks.jump_vx {
+            Processor::get_x(opcode)
+        } else {
+            0
+        };
+        ProgramCounter::Jump(Processor::get_nnn(opcode) + self.reg[x] as usize)
     }
 
     fn op_c(&mut self, opcode: u16) -> ProgramCounter {
         /*
         0xCxkk(RND Vx, byte) = Vx = random bytes & kk.
         */
-        let mut rng = rand::thread_rng();
-        self.reg[Processor::get_x(opcode)] = Processor::get_0nn(opcode) & rng.gen::<u8>();
+        self.reg[Processor::get_x(opcode)] = Processor::get_0nn(opcode) & self.rng.gen::<u8>();
         ProgramCounter::Next
     }
 
@@ -335,46 +996,49 @@ impl Processor {
 
         // https://tobiasvl.github.io/blog/write-a-chip-8-emulator/#dxyn-display
         for byte in 0..n {
-            let data = self.ram[self.i + byte];
-            let sy = vy + byte;
-            if sy == CHIP8_SCREEN_HEIGHT {
-                break;
-            }
-            for bit in 0..8 {
-                let bit_to_draw = data & (1 << (7 - bit));
-                let sx = vx + bit;
-                if sx == CHIP8_SCREEN_WIDTH {
+            let data = self.read_ram(self.i + byte);
+            let mut sy = vy + byte;
+            if sy >= CHIP8_SCREEN_HEIGHT {
+                if !self.quirks.wrap_sprites {
                     break;
                 }
-                self.reg[0x0F] |= bit_to_draw & self.vram[sy][sx];
-                self.vram[sy][sx] ^= bit_to_draw;
+                sy %= CHIP8_SCREEN_HEIGHT;
+            }
+            // the sprite row at x = 0 is the top byte of a screen row, shifting it right drops
+            // the pixels past the right edge and rotating wraps them around to the left
+            let sprite = (data as u64) << (CHIP8_SCREEN_WIDTH - 8);
+            let row = if self.quirks.wrap_sprites {
+                sprite.rotate_right(vx as u32)
+            } else {
+                sprite >> vx
+            };
+            if self.vram.draw_row(sy, row) {
+                self.reg[0x0F] = 1;
             }
         }
         ProgramCounter::Next
     }
 
-    fn op_e(&mut self, opcode: u16, keypad: &Keypad) -> ProgramCounter {
+    fn op_e(&mut self, opcode: u16, keypad: &dyn Input) -> ProgramCounter {
         let x = Processor::get_x(opcode);
         match Processor::get_0nn(opcode) {
             0x9E => {
                 // 0xEX9E(SKP Vx) = Skip next instruction if key with the value of Vx is pressed.
-                let keycode = Keypad::unmap_key(self.reg[x]);
-                ProgramCounter::skip_if(keypad.is_pressed(keycode.unwrap()))
+                ProgramCounter::skip_if(keypad.is_key_down(self.reg[x]))
             }
             0xA1 => {
                 // 0xEXA1(SKNP Vx) = Skip next instruction if key with the value of Vx is not
                 // pressed.
-                let keycode = Keypad::unmap_key(self.reg[x]);
-                ProgramCounter::skip_if(!keypad.is_pressed(keycode.unwrap()))
+                ProgramCounter::skip_if(!keypad.is_key_down(self.reg[x]))
             }
             _ => {
-                Processor::print_err(opcode);
+                self.invalid_opcode(opcode);
                 ProgramCounter::Next
             }
         }
     }
 
-    fn op_f(&mut self, opcode: u16, keypad: &mut Keypad) -> ProgramCounter {
+    fn op_f(&mut self, opcode: u16, keypad: &mut dyn Input) -> ProgramCounter {
         let x = Processor::get_x(opcode);
         match Processor::get_0nn(opcode) {
             0x07 => {
@@ -384,25 +1048,13 @@ impl Processor {
             }
             0x0A => {
                 // 0xFx0A(LD Vx, K) = Wait for a key press, store the value of the key in Vx.
-                let key_idx = loop {
-                    let event: Event = keypad.wait_key_press();
-                    let keycode = match event {
-                        Event::KeyDown {
-                            keycode: Some(code),
-                            ..
-                        } => Some(code),
-                        _ => None,
-                    };
-                    if let Some(keycode) = keycode {
-                        let scancode = Scancode::from_keycode(keycode).unwrap();
-                        let key = Keypad::map_key(scancode);
-                        if let Some(key) = key {
-                            break key;
-                        }
-                    }
+                let key_idx = match keypad.wait_key() {
+                    Some(key_idx) => key_idx,
+                    // run this instruction again until a key is pressed
+                    None => return ProgramCounter::Jump(self.pc),
                 };
 
-                self.ram[x] = key_idx;
+                self.reg[x] = key_idx;
                 ProgramCounter::Next
             }
             0x15 => {
@@ -417,7 +1069,11 @@ impl Processor {
             }
             0x1E => {
                 // Fx1E(ADD I, Vx) = Set I = I + Vx.
-                self.i += self.reg[x] as usize;
+                // I is a 16 bit register, it can go past the end of RAM but not past 0xFFFF.
+                self.i = (self.i + self.reg[x] as usize) & 0xFFFF;
+                if self.i >= CHIP8_RAM && self.break_on_index_overflow {
+                    self.watch_hit.get_or_insert(Stop::IndexOverflow);
+                }
                 ProgramCounter::Next
             }
             0x29 => {
@@ -426,37 +1082,64 @@ impl Processor {
                 // It just requests the char(0-F) that it wants and we give it that.
                 // So as each char takes up 5 bytes,
                 // we calculate the offset by multiplying V[x] by 5 to get the font addr.
-                self.i = (self.ram[x] as usize) * 5;
+                self.i = (self.reg[x] as usize) * 5;
                 ProgramCounter::Next
             }
             0x33 => {
                 // Fx33(LD B, Vx) = Store BCD representation of Vx in memory locations I, I+1, and I+2.
-                self.ram[self.i] = self.reg[x] / 100;
-                self.ram[self.i + 1] = (self.reg[x] / 10) % 10;
-                self.ram[self.i + 2] = (self.reg[x]) % 10;
+                self.write_ram(self.i, self.reg[x] / 100);
+                self.write_ram(self.i + 1, (self.reg[x] / 10) % 10);
+                self.write_ram(self.i + 2, (self.reg[x]) % 10);
                 ProgramCounter::Next
             }
             0x55 => {
                 // Fx55(LD [I], Vx) = Store registers V0 through Vx in memory starting at location I.
                 for i in 0..=x {
-                    self.ram[self.i + i] = self.reg[i];
+                    self.write_ram(self.i + i, self.reg[i]);
+                }
+                if self.quirks.increment_i {
+                    self.i = (self.i + x + 1) & 0xFFFF;
                 }
                 ProgramCounter::Next
             }
             0x65 => {
                 // Fx65(LD Vx, [I]) = Read registers V0 through Vx from memory starting at location I.
                 for i in 0..=x {
-                    self.reg[i] = self.ram[self.i + i];
+                    self.reg[i] = self.read_ram(self.i + i);
+                }
+                if self.quirks.increment_i {
+                    self.i = (self.i + x + 1) & 0xFFFF;
+                }
+                ProgramCounter::Next
+            }
+            0x75 => {
+                // Fx75(LD R, Vx) = Store registers V0 through Vx in the RPL user flags.
+                self.rpl[..=x].copy_from_slice(&self.reg[..=x]);
+                if let Some(store) = self.flag_store.as_mut() {
+                    if let Err(e) = store.save(&RomDatabase::sha1(&self.rom), &self.rpl) {
+                        println!("Failed to save the RPL flags: {}", e);
+                    }
                 }
                 ProgramCounter::Next
             }
+            0x85 => {
+                // Fx85(LD Vx, R) = Read registers V0 through Vx from the RPL user flags.
+                self.reg[..=x].copy_from_slice(&self.rpl[..=x]);
+                ProgramCounter::Next
+            }
             _ => {
-                Processor::print_err(opcode);
+                self.invalid_opcode(opcode);
                 ProgramCounter::Next
             }
         }
     }
 
+    fn reset_vf(&mut self) {
+        if self.quirks.vf_reset {
+            self.reg[0x0F] = 0;
+        }
+    }
+
     pub fn pretty_print(&self) {
         for (i, &val) in self.reg.iter().enumerate() {
             print!("V{:01x}: {}", i, val);
@@ -489,15 +1172,13 @@ impl Processor {
         (opcode & 0x0FFF) as usize
     }
 
-    fn print_err(opcode: u16) {
-        println!(
-            "Invalid operation in OPCODE {:04x} with args {:04x}",
-            opcode & 0xF000,
-            Processor::get_nnn(opcode)
-        );
+    // Reported like the other faults instead of printed, a fuzzer runs into plenty of them.
+    fn invalid_opcode(&mut self, opcode: u16) {
+        self.watch_hit.get_or_insert(Stop::InvalidOpcode(opcode));
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 #[path = "./processor_test.rs"]
 mod processor_test;
@@ -1,14 +1,9 @@
 use super::*;
+use crate::flags::MemoryFlagStore;
+use crate::HeadlessKeypad;
 
-fn build_processor() -> Processor {
-    let mut processor = Processor::new();
-    processor.pc = 0x200;
-    processor
-}
-
-fn get_keypad() -> Keypad {
-    let sdl_context = sdl2::init().unwrap();
-    Keypad::new(&sdl_context)
+fn get_keypad() -> HeadlessKeypad {
+    HeadlessKeypad::new()
 }
 
 #[test]
@@ -53,10 +48,7 @@ fn test_op_00e0() {
     let mut keypad = get_keypad();
     processor.execute_opcode(0x00E0, &mut keypad);
 
-    assert_eq!(
-        processor.vram,
-        [[0; CHIP8_SCREEN_WIDTH]; CHIP8_SCREEN_HEIGHT]
-    );
+    assert_eq!(processor.vram, Framebuffer::new());
     assert_eq!(processor.clear_flag, true);
     assert_eq!(processor.pc, 0x200 + OPCODE_SIZE);
 }
@@ -161,7 +153,483 @@ fn test_7xkk() {
     processor.execute_opcode(0x7588, &mut keypad);
     assert_eq!(processor.reg[0x5], 0x88);
 
-    // TODO: check this
+    // 0x88 + 0xFE = 0x186, wraps around without touching VF
     processor.execute_opcode(0x75FE, &mut keypad);
-    assert_eq!(processor.reg[0x5], 0x87);
+    assert_eq!(processor.reg[0x5], 0x86);
+    assert_eq!(processor.reg[0xF], 0);
+}
+
+#[test]
+fn test_save_state() {
+    let mut processor = Processor::new();
+    processor.load(&[0x12, 0x34]);
+    processor.reg[0x3] = 0x10;
+    processor.stack[0] = 0x246;
+    processor.sp = 1;
+    processor.pc = 0x2A0;
+    processor.i = 0x300;
+    processor.delay_timer = 7;
+    processor.vram.set(63, 31, true);
+    let state = processor.save_state();
+
+    let mut restored = Processor::new();
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.reg, processor.reg);
+    assert_eq!(restored.stack, processor.stack);
+    assert_eq!(restored.sp, 1);
+    assert_eq!(restored.pc, 0x2A0);
+    assert_eq!(restored.i, 0x300);
+    assert_eq!(restored.delay_timer, 7);
+    assert_eq!(restored.ram, processor.ram);
+    assert_eq!(restored.vram, processor.vram);
+    assert_eq!(restored.save_state(), state);
+}
+
+#[test]
+fn test_load_invalid_state() {
+    let mut processor = Processor::new();
+    let mut state = processor.save_state();
+    assert!(processor.load_state(&state[1..]).is_err());
+
+    state[4] = 0xFF;
+    assert!(processor.load_state(&state).is_err());
+
+    // V0, then the stack pointer out of range
+    state[4] = STATE_VERSION;
+    state[5] = 0x42;
+    let mut corrupt = state.clone();
+    corrupt[55] = 17;
+    assert!(processor.load_state(&corrupt).is_err());
+    // nothing was loaded
+    assert_eq!(processor.reg[0], 0);
+    assert!(processor.load_state(&state).is_ok());
+    assert_eq!(processor.reg[0], 0x42);
+}
+
+#[test]
+fn test_load_state_index_past_ram() {
+    let mut processor = Processor::new();
+    let mut keypad = get_keypad();
+    processor.i = 0xFF0;
+    processor.reg[0] = 0xFF;
+    processor.execute_opcode(0xF01E, &mut keypad);
+    assert_eq!(processor.i, 0x10EF);
+
+    let state = processor.save_state();
+    let mut loaded = Processor::new();
+    assert_eq!(loaded.load_state(&state), Ok(()));
+    assert_eq!(loaded.i, 0x10EF);
+
+    // I wraps around at 16 bits
+    processor.i = 0xFFFF;
+    processor.execute_opcode(0xF01E, &mut keypad);
+    assert_eq!(processor.i, 0xFE);
+}
+
+#[test]
+fn test_pc_past_ram() {
+    let mut processor = Processor::new();
+    let mut keypad = get_keypad();
+    // JP 0xFFF, the opcode there is made of the last byte of RAM and the first one
+    processor.load(&[0x1F, 0xFF]);
+    processor.ram[0xFFF] = 0x60;
+    processor.step(&mut keypad);
+    processor.step(&mut keypad);
+    assert_eq!(processor.reg[0], processor.ram[0]);
+    assert_eq!(processor.pc, 0x1001);
+
+    let mut loaded = Processor::new();
+    assert_eq!(loaded.load_state(&processor.save_state()), Ok(()));
+    assert_eq!(loaded.pc, 0x1001);
+
+    // JP V0, 0xFFF
+    for block_cache in [false, true] {
+        let mut processor = Processor::new();
+        processor.set_block_cache(block_cache);
+        processor.load(&[0x60, 0xFF, 0xBF, 0xFF]);
+        processor.run_frame(&mut keypad, 3);
+        assert_eq!(processor.pc, 0x100);
+    }
+}
+
+#[test]
+fn test_invalid_opcode() {
+    let mut processor = Processor::new();
+    let mut keypad = get_keypad();
+    processor.execute_opcode(0x0000, &mut keypad);
+    assert_eq!(
+        processor.take_watch_hit(),
+        Some(Stop::InvalidOpcode(0x0000))
+    );
+    assert_eq!(processor.pc, 0x200 + OPCODE_SIZE);
+    processor.execute_opcode(0xE1FF, &mut keypad);
+    assert_eq!(
+        processor.take_watch_hit(),
+        Some(Stop::InvalidOpcode(0xE1FF))
+    );
+}
+
+#[test]
+fn test_watchpoints() {
+    let mut processor = Processor::new();
+    let mut keypad = get_keypad();
+    processor.add_watchpoint(Watchpoint {
+        range: 0x300..=0x30F,
+        access: Access::Write,
+    });
+    processor.add_watchpoint(Watchpoint {
+        range: 0x400..=0x400,
+        access: Access::Read,
+    });
+
+    // reads of a write watchpoint are ignored
+    processor.i = 0x300;
+    processor.execute_opcode(0xF365, &mut keypad);
+    assert_eq!(processor.take_watch_hit(), None);
+
+    processor.execute_opcode(0xF355, &mut keypad);
+    assert_eq!(
+        processor.take_watch_hit(),
+        Some(Stop::Watchpoint {
+            addr: 0x300,
+            access: Access::Write
+        })
+    );
+
+    // sprites are read from RAM too
+    processor.i = 0x3FE;
+    processor.execute_opcode(0xD005, &mut keypad);
+    assert_eq!(
+        processor.take_watch_hit(),
+        Some(Stop::Watchpoint {
+            addr: 0x400,
+            access: Access::Read
+        })
+    );
+}
+
+#[test]
+fn test_index_overflow() {
+    let mut processor = Processor::new();
+    let mut keypad = get_keypad();
+    processor.i = 0xFFE;
+    processor.reg[1] = 1;
+    processor.execute_opcode(0xF11E, &mut keypad);
+    assert_eq!(processor.take_watch_hit(), None);
+
+    processor.set_break_on_index_overflow(true);
+    processor.execute_opcode(0xF11E, &mut keypad);
+    assert_eq!(processor.take_watch_hit(), Some(Stop::IndexOverflow));
+
+    // I is now past the end of RAM, accesses wrap around instead of panicking
+    processor.set_break_on_index_overflow(false);
+    processor.reg[0] = 0xAB;
+    processor.execute_opcode(0xF055, &mut keypad);
+    assert_eq!(processor.ram[0x000], 0xAB);
+    processor.execute_opcode(0xD001, &mut keypad);
+    processor.set_break_on_index_overflow(true);
+    processor.execute_opcode(0xF065, &mut keypad);
+    assert_eq!(processor.take_watch_hit(), Some(Stop::IndexOverflow));
+}
+
+#[test]
+fn test_fx0a_fx29() {
+    let mut processor = Processor::new();
+    let mut keypad = get_keypad();
+
+    // Fx0A waits on the same instruction until a key is pressed
+    processor.execute_opcode(0xF30A, &mut keypad);
+    assert_eq!(processor.pc, 0x200);
+    keypad.set_key(0xB, true);
+    processor.execute_opcode(0xF30A, &mut keypad);
+    assert_eq!(processor.reg[3], 0xB);
+    assert_eq!(processor.pc, 0x200 + OPCODE_SIZE);
+
+    processor.execute_opcode(0xF329, &mut keypad);
+    assert_eq!(processor.i, 0xB * 5);
+}
+
+#[test]
+fn test_quirks() {
+    let mut processor = Processor::new();
+    let mut keypad = get_keypad();
+    processor.set_quirks(Quirks::cosmac());
+
+    processor.reg[1] = 0x81;
+    processor.reg[2] = 0x03;
+    processor.execute_opcode(0x8126, &mut keypad);
+    assert_eq!(processor.reg[1], 0x01);
+    assert_eq!(processor.reg[0xF], 1);
+
+    processor.execute_opcode(0x8121, &mut keypad);
+    assert_eq!(processor.reg[0xF], 0);
+
+    processor.i = 0x300;
+    processor.execute_opcode(0xF255, &mut keypad);
+    assert_eq!(processor.i, 0x303);
+
+    processor.set_quirks(Quirks::schip());
+    processor.reg[2] = 0x10;
+    processor.execute_opcode(0xB220, &mut keypad);
+    assert_eq!(processor.pc, 0x230);
+}
+
+#[test]
+fn test_wrap_sprites() {
+    let mut processor = Processor::new();
+    let mut keypad = get_keypad();
+    processor.reg[0] = 62;
+    processor.reg[1] = 31;
+    // font sprite for 0 starts with 0xF0
+    processor.i = 0;
+
+    processor.execute_opcode(0xD012, &mut keypad);
+    assert!(processor.vram.pixel(63, 31));
+    assert!(!processor.vram.pixel(0, 0));
+
+    processor.vram.clear();
+    processor.set_quirks(Quirks::xochip());
+    processor.execute_opcode(0xD012, &mut keypad);
+    assert!(processor.vram.pixel(63, 31));
+    assert!(processor.vram.pixel(0, 31));
+    // second row of the sprite is 0x90
+    assert!(processor.vram.pixel(62, 0));
+    assert!(processor.vram.pixel(1, 0));
+}
+
+#[test]
+fn test_stack_faults() {
+    let mut processor = Processor::new();
+    let mut keypad = get_keypad();
+
+    // returning with an empty stack doesn't panic
+    processor.execute_opcode(0x00EE, &mut keypad);
+    assert_eq!(processor.take_watch_hit(), Some(Stop::StackUnderflow));
+    assert_eq!(processor.sp, 0);
+    assert_eq!(processor.pc, 0x200 + OPCODE_SIZE);
+
+    processor.set_stack_depth_limit(Some(2));
+    processor.execute_opcode(0x2300, &mut keypad);
+    processor.execute_opcode(0x2300, &mut keypad);
+    assert_eq!(processor.take_watch_hit(), None);
+    processor.execute_opcode(0x2300, &mut keypad);
+    assert_eq!(processor.take_watch_hit(), Some(Stop::StackDepth(3)));
+    assert_eq!(processor.stack(), &[0x204, 0x302, 0x302]);
+
+    processor.sp = STACK_SIZE;
+    processor.execute_opcode(0x2300, &mut keypad);
+    assert_eq!(processor.take_watch_hit(), Some(Stop::StackOverflow));
+    assert_eq!(processor.sp, STACK_SIZE);
+}
+
+#[test]
+fn test_reset() {
+    let mut processor = Processor::new();
+    let mut keypad = get_keypad();
+    // LD V0, 5; LD DT, V0; CALL 206; RET
+    processor.load(&[0x60, 0x05, 0xF0, 0x15, 0x22, 0x06, 0x00, 0xEE]);
+    for _ in 0..3 {
+        processor.step(&mut keypad);
+    }
+    processor.ram[0x300] = 0xFF;
+    processor.ram[0x000] = 0xAA;
+    processor.vram.set(0, 0, true);
+    assert_eq!(processor.sp, 1);
+
+    processor.reset();
+    assert_eq!(processor.pc, 0x200);
+    assert_eq!(processor.sp, 0);
+    assert_eq!(processor.reg[0], 0);
+    assert_eq!(processor.delay_timer, 0);
+    assert!(!processor.vram.pixel(0, 0));
+    assert_eq!(processor.ram[0x300], 0);
+    assert_eq!(processor.ram[0x000], FONTSET[0]);
+    assert_eq!(
+        processor.ram[0x200..0x208],
+        [0x60, 0x05, 0xF0, 0x15, 0x22, 0x06, 0x00, 0xEE]
+    );
+
+    processor.reload(&[0x00, 0xE0]);
+    assert_eq!(processor.ram[0x200..0x204], [0x00, 0xE0, 0x00, 0x00]);
+}
+
+#[test]
+fn test_load_oversize_rom() {
+    // cut off at the end of RAM instead of panicking
+    let rom = vec![0xAB; 4000];
+    let mut processor = Processor::new();
+    processor.load(&rom);
+    assert_eq!(processor.ram[CHIP8_RAM - 1], 0xAB);
+    processor.reset();
+    assert_eq!(processor.ram[CHIP8_RAM - 1], 0xAB);
+    processor.reload(&rom);
+    assert_eq!(processor.rom.len(), CHIP8_ROM_SIZE);
+    assert_eq!(processor.ram[CHIP8_RAM - 1], 0xAB);
+}
+
+#[test]
+fn test_fx75_fx85() {
+    let store = MemoryFlagStore::new();
+    let mut keypad = get_keypad();
+    // LD V0, 1; LD V1, 2; LD V2, 3; LD R, V1; LD V2, R
+    let rom = [0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0xF1, 0x75, 0xF2, 0x85];
+    let mut processor = Processor::new();
+    processor.set_flag_store(Some(Box::new(store.clone())));
+    processor.load(&rom);
+    for _ in 0..5 {
+        processor.step(&mut keypad);
+    }
+    assert_eq!(processor.reg[..3], [1, 2, 0]);
+    assert_eq!(processor.rpl_flags()[..3], [1, 2, 0]);
+    let key = RomDatabase::sha1(&rom);
+    assert_eq!(store.get(&key).unwrap()[..3], [1, 2, 0]);
+
+    // the flags outlive resets and are loaded for the next run of the same ROM
+    processor.reset();
+    assert_eq!(processor.rpl_flags()[..2], [1, 2]);
+    let mut processor = Processor::new();
+    processor.load(&rom);
+    assert_eq!(processor.rpl_flags()[..2], [0, 0]);
+    processor.set_flag_store(Some(Box::new(store.clone())));
+    assert_eq!(processor.rpl_flags()[..2], [1, 2]);
+    processor.reload(&[0x00, 0xE0]);
+    assert_eq!(processor.rpl_flags()[..2], [0, 0]);
+}
+
+// Dxyn pixel by pixel, the way the row based version has to behave.
+fn draw_pixels(vram: &mut Framebuffer, sprite: &[u8], x: usize, y: usize, wrap: bool) -> u8 {
+    let mut collision = 0;
+    for (dy, &data) in sprite.iter().enumerate() {
+        for dx in 0..8 {
+            let (sx, sy) = (x % CHIP8_SCREEN_WIDTH + dx, y % CHIP8_SCREEN_HEIGHT + dy);
+            let on_screen = sx < CHIP8_SCREEN_WIDTH && sy < CHIP8_SCREEN_HEIGHT;
+            if data & (0x80 >> dx) == 0 || !(on_screen || wrap) {
+                continue;
+            }
+            if vram.toggle(sx % CHIP8_SCREEN_WIDTH, sy % CHIP8_SCREEN_HEIGHT) {
+                collision = 1;
+            }
+        }
+    }
+    collision
+}
+
+#[test]
+fn test_op_d_rows() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut keypad = get_keypad();
+    for quirks in [Quirks::default(), Quirks::xochip()] {
+        let mut processor = Processor::new();
+        processor.set_quirks(quirks);
+        let mut expected = Framebuffer::new();
+        for _ in 0..500 {
+            let n = rng.gen_range(1..16);
+            let sprite: Vec<u8> = (0..n).map(|_| rng.gen()).collect();
+            let (x, y) = (rng.gen::<u8>(), rng.gen::<u8>());
+            processor.ram[0x300..0x300 + n].copy_from_slice(&sprite);
+            processor.i = 0x300;
+            processor.reg[1] = x;
+            processor.reg[2] = y;
+            processor.execute_opcode(0xD120 | n as u16, &mut keypad);
+
+            let wrap = quirks.wrap_sprites;
+            let collision = draw_pixels(&mut expected, &sprite, x as usize, y as usize, wrap);
+            assert_eq!(processor.vram, expected);
+            assert_eq!(processor.reg[0xF], collision);
+        }
+    }
+}
+
+// Random programs in 0x200 - 0x2FF that only jump inside that range and only write to RAM
+// from 0x300 on (or the font), so they can't run off the end of RAM.
+fn random_program(rng: &mut StdRng) -> Vec<u8> {
+    let mut program = Vec::new();
+    for _ in 0..126 {
+        let (x, y, kk) = (
+            rng.gen_range(0..16u16),
+            rng.gen_range(0..16u16),
+            rng.gen::<u8>(),
+        );
+        let addr = 0x200 + rng.gen_range(0..128u16) * 2;
+        let data = 0x300 + rng.gen_range(0..0xF0u16);
+        let opcode = match rng.gen_range(0..22) {
+            0 => 0x6000 | x << 8 | kk as u16,
+            1 => 0x7000 | x << 8 | kk as u16,
+            2 => 0x8000 | x << 8 | y << 4 | [0, 1, 2, 3, 4, 5, 6, 7, 0xE][rng.gen_range(0..9)],
+            3 => 0xA000 | data,
+            4 => 0xC000 | x << 8 | kk as u16,
+            5 => 0xD000 | x << 8 | y << 4 | rng.gen_range(0..16u16),
+            6 => 0x3000 | x << 8 | kk as u16,
+            7 => 0x4000 | x << 8 | kk as u16,
+            8 => 0x5000 | x << 8 | y << 4,
+            9 => 0x9000 | x << 8 | y << 4,
+            10 => 0x1000 | addr,
+            11 => 0x2000 | addr,
+            12 => 0x00EE,
+            13 => 0x00E0,
+            14 => 0xE09E | x << 8,
+            15 => 0xF007 | x << 8,
+            16 => 0xF015 | x << 8,
+            17 => 0xF029 | x << 8,
+            18 => 0xF033 | x << 8,
+            19 => 0xF055 | x << 8,
+            20 => 0xF065 | x << 8,
+            _ => 0x8000 | x << 8 | y << 4 | 4,
+        };
+        program.extend_from_slice(&opcode.to_be_bytes());
+    }
+    // JP 200 twice, so that a skip right before the end lands on one too
+    program.extend_from_slice(&[0x12, 0x00, 0x12, 0x00]);
+    program
+}
+
+fn run_both(program: &[u8], quirks: Quirks, seed: u64, frames: usize) -> (Processor, Processor) {
+    let mut keypad = get_keypad();
+    keypad.set_key(3, true);
+    let mut run = |blocks: bool| {
+        let mut processor = Processor::new();
+        processor.set_quirks(quirks);
+        processor.set_block_cache(blocks);
+        processor.seed_rng(seed);
+        processor.load(program);
+        let changed: Vec<bool> = (0..frames)
+            .map(|_| processor.run_frame(&mut keypad, 7))
+            .collect();
+        (processor, changed)
+    };
+    let (interpreted, changed) = run(false);
+    let (compiled, compiled_changed) = run(true);
+    assert_eq!(compiled_changed, changed);
+    (interpreted, compiled)
+}
+
+#[test]
+fn test_block_cache_matches_interpreter() {
+    let mut rng = StdRng::seed_from_u64(2);
+    for n in 0..200 {
+        let program = random_program(&mut rng);
+        let quirks = [Quirks::default(), Quirks::cosmac(), Quirks::xochip()][n % 3];
+        let (interpreted, compiled) = run_both(&program, quirks, n as u64, 30);
+        assert_eq!(
+            compiled.save_state(),
+            interpreted.save_state(),
+            "program {}",
+            n
+        );
+        assert!(!compiled.block_cache().unwrap().is_empty());
+    }
+}
+
+#[test]
+fn test_block_cache_self_modifying_code() {
+    // LD I, 208; LD V0, 0x70; LD V1, 0x01; LD [I], V1; LD V2, 5; JP 206
+    // The store turns `LD V2, 5` into `ADD V0, 1` and jumps back to the store.
+    let program = [
+        0xA2, 0x08, 0x60, 0x70, 0x61, 0x01, 0xF1, 0x55, 0x62, 0x05, 0x12, 0x06,
+    ];
+    let (interpreted, compiled) = run_both(&program, Quirks::default(), 0, 3);
+    assert_eq!(compiled.save_state(), interpreted.save_state());
+    assert_eq!(compiled.ram[0x208], 0x71);
+    assert_eq!(compiled.reg[0], 0x71);
+    assert_eq!(compiled.reg[2], 0);
 }
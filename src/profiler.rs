@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::disassembler::disassemble;
+use crate::CHIP8_RAM;
+
+// Counts where the time goes: executions per address and per instruction class (first
+// nibble), time spent blocked in Fx0A and DRW calls per frame.
+
+const HOT_SPOTS: usize = 20;
+
+pub struct Profiler {
+    pc_counts: Vec<u64>,
+    // opcode at each address the last time it ran
+    opcodes: Vec<u16>,
+    class_counts: [u64; 16],
+    cycles: u64,
+    key_wait: Duration,
+    key_wait_cycles: u64,
+    draws: u32,
+    draws_per_frame: Vec<u32>,
+}
+
+#[derive(Serialize)]
+struct HotSpot {
+    addr: usize,
+    count: u64,
+    instruction: String,
+}
+
+#[derive(Serialize)]
+struct Summary {
+    cycles: u64,
+    // "0" to "F"
+    classes: BTreeMap<String, u64>,
+    hot_spots: Vec<HotSpot>,
+    key_wait_ms: u128,
+    key_wait_cycles: u64,
+    frames: usize,
+    draws_per_frame: Vec<u32>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            pc_counts: vec![0; CHIP8_RAM],
+            opcodes: vec![0; CHIP8_RAM],
+            class_counts: [0; 16],
+            cycles: 0,
+            key_wait: Duration::ZERO,
+            key_wait_cycles: 0,
+            draws: 0,
+            draws_per_frame: Vec::new(),
+        }
+    }
+
+    // `elapsed` is how long the instruction took, only Fx0A takes noticeably long.
+    pub fn record(&mut self, pc: usize, opcode: u16, elapsed: Duration) {
+        self.cycles += 1;
+        self.pc_counts[pc] += 1;
+        self.opcodes[pc] = opcode;
+        self.class_counts[(opcode >> 12) as usize] += 1;
+        match opcode & 0xF0FF {
+            0xF00A => {
+                self.key_wait += elapsed;
+                self.key_wait_cycles += 1;
+            }
+            _ if opcode & 0xF000 == 0xD000 => self.draws += 1,
+            _ => {}
+        }
+    }
+
+    // Called by the frontend every 60th of a second.
+    pub fn end_frame(&mut self) {
+        self.draws_per_frame.push(self.draws);
+        self.draws = 0;
+    }
+
+    pub fn count(&self, pc: usize) -> u64 {
+        self.pc_counts[pc]
+    }
+
+    pub fn class_count(&self, class: u8) -> u64 {
+        self.class_counts[class as usize]
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn key_wait(&self) -> Duration {
+        self.key_wait
+    }
+
+    pub fn draws_per_frame(&self) -> &[u32] {
+        &self.draws_per_frame
+    }
+
+    // Most executed addresses first.
+    fn hot_spots(&self) -> Vec<HotSpot> {
+        let mut spots: Vec<HotSpot> = self
+            .pc_counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(addr, &count)| HotSpot {
+                addr,
+                count,
+                instruction: disassemble(self.opcodes[addr]),
+            })
+            .collect();
+        spots.sort_by(|a, b| b.count.cmp(&a.count).then(a.addr.cmp(&b.addr)));
+        spots
+    }
+
+    fn summary(&self) -> Summary {
+        Summary {
+            cycles: self.cycles,
+            classes: (0..16)
+                .map(|class| (format!("{:X}", class), self.class_counts[class]))
+                .collect(),
+            hot_spots: self.hot_spots(),
+            key_wait_ms: self.key_wait.as_millis(),
+            key_wait_cycles: self.key_wait_cycles,
+            frames: self.draws_per_frame.len(),
+            draws_per_frame: self.draws_per_frame.clone(),
+        }
+    }
+
+    pub fn write_report(&self, out: &mut impl Write) -> io::Result<()> {
+        let percent = |count: u64| 100.0 * count as f64 / self.cycles.max(1) as f64;
+        writeln!(out, "Cycles: {}", self.cycles)?;
+
+        writeln!(out, "\nHot spots:")?;
+        for spot in self.hot_spots().iter().take(HOT_SPOTS) {
+            // Fx07 near the top usually is a busy-wait on the delay timer
+            let note = if self.opcodes[spot.addr] & 0xF0FF == 0xF007 {
+                "  <- delay timer poll"
+            } else {
+                ""
+            };
+            writeln!(
+                out,
+                "  {:03X}  {:<18} {:>10} {:5.1}%{}",
+                spot.addr,
+                spot.instruction,
+                spot.count,
+                percent(spot.count),
+                note
+            )?;
+        }
+
+        writeln!(out, "\nInstruction classes:")?;
+        let mut classes: Vec<usize> = (0..16).filter(|&c| self.class_counts[c] > 0).collect();
+        classes.sort_by(|&a, &b| self.class_counts[b].cmp(&self.class_counts[a]));
+        for class in classes {
+            let count = self.class_counts[class];
+            writeln!(
+                out,
+                "  {:X}xxx {:>10} {:5.1}%",
+                class,
+                count,
+                percent(count)
+            )?;
+        }
+
+        writeln!(
+            out,
+            "\nWaiting for keys (Fx0A): {} ms over {} cycles",
+            self.key_wait.as_millis(),
+            self.key_wait_cycles
+        )?;
+        let frames = &self.draws_per_frame;
+        if !frames.is_empty() {
+            let total: u64 = frames.iter().map(|&draws| draws as u64).sum();
+            writeln!(
+                out,
+                "DRW per frame: {:.1} average, {} max over {} frames",
+                total as f64 / frames.len() as f64,
+                frames.iter().max().unwrap_or(&0),
+                frames.len()
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn write_json(&self, out: &mut impl Write) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut *out, &self.summary())?;
+        writeln!(out)
+    }
+}
+
+#[cfg(test)]
+#[path = "./profiler_test.rs"]
+mod profiler_test;
@@ -0,0 +1,36 @@
+use super::*;
+use crate::{HeadlessKeypad, Processor};
+
+#[test]
+fn test_profile_loop() {
+    let mut processor = Processor::new();
+    let mut keypad = HeadlessKeypad::new();
+    // 200: LD V0, DT / 202: SE V0, 0 / 204: JP 200 / 206: DRW V0, V0, 1 / 208: JP 208
+    processor.load(&[0xF0, 0x07, 0x30, 0x00, 0x12, 0x00, 0xD0, 0x01, 0x12, 0x08]);
+    processor.set_delay_timer(3);
+    processor.set_profiler(Some(Profiler::new()));
+    for _ in 0..10 {
+        processor.emulate_cycle(&mut keypad);
+    }
+    let profiler = processor.profiler_mut().unwrap();
+    profiler.end_frame();
+
+    assert_eq!(profiler.cycles(), 10);
+    assert_eq!(profiler.count(0x200), 2);
+    assert_eq!(profiler.count(0x208), 4);
+    assert_eq!(profiler.class_count(0x1), 5);
+    assert_eq!(profiler.draws_per_frame(), &[1]);
+
+    let mut report = Vec::new();
+    profiler.write_report(&mut report).unwrap();
+    let report = String::from_utf8(report).unwrap();
+    assert!(report.contains("  208  JP 0x208                    4  40.0%"));
+    assert!(report.contains("LD V0, DT                   2  20.0%  <- delay timer poll"));
+
+    let mut json = Vec::new();
+    profiler.write_json(&mut json).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(json["cycles"], 10);
+    assert_eq!(json["classes"]["F"], 2);
+    assert_eq!(json["hot_spots"][0]["addr"], 0x208);
+}
@@ -0,0 +1,74 @@
+// Behaviours that differ between CHIP-8 interpreters.
+// https://github.com/Timendus/chip8-test-suite#quirks-test
+//
+// The default is how this emulator always behaved and is what most modern ROMs expect.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Quirks {
+    // 8xy1, 8xy2 and 8xy3 reset VF to 0.
+    pub vf_reset: bool,
+    // 8xy6 and 8xyE shift Vy into Vx instead of shifting Vx in place.
+    pub shift_vy: bool,
+    // Fx55 and Fx65 leave I pointing past the last register they touched.
+    pub increment_i: bool,
+    // Bxnn jumps to xnn + Vx instead of Bnnn jumping to nnn + V0.
+    pub jump_vx: bool,
+    // Sprites wrap around the edges of the screen instead of being clipped.
+    pub wrap_sprites: bool,
+}
+
+impl Quirks {
+    pub const PROFILES: [&'static str; 4] = ["default", "cosmac", "schip", "xochip"];
+
+    // Original COSMAC VIP interpreter.
+    pub fn cosmac() -> Self {
+        Quirks {
+            vf_reset: true,
+            shift_vy: true,
+            increment_i: true,
+            jump_vx: false,
+            wrap_sprites: false,
+        }
+    }
+
+    // SUPER-CHIP 1.1 on the HP-48.
+    pub fn schip() -> Self {
+        Quirks {
+            vf_reset: false,
+            shift_vy: false,
+            increment_i: false,
+            jump_vx: true,
+            wrap_sprites: false,
+        }
+    }
+
+    // XO-CHIP as implemented by Octo.
+    pub fn xochip() -> Self {
+        Quirks {
+            vf_reset: false,
+            shift_vy: true,
+            increment_i: true,
+            jump_vx: false,
+            wrap_sprites: true,
+        }
+    }
+
+    // Platform by file extension: `.ch8`, `.sc8` (SUPER-CHIP) or `.xo8` (XO-CHIP).
+    pub fn for_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "ch8" => Some(Quirks::default()),
+            "sc8" => Some(Quirks::schip()),
+            "xo8" => Some(Quirks::xochip()),
+            _ => None,
+        }
+    }
+
+    pub fn profile(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Quirks::default()),
+            "cosmac" | "chip8" | "vip" => Some(Quirks::cosmac()),
+            "schip" | "superchip" => Some(Quirks::schip()),
+            "xochip" => Some(Quirks::xochip()),
+            _ => None,
+        }
+    }
+}
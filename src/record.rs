@@ -0,0 +1,260 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use gif::{Encoder, Frame, Repeat};
+
+use crate::framebuffer::Framebuffer;
+use crate::{CHIP8_SCREEN_HEIGHT, CHIP8_SCREEN_WIDTH};
+
+// Records the screen once per 60 Hz frame, either as an animated GIF or as a video stream
+// with a WAV of the beeper next to it (`out.y4m` -> `out.wav`):
+// * `.gif`: only frames that differ from the previous one are stored, each shown for as long
+//   as it was on screen (rounded to the 1/100 s GIF delays)
+// * `.y4m`: YUV4MPEG2, plays in most video players and is accepted by ffmpeg
+// * `.raw`: bare RGB24 frames, `ffmpeg -f rawvideo -pixel_format rgb24 -video_size WxH
+//   -framerate 60 -i out.raw`
+// Nothing here needs SDL so headless runs can record too.
+
+pub const FRAME_RATE: u64 = 60;
+pub const SAMPLE_RATE: u32 = 44100;
+const SAMPLES_PER_FRAME: u32 = SAMPLE_RATE / FRAME_RATE as u32;
+const BEEP_FREQUENCY: u32 = 440;
+
+enum Video {
+    Gif {
+        encoder: Encoder<BufWriter<File>>,
+        // frame waiting for the next change to know how long it was shown, and the frame
+        // number it first appeared on
+        pending: Option<(Vec<u8>, u64)>,
+    },
+    Y4m(BufWriter<File>),
+    Raw(BufWriter<File>),
+}
+
+pub struct Recorder {
+    path: PathBuf,
+    video: Video,
+    audio: Option<Wav>,
+    // background and foreground as 0xRRGGBB
+    palette: [u32; 2],
+    scale: usize,
+    frames: u64,
+}
+
+impl Recorder {
+    // The format follows the extension of `path`, `scale` enlarges every pixel to a square
+    // of that size.
+    pub fn create(path: impl AsRef<Path>, palette: (u32, u32), scale: usize) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let scale = scale.max(1);
+        let (width, height) = (CHIP8_SCREEN_WIDTH * scale, CHIP8_SCREEN_HEIGHT * scale);
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        let file = || File::create(&path).map(BufWriter::new);
+
+        let video = match extension.to_ascii_lowercase().as_str() {
+            "gif" => {
+                let colors: Vec<u8> = [palette.0, palette.1]
+                    .iter()
+                    .flat_map(|&color| Recorder::rgb(color))
+                    .collect();
+                let mut encoder = Encoder::new(file()?, width as u16, height as u16, &colors)
+                    .map_err(io::Error::other)?;
+                encoder
+                    .set_repeat(Repeat::Infinite)
+                    .map_err(io::Error::other)?;
+                Video::Gif {
+                    encoder,
+                    pending: None,
+                }
+            }
+            "y4m" => {
+                let mut out = file()?;
+                writeln!(
+                    out,
+                    "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+                    width, height, FRAME_RATE
+                )?;
+                Video::Y4m(out)
+            }
+            "raw" => Video::Raw(file()?),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Unknown recording format {}, expected .gif, .y4m or .raw",
+                        path.display()
+                    ),
+                ))
+            }
+        };
+        let audio = match video {
+            Video::Gif { .. } => None,
+            _ => Some(Wav::create(&path.with_extension("wav"))?),
+        };
+
+        Ok(Recorder {
+            path,
+            video,
+            audio,
+            palette: [palette.0, palette.1],
+            scale,
+            frames: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    // Called once per 60 Hz frame, `beeping` while the sound timer is running.
+    pub fn frame(&mut self, vram: &Framebuffer, beeping: bool) -> io::Result<()> {
+        let pixels = self.scaled(vram);
+        match &mut self.video {
+            Video::Gif { encoder, pending } => {
+                if !matches!(pending, Some((last, _)) if *last == pixels) {
+                    if let Some((last, start)) = pending.take() {
+                        Recorder::write_gif_frame(encoder, self.scale, last, start, self.frames)?;
+                    }
+                    *pending = Some((pixels, self.frames));
+                }
+            }
+            Video::Y4m(out) => {
+                writeln!(out, "FRAME")?;
+                let [background, foreground] = self.palette.map(Recorder::yuv);
+                for component in 0..3 {
+                    let plane = [background[component], foreground[component]];
+                    let plane: Vec<u8> = pixels.iter().map(|&p| plane[p as usize]).collect();
+                    out.write_all(&plane)?;
+                }
+            }
+            Video::Raw(out) => {
+                let colors = self.palette.map(Recorder::rgb);
+                let frame: Vec<u8> = pixels.iter().flat_map(|&p| colors[p as usize]).collect();
+                out.write_all(&frame)?;
+            }
+        }
+        if let Some(audio) = self.audio.as_mut() {
+            audio.write_frame(beeping)?;
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    // Writes what's still buffered and completes the headers.
+    pub fn finish(mut self) -> io::Result<()> {
+        match &mut self.video {
+            Video::Gif { encoder, pending } => {
+                if let Some((last, start)) = pending.take() {
+                    Recorder::write_gif_frame(encoder, self.scale, last, start, self.frames)?;
+                }
+                encoder.get_mut().flush()?;
+            }
+            Video::Y4m(out) | Video::Raw(out) => out.flush()?,
+        }
+        match self.audio.take() {
+            Some(audio) => audio.finish(),
+            None => Ok(()),
+        }
+    }
+
+    // Palette indices, one byte per output pixel.
+    fn scaled(&self, vram: &Framebuffer) -> Vec<u8> {
+        let mut pixels =
+            Vec::with_capacity(CHIP8_SCREEN_WIDTH * CHIP8_SCREEN_HEIGHT * self.scale * self.scale);
+        for y in 0..CHIP8_SCREEN_HEIGHT {
+            let line: Vec<u8> = (0..CHIP8_SCREEN_WIDTH)
+                .flat_map(|x| std::iter::repeat_n(vram.pixel(x, y) as u8, self.scale))
+                .collect();
+            for _ in 0..self.scale {
+                pixels.extend_from_slice(&line);
+            }
+        }
+        pixels
+    }
+
+    fn write_gif_frame(
+        encoder: &mut Encoder<BufWriter<File>>,
+        scale: usize,
+        pixels: Vec<u8>,
+        start: u64,
+        end: u64,
+    ) -> io::Result<()> {
+        // delays are in 1/100 s, rounding the start and end keeps the total in step with 60 Hz
+        let centis = |frame: u64| (frame * 100 + FRAME_RATE / 2) / FRAME_RATE;
+        let frame = Frame {
+            width: (CHIP8_SCREEN_WIDTH * scale) as u16,
+            height: (CHIP8_SCREEN_HEIGHT * scale) as u16,
+            delay: (centis(end) - centis(start)).max(1) as u16,
+            buffer: Cow::Owned(pixels),
+            ..Frame::default()
+        };
+        encoder.write_frame(&frame).map_err(io::Error::other)
+    }
+
+    fn rgb(color: u32) -> [u8; 3] {
+        [(color >> 16) as u8, (color >> 8) as u8, color as u8]
+    }
+
+    // BT.601 studio range
+    fn yuv(color: u32) -> [u8; 3] {
+        let [r, g, b] = Recorder::rgb(color).map(|c| c as f64);
+        let y = 16.0 + 0.257 * r + 0.504 * g + 0.098 * b;
+        let u = 128.0 - 0.148 * r - 0.291 * g + 0.439 * b;
+        let v = 128.0 + 0.439 * r - 0.368 * g - 0.071 * b;
+        [y, u, v].map(|c| c.round().clamp(0.0, 255.0) as u8)
+    }
+}
+
+// 8-bit mono PCM, the beeper as a square wave.
+struct Wav {
+    out: BufWriter<File>,
+    samples: u32,
+}
+
+impl Wav {
+    fn create(path: &Path) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        // the sizes are filled in by `finish`
+        out.write_all(b"RIFF\0\0\0\0WAVEfmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?; // PCM
+        out.write_all(&1u16.to_le_bytes())?; // mono
+        out.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        out.write_all(&SAMPLE_RATE.to_le_bytes())?; // bytes per second
+        out.write_all(&1u16.to_le_bytes())?; // bytes per sample
+        out.write_all(&8u16.to_le_bytes())?; // bits per sample
+        out.write_all(b"data\0\0\0\0")?;
+        Ok(Wav { out, samples: 0 })
+    }
+
+    fn write_frame(&mut self, beeping: bool) -> io::Result<()> {
+        let half_period = SAMPLE_RATE / BEEP_FREQUENCY / 2;
+        let frame: Vec<u8> = (self.samples..self.samples + SAMPLES_PER_FRAME)
+            .map(|sample| match (beeping, (sample / half_period) % 2) {
+                (false, _) => 0x80,
+                (true, 0) => 0xA0,
+                (true, _) => 0x60,
+            })
+            .collect();
+        self.samples += SAMPLES_PER_FRAME;
+        self.out.write_all(&frame)
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&(36 + self.samples).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&self.samples.to_le_bytes())?;
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+#[path = "./record_test.rs"]
+mod record_test;
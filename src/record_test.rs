@@ -0,0 +1,74 @@
+use super::*;
+use std::fs;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("chip8-record-{}-{}", std::process::id(), name))
+}
+
+fn screens() -> (Framebuffer, Framebuffer) {
+    let blank = Framebuffer::new();
+    let mut dot = blank;
+    dot.set(2, 1, true);
+    (blank, dot)
+}
+
+#[test]
+fn test_gif() {
+    let path = temp_path("test.gif");
+    let (blank, dot) = screens();
+    let mut recorder = Recorder::create(&path, (0x000000, 0xFFFFFF), 2).unwrap();
+    // 3 frames blank, 3 with a dot and the blank screen again
+    for vram in [&blank, &blank, &blank, &dot, &dot, &dot, &blank] {
+        recorder.frame(vram, false).unwrap();
+    }
+    recorder.finish().unwrap();
+
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(File::open(&path).unwrap()).unwrap();
+    assert_eq!((decoder.width(), decoder.height()), (128, 64));
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        frames.push((
+            frame.delay,
+            frame.buffer.iter().filter(|&&p| p == 1).count(),
+        ));
+    }
+    // 0.05 s each for the first two and the rest of the 7/60 s for the last one
+    assert_eq!(frames, vec![(5, 0), (5, 4), (2, 0)]);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_y4m_and_wav() {
+    let path = temp_path("test.y4m");
+    let (blank, dot) = screens();
+    let mut recorder = Recorder::create(&path, (0x000000, 0xFFFFFF), 1).unwrap();
+    recorder.frame(&blank, false).unwrap();
+    recorder.frame(&dot, true).unwrap();
+    recorder.finish().unwrap();
+
+    let video = fs::read(&path).unwrap();
+    let header = b"YUV4MPEG2 W64 H32 F60:1 Ip A1:1 C444\n";
+    assert!(video.starts_with(header));
+    let frame_size = b"FRAME\n".len() + 3 * 64 * 32;
+    assert_eq!(video.len(), header.len() + 2 * frame_size);
+    // Y of the lit pixel in the second frame
+    let second = header.len() + frame_size + b"FRAME\n".len();
+    assert_eq!(video[second + 64 + 2], 235);
+    assert_eq!(video[second], 16);
+
+    let wav_path = path.with_extension("wav");
+    let wav = fs::read(&wav_path).unwrap();
+    assert_eq!(wav.len(), 44 + 2 * 735);
+    assert_eq!(&wav[40..44], &(2u32 * 735).to_le_bytes());
+    assert!(wav[44..44 + 735].iter().all(|&sample| sample == 0x80));
+    assert!(wav[44 + 735..].contains(&0xA0));
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&wav_path).unwrap();
+}
+
+#[test]
+fn test_unknown_format() {
+    assert!(Recorder::create(temp_path("test.mp4"), (0, 0), 1).is_err());
+}
@@ -0,0 +1,141 @@
+use std::collections::VecDeque;
+
+use crate::Processor;
+
+// Keeps the most recent save state in full and every older one as a delta against the
+// snapshot that followed it. A delta is the XOR of both snapshots stored as runs of
+// `[skip: u16][len: u16][len bytes]`, so unchanged RAM costs next to nothing.
+pub struct Rewind {
+    interval: usize,
+    capacity: usize,
+    frames: usize,
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl Rewind {
+    // Takes a snapshot every `interval` frames and keeps at most `capacity` of them.
+    pub fn new(interval: usize, capacity: usize) -> Self {
+        Rewind {
+            interval: interval.max(1),
+            capacity: capacity.max(1),
+            frames: 0,
+            latest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    // Number of snapshots that can still be rewound to.
+    pub fn len(&self) -> usize {
+        self.latest.as_ref().map_or(0, |_| self.deltas.len() + 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames = 0;
+        self.latest = None;
+        self.deltas.clear();
+    }
+
+    // Call once per emulated frame.
+    pub fn capture(&mut self, processor: &Processor) {
+        self.frames += 1;
+        if self.latest.is_some() && self.frames < self.interval {
+            return;
+        }
+        self.frames = 0;
+
+        let snapshot = processor.save_state();
+        if let Some(latest) = self.latest.take() {
+            self.deltas
+                .push_back(Rewind::encode_delta(&latest, &snapshot));
+            if self.deltas.len() >= self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.latest = Some(snapshot);
+    }
+
+    // Restores the previous snapshot. Returns false when there is nothing left to rewind to.
+    pub fn step_back(&mut self, processor: &mut Processor) -> Result<bool, String> {
+        let latest = match self.latest.as_mut() {
+            Some(latest) => latest,
+            None => return Ok(false),
+        };
+
+        // frames ran since the last snapshot, so going back to it is a step on its own
+        if self.frames == 0 {
+            match self.deltas.pop_back() {
+                Some(delta) => Rewind::apply_delta(latest, &delta),
+                None => return Ok(false),
+            }
+        }
+        self.frames = 0;
+        processor.load_state(latest)?;
+        Ok(true)
+    }
+
+    // Rewinds by roughly `frames` frames, rounded up to the snapshot interval.
+    // Returns how many frames were actually rewound.
+    pub fn rewind(&mut self, processor: &mut Processor, frames: usize) -> Result<usize, String> {
+        let mut rewound = 0;
+        while rewound < frames {
+            let step = if self.frames == 0 {
+                self.interval
+            } else {
+                self.frames
+            };
+            if !self.step_back(processor)? {
+                break;
+            }
+            rewound += step;
+        }
+        Ok(rewound)
+    }
+
+    fn encode_delta(old: &[u8], new: &[u8]) -> Vec<u8> {
+        let mut delta = Vec::new();
+        let mut pos = 0;
+        while pos < old.len() {
+            let start = pos;
+            while pos < old.len() && old[pos] == new[pos] && pos - start < u16::MAX as usize {
+                pos += 1;
+            }
+            let skip = pos - start;
+
+            let start = pos;
+            while pos < old.len() && old[pos] != new[pos] && pos - start < u16::MAX as usize {
+                pos += 1;
+            }
+            if pos == start && pos == old.len() {
+                break;
+            }
+            delta.extend_from_slice(&(skip as u16).to_le_bytes());
+            delta.extend_from_slice(&((pos - start) as u16).to_le_bytes());
+            delta.extend((start..pos).map(|i| old[i] ^ new[i]));
+        }
+        delta
+    }
+
+    fn apply_delta(snapshot: &mut [u8], delta: &[u8]) {
+        let mut pos = 0;
+        let mut delta = delta;
+        while delta.len() >= 4 {
+            let skip = u16::from_le_bytes([delta[0], delta[1]]) as usize;
+            let len = u16::from_le_bytes([delta[2], delta[3]]) as usize;
+            pos += skip;
+            for (byte, &diff) in snapshot[pos..pos + len].iter_mut().zip(&delta[4..4 + len]) {
+                *byte ^= diff;
+            }
+            pos += len;
+            delta = &delta[4 + len..];
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./rewind_test.rs"]
+mod rewind_test;
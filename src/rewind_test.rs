@@ -0,0 +1,96 @@
+use super::*;
+
+// Runs one "frame" that leaves a recognizable trace in V0 and RAM.
+fn advance(processor: &mut Processor, rewind: &mut Rewind, frame: u8) {
+    processor.set_register(0, frame);
+    processor.ram_mut()[0x300 + frame as usize] = frame;
+    rewind.capture(processor);
+}
+
+#[test]
+fn test_step_back() {
+    let mut processor = Processor::new();
+    let mut rewind = Rewind::new(1, 10);
+    for frame in 1..=5 {
+        advance(&mut processor, &mut rewind, frame);
+    }
+    assert_eq!(rewind.len(), 5);
+
+    // the latest snapshot is the current state, so the first step goes to frame 4
+    assert_eq!(rewind.step_back(&mut processor), Ok(true));
+    assert_eq!(processor.register(0), 4);
+    assert_eq!(processor.ram()[0x305], 0);
+    assert_eq!(rewind.step_back(&mut processor), Ok(true));
+    assert_eq!(processor.register(0), 3);
+    assert_eq!(processor.ram()[0x303], 3);
+    assert_eq!(processor.ram()[0x304], 0);
+
+    // continuing from a rewound state records new history
+    advance(&mut processor, &mut rewind, 9);
+    assert_eq!(rewind.step_back(&mut processor), Ok(true));
+    assert_eq!(processor.register(0), 3);
+}
+
+#[test]
+fn test_capacity() {
+    let mut processor = Processor::new();
+    let mut rewind = Rewind::new(1, 3);
+    for frame in 1..=10 {
+        advance(&mut processor, &mut rewind, frame);
+    }
+    assert_eq!(rewind.len(), 3);
+
+    assert_eq!(rewind.step_back(&mut processor), Ok(true));
+    assert_eq!(rewind.step_back(&mut processor), Ok(true));
+    assert_eq!(processor.register(0), 8);
+    assert_eq!(rewind.step_back(&mut processor), Ok(false));
+    assert_eq!(processor.register(0), 8);
+}
+
+#[test]
+fn test_rewind_frames() {
+    let mut processor = Processor::new();
+    let mut rewind = Rewind::new(4, 100);
+    for frame in 1..=18 {
+        advance(&mut processor, &mut rewind, frame);
+    }
+
+    // snapshots were taken at frames 1, 5, 9, 13 and 17
+    assert_eq!(rewind.rewind(&mut processor, 1), Ok(1));
+    assert_eq!(processor.register(0), 17);
+    assert_eq!(rewind.rewind(&mut processor, 5), Ok(8));
+    assert_eq!(processor.register(0), 9);
+    assert_eq!(rewind.rewind(&mut processor, 100), Ok(8));
+    assert_eq!(processor.register(0), 1);
+    assert!(!rewind.is_empty());
+}
+
+#[test]
+fn test_index_past_ram() {
+    let mut processor = Processor::new();
+    let mut rewind = Rewind::new(1, 10);
+    processor.set_i(0x14EB);
+    rewind.capture(&processor);
+    processor.set_i(0x300);
+    rewind.capture(&processor);
+
+    assert_eq!(rewind.step_back(&mut processor), Ok(true));
+    assert_eq!(processor.i(), 0x14EB);
+}
+
+#[test]
+fn test_delta() {
+    let old = vec![0u8; 100];
+    let mut new = old.clone();
+    new[3] = 1;
+    new[4] = 2;
+    new[99] = 3;
+
+    let delta = Rewind::encode_delta(&old, &new);
+    assert_eq!(delta.len(), 4 + 2 + 4 + 1);
+
+    let mut restored = new.clone();
+    Rewind::apply_delta(&mut restored, &delta);
+    assert_eq!(restored, old);
+    assert!(Rewind::encode_delta(&old, &old).is_empty());
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+
+use crate::Quirks;
+
+// Reader for the CHIP-8 community ROM database.
+// https://github.com/chip-8/chip-8-database
+//
+// The database is a directory with `programs.json`, `sha1-hashes.json` and `platforms.json`.
+
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    authors: Vec<String>,
+    roms: HashMap<String, Rom>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rom {
+    #[serde(default)]
+    platforms: Vec<String>,
+    #[serde(default)]
+    quirky_platforms: HashMap<String, QuirkFlags>,
+    tickrate: Option<u32>,
+    #[serde(default)]
+    keys: HashMap<String, u8>,
+    colors: Option<RomColors>,
+}
+
+#[derive(Deserialize)]
+struct RomColors {
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Platform {
+    id: String,
+    default_tickrate: Option<u32>,
+    #[serde(default)]
+    quirks: QuirkFlags,
+}
+
+// Quirks as named by the database, missing ones are taken from the platform.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+struct QuirkFlags {
+    shift: Option<bool>,
+    memory_increment_by_x: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    wrap: Option<bool>,
+    jump: Option<bool>,
+    logic: Option<bool>,
+}
+
+impl QuirkFlags {
+    fn apply(&self, quirks: &mut Quirks) {
+        if let Some(shift) = self.shift {
+            quirks.shift_vy = !shift;
+        }
+        // memoryIncrementByX (I += x) isn't supported, leaving I unchanged is the closest match
+        if let Some(unchanged) = self.memory_leave_i_unchanged {
+            quirks.increment_i = !unchanged && !self.memory_increment_by_x.unwrap_or(false);
+        }
+        if let Some(wrap) = self.wrap {
+            quirks.wrap_sprites = wrap;
+        }
+        if let Some(jump) = self.jump {
+            quirks.jump_vx = jump;
+        }
+        if let Some(logic) = self.logic {
+            quirks.vf_reset = logic;
+        }
+    }
+}
+
+// Everything the database knows about a ROM.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RomInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub platform: Option<String>,
+    // instructions per second, the database stores them per frame
+    pub ips: Option<u32>,
+    pub quirks: Quirks,
+    // e.g. "up" -> 0x5, "a" -> 0x6
+    pub keys: HashMap<String, u8>,
+    // background, foreground, as RGB
+    pub colors: Option<(u32, u32)>,
+}
+
+pub struct RomDatabase {
+    programs: Vec<Program>,
+    hashes: HashMap<String, usize>,
+    platforms: Vec<Platform>,
+}
+
+impl RomDatabase {
+    pub fn load(dir: &Path) -> io::Result<Self> {
+        let read = |name: &str| fs::read_to_string(dir.join(name));
+        RomDatabase::from_json(
+            &read("programs.json")?,
+            &read("sha1-hashes.json")?,
+            &read("platforms.json")?,
+        )
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn from_json(programs: &str, hashes: &str, platforms: &str) -> Result<Self, String> {
+        let parse_err = |name: &str, e: serde_json::Error| format!("Invalid {}: {}", name, e);
+        Ok(RomDatabase {
+            programs: serde_json::from_str(programs).map_err(|e| parse_err("programs", e))?,
+            hashes: serde_json::from_str(hashes).map_err(|e| parse_err("hashes", e))?,
+            platforms: serde_json::from_str(platforms).map_err(|e| parse_err("platforms", e))?,
+        })
+    }
+
+    pub fn sha1(rom: &[u8]) -> String {
+        Sha1::digest(rom)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<RomInfo> {
+        self.lookup_hash(&RomDatabase::sha1(rom))
+    }
+
+    pub fn lookup_hash(&self, hash: &str) -> Option<RomInfo> {
+        let program = self.programs.get(*self.hashes.get(hash)?)?;
+        let rom = program.roms.get(hash)?;
+        let platform_id = rom.platforms.first();
+        let platform = platform_id.and_then(|id| self.platforms.iter().find(|p| &p.id == id));
+
+        let mut quirks = Quirks::default();
+        if let Some(platform) = platform {
+            platform.quirks.apply(&mut quirks);
+        }
+        if let Some(flags) = platform_id.and_then(|id| rom.quirky_platforms.get(id)) {
+            flags.apply(&mut quirks);
+        }
+
+        let tickrate = rom
+            .tickrate
+            .or_else(|| platform.and_then(|p| p.default_tickrate));
+        let colors = rom.colors.as_ref().and_then(|colors| {
+            let background = RomDatabase::parse_color(colors.pixels.first()?)?;
+            let foreground = RomDatabase::parse_color(colors.pixels.get(1)?)?;
+            Some((background, foreground))
+        });
+
+        Some(RomInfo {
+            title: program.title.clone(),
+            authors: program.authors.clone(),
+            platform: platform_id.cloned(),
+            ips: tickrate.and_then(|tickrate| tickrate.checked_mul(60)),
+            quirks,
+            keys: rom.keys.clone(),
+            colors,
+        })
+    }
+
+    // `#rrggbb`
+    pub(crate) fn parse_color(color: &str) -> Option<u32> {
+        let hex = color.strip_prefix('#')?;
+        if hex.len() != 6 {
+            return None;
+        }
+        u32::from_str_radix(hex, 16).ok()
+    }
+}
+
+#[cfg(test)]
+#[path = "./romdb_test.rs"]
+mod romdb_test;
@@ -0,0 +1,79 @@
+use super::*;
+
+const ROM: [u8; 4] = [0x12, 0x00, 0x00, 0xE0];
+
+fn build_database() -> RomDatabase {
+    let hash = RomDatabase::sha1(&ROM);
+    let programs = format!(
+        r##"[{{
+            "title": "Test Game",
+            "authors": ["Someone"],
+            "roms": {{
+                "{}": {{
+                    "file": "test.ch8",
+                    "platforms": ["superchip"],
+                    "quirkyPlatforms": {{ "superchip": {{ "wrap": true }} }},
+                    "keys": {{ "up": 5, "a": 6 }},
+                    "colors": {{ "pixels": ["#102030", "#ffffff"] }}
+                }}
+            }}
+        }}]"##,
+        hash
+    );
+    let hashes = format!(r#"{{ "{}": 0 }}"#, hash);
+    let platforms = r#"[
+        { "id": "originalChip8", "defaultTickrate": 15,
+          "quirks": { "shift": false, "memoryLeaveIUnchanged": false, "wrap": false,
+                      "jump": false, "logic": true } },
+        { "id": "superchip", "defaultTickrate": 30,
+          "quirks": { "shift": true, "memoryLeaveIUnchanged": true, "wrap": false,
+                      "jump": true, "logic": false } }
+    ]"#;
+    RomDatabase::from_json(&programs, &hashes, platforms).unwrap()
+}
+
+#[test]
+fn test_sha1() {
+    assert_eq!(
+        RomDatabase::sha1(b"abc"),
+        "a9993e364706816aba3e25717850c26c9cd0d89d"
+    );
+}
+
+#[test]
+fn test_lookup() {
+    let info = build_database().lookup(&ROM).unwrap();
+    assert_eq!(info.title, "Test Game");
+    assert_eq!(info.authors, vec!["Someone".to_string()]);
+    assert_eq!(info.platform.as_deref(), Some("superchip"));
+    assert_eq!(info.ips, Some(30 * 60));
+    assert_eq!(info.keys.get("up"), Some(&5));
+    assert_eq!(info.colors, Some((0x102030, 0xFFFFFF)));
+
+    // platform quirks with the ROM specific wrap on top
+    let mut expected = Quirks::schip();
+    expected.wrap_sprites = true;
+    assert_eq!(info.quirks, expected);
+}
+
+#[test]
+fn test_huge_tickrate() {
+    let hash = RomDatabase::sha1(&ROM);
+    let programs = format!(
+        r#"[{{ "title": "Fast", "roms": {{ "{}": {{ "tickrate": 4294967295 }} }} }}]"#,
+        hash
+    );
+    let hashes = format!(r#"{{ "{}": 0 }}"#, hash);
+    let db = RomDatabase::from_json(&programs, &hashes, "[]").unwrap();
+    assert_eq!(db.lookup(&ROM).unwrap().ips, None);
+}
+
+#[test]
+fn test_lookup_unknown() {
+    assert_eq!(build_database().lookup(&[0x00, 0xE0]), None);
+}
+
+#[test]
+fn test_invalid_json() {
+    assert!(RomDatabase::from_json("{", "{}", "[]").is_err());
+}
@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+// Decides how many 60 Hz frames to emulate for the real time that passed. A frame is a fixed
+// number of instructions followed by one timer tick, so the timers stay in step with the
+// instruction count whatever the speed.
+//
+// Fast forward is meant to be held down and runs at `fast_forward_speed` (uncapped unless
+// set), slow motion is toggled and runs at `slow_motion_speed`. While paused nothing runs
+// except frames queued with `advance`.
+
+pub const FRAMES_PER_SECOND: f64 = 60.0;
+pub const UNCAPPED: f64 = f64::INFINITY;
+
+// catching up after a stall (e.g. in the debugger) is limited to this many frames,
+// anything beyond is dropped
+const MAX_CATCH_UP: f64 = 6.0;
+
+pub struct Scheduler {
+    paused: bool,
+    advance: u32,
+    fast_forward: bool,
+    fast_forward_speed: f64,
+    slow_motion: bool,
+    slow_motion_speed: f64,
+    // frames that are due but weren't run yet
+    owed: f64,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler::new()
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler {
+            paused: false,
+            advance: 0,
+            fast_forward: false,
+            fast_forward_speed: UNCAPPED,
+            slow_motion: false,
+            slow_motion_speed: 0.25,
+            owed: 0.0,
+        }
+    }
+
+    // Instructions per frame for a given number of instructions per second.
+    pub fn cycles_per_frame(ips: u32) -> usize {
+        ((ips as f64 / FRAMES_PER_SECOND).round() as usize).max(1)
+    }
+
+    // Number of frames to run now, `elapsed` is the real time since the last call. When
+    // uncapped this is `u32::MAX` and the frontend should stop once its time for the current
+    // frame is spent.
+    pub fn frames_due(&mut self, elapsed: Duration) -> u32 {
+        if self.paused {
+            self.owed = 0.0;
+            return std::mem::take(&mut self.advance);
+        }
+        let speed = self.speed();
+        if speed.is_infinite() {
+            self.owed = 0.0;
+            return u32::MAX;
+        }
+        self.owed += elapsed.as_secs_f64() * FRAMES_PER_SECOND * speed;
+        self.owed = self.owed.min(MAX_CATCH_UP * speed.max(1.0));
+        let frames = self.owed.floor();
+        self.owed -= frames;
+        frames as u32
+    }
+
+    // Real time until `frames_due` has another frame, for sleeping in between. None when
+    // uncapped, frontends shouldn't wait at all then. While paused this is one frame so that
+    // input is still polled.
+    pub fn next_frame_in(&self) -> Option<Duration> {
+        let frame = 1.0 / FRAMES_PER_SECOND;
+        let speed = self.speed();
+        if self.paused && self.advance > 0 {
+            Some(Duration::ZERO)
+        } else if self.paused {
+            Some(Duration::from_secs_f64(frame))
+        } else if speed.is_infinite() {
+            None
+        } else {
+            Some(Duration::from_secs_f64(
+                (1.0 - self.owed).max(0.0) * frame / speed,
+            ))
+        }
+    }
+
+    // Emulated frames per real frame right now, 0 while paused.
+    pub fn speed(&self) -> f64 {
+        match (self.paused, self.fast_forward, self.slow_motion) {
+            (true, _, _) => 0.0,
+            (false, true, _) => self.fast_forward_speed,
+            (false, false, true) => self.slow_motion_speed,
+            (false, false, false) => 1.0,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.advance = 0;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.set_paused(!self.paused);
+    }
+
+    // Runs exactly one more frame, only while paused.
+    pub fn advance(&mut self) {
+        if self.paused {
+            self.advance += 1;
+        }
+    }
+
+    pub fn is_fast_forward(&self) -> bool {
+        self.fast_forward
+    }
+
+    // Usually set on key down and cleared on key up.
+    pub fn set_fast_forward(&mut self, enabled: bool) {
+        self.fast_forward = enabled;
+    }
+
+    // A multiple of the normal speed or `UNCAPPED`.
+    pub fn set_fast_forward_speed(&mut self, speed: f64) {
+        self.fast_forward_speed = speed.max(1.0);
+    }
+
+    pub fn is_slow_motion(&self) -> bool {
+        self.slow_motion
+    }
+
+    pub fn toggle_slow_motion(&mut self) {
+        self.slow_motion = !self.slow_motion;
+    }
+
+    pub fn set_slow_motion_speed(&mut self, speed: f64) {
+        self.slow_motion_speed = speed.clamp(0.01, 1.0);
+    }
+
+    // Short description for the status line, empty when running normally.
+    pub fn label(&self) -> String {
+        let speed = self.speed();
+        if self.paused {
+            "PAUSED".to_string()
+        } else if speed.is_infinite() {
+            ">>".to_string()
+        } else if speed == 1.0 {
+            String::new()
+        } else {
+            format!("X{}", speed)
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./scheduler_test.rs"]
+mod scheduler_test;
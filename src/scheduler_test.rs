@@ -0,0 +1,84 @@
+use super::*;
+use crate::input::HeadlessKeypad;
+use crate::Processor;
+
+const FRAME: Duration = Duration::from_micros(16_667);
+
+#[test]
+fn test_speeds() {
+    let mut scheduler = Scheduler::new();
+    assert_eq!(scheduler.frames_due(FRAME * 3), 3);
+    assert_eq!(scheduler.label(), "");
+
+    // slow motion carries the fractions over
+    scheduler.toggle_slow_motion();
+    let frames: u32 = (0..8).map(|_| scheduler.frames_due(FRAME)).sum();
+    assert_eq!(frames, 2);
+    assert_eq!(scheduler.label(), "X0.25");
+
+    scheduler.set_fast_forward_speed(4.0);
+    scheduler.set_fast_forward(true);
+    assert_eq!(scheduler.frames_due(FRAME), 4);
+    scheduler.set_fast_forward_speed(UNCAPPED);
+    assert_eq!(scheduler.frames_due(FRAME), u32::MAX);
+    assert_eq!(scheduler.label(), ">>");
+
+    // a long stall doesn't turn into a burst of frames
+    scheduler.set_fast_forward(false);
+    scheduler.toggle_slow_motion();
+    assert_eq!(scheduler.frames_due(Duration::from_secs(10)), 6);
+}
+
+#[test]
+fn test_pause_and_advance() {
+    let mut scheduler = Scheduler::new();
+    scheduler.advance();
+    scheduler.toggle_pause();
+    assert_eq!(scheduler.label(), "PAUSED");
+    assert_eq!(scheduler.frames_due(Duration::from_secs(1)), 0);
+
+    scheduler.advance();
+    scheduler.advance();
+    assert_eq!(scheduler.frames_due(Duration::ZERO), 2);
+    assert_eq!(scheduler.frames_due(FRAME), 0);
+
+    scheduler.toggle_pause();
+    assert_eq!(scheduler.frames_due(FRAME), 1);
+}
+
+#[test]
+fn test_run_frame_ticks_timers_once() {
+    let mut processor = Processor::new();
+    // LD V0, 10; LD DT, V0; JP 204
+    processor.load(&[0x60, 0x0A, 0xF0, 0x15, 0x12, 0x04]);
+    let mut keypad = HeadlessKeypad::new();
+    let cycles = Scheduler::cycles_per_frame(1000);
+    assert_eq!(cycles, 17);
+
+    processor.run_frame(&mut keypad, cycles);
+    assert_eq!(processor.delay_timer(), 9);
+    for _ in 0..9 {
+        processor.run_frame(&mut keypad, cycles);
+    }
+    assert_eq!(processor.delay_timer(), 0);
+}
+
+#[test]
+fn test_next_frame_in() {
+    let mut scheduler = Scheduler::new();
+    let frame = Duration::from_secs_f64(1.0 / FRAMES_PER_SECOND);
+    assert_eq!(scheduler.next_frame_in(), Some(frame));
+    // half a frame is owed already
+    assert_eq!(scheduler.frames_due(frame / 2), 0);
+    assert!(scheduler.next_frame_in().unwrap() < frame * 3 / 4);
+
+    scheduler.toggle_slow_motion();
+    assert!(scheduler.next_frame_in().unwrap() > frame);
+    scheduler.set_fast_forward(true);
+    assert_eq!(scheduler.next_frame_in(), None);
+
+    scheduler.toggle_pause();
+    assert_eq!(scheduler.next_frame_in(), Some(frame));
+    scheduler.advance();
+    assert_eq!(scheduler.next_frame_in(), Some(Duration::ZERO));
+}
@@ -0,0 +1,133 @@
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+
+use crate::disassembler::disassemble;
+use crate::{CHIP8_RAM, OPCODE_SIZE};
+
+// Detects self-modifying code: instructions writing over bytes that were already executed,
+// and execution of bytes that were written by the program (rather than loaded with the ROM).
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum SmcKind {
+    // a write to an address that was executed before
+    WriteToCode,
+    // execution of an address that was written before
+    ExecuteWritten,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SmcEvent {
+    pub kind: SmcKind,
+    pub addr: usize,
+    // the instruction doing the write or being executed
+    pub pc: usize,
+    pub opcode: u16,
+}
+
+pub struct SmcTracker {
+    executed: Vec<bool>,
+    written: Vec<bool>,
+    // only the first event for each address and kind, loops would flood it otherwise
+    events: Vec<SmcEvent>,
+    seen: BTreeSet<(SmcKind, usize)>,
+    break_on_detect: bool,
+}
+
+impl Default for SmcTracker {
+    fn default() -> Self {
+        SmcTracker::new()
+    }
+}
+
+impl SmcTracker {
+    pub fn new() -> Self {
+        SmcTracker {
+            executed: vec![false; CHIP8_RAM],
+            written: vec![false; CHIP8_RAM],
+            events: Vec::new(),
+            seen: BTreeSet::new(),
+            break_on_detect: false,
+        }
+    }
+
+    pub fn set_break(&mut self, enabled: bool) {
+        self.break_on_detect = enabled;
+    }
+
+    pub fn breaks(&self) -> bool {
+        self.break_on_detect
+    }
+
+    pub fn executed(&self, addr: usize) -> bool {
+        self.executed.get(addr).copied().unwrap_or(false)
+    }
+
+    pub fn written(&self, addr: usize) -> bool {
+        self.written.get(addr).copied().unwrap_or(false)
+    }
+
+    pub fn events(&self) -> &[SmcEvent] {
+        &self.events
+    }
+
+    // Called before the instruction at `pc` runs, returns a new event if it was written.
+    pub fn on_execute(&mut self, pc: usize, opcode: u16) -> Option<SmcEvent> {
+        let mut event = None;
+        for addr in pc..(pc + OPCODE_SIZE).min(CHIP8_RAM) {
+            self.executed[addr] = true;
+            if self.written[addr] && event.is_none() {
+                event = self.record(SmcKind::ExecuteWritten, addr, pc, opcode);
+            }
+        }
+        event
+    }
+
+    // Called for every RAM write of the instruction at `pc`.
+    pub fn on_write(&mut self, addr: usize, pc: usize, opcode: u16) -> Option<SmcEvent> {
+        self.written[addr] = true;
+        if self.executed[addr] {
+            return self.record(SmcKind::WriteToCode, addr, pc, opcode);
+        }
+        None
+    }
+
+    fn record(&mut self, kind: SmcKind, addr: usize, pc: usize, opcode: u16) -> Option<SmcEvent> {
+        if !self.seen.insert((kind, addr)) {
+            return None;
+        }
+        let event = SmcEvent {
+            kind,
+            addr,
+            pc,
+            opcode,
+        };
+        self.events.push(event);
+        Some(event)
+    }
+
+    pub fn write_report(&self, out: &mut impl Write) -> io::Result<()> {
+        if self.events.is_empty() {
+            return writeln!(out, "No self-modifying code detected");
+        }
+        writeln!(out, "Self-modifying code:")?;
+        for event in self.events.iter() {
+            let what = match event.kind {
+                SmcKind::WriteToCode => "writes to executed",
+                SmcKind::ExecuteWritten => "executes written",
+            };
+            writeln!(
+                out,
+                "  {:03X}  {:<18} {} {:03X}",
+                event.pc,
+                disassemble(event.opcode),
+                what,
+                event.addr
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[path = "./smc_test.rs"]
+mod smc_test;
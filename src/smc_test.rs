@@ -0,0 +1,33 @@
+use super::*;
+
+#[test]
+fn test_write_to_code() {
+    let mut smc = SmcTracker::new();
+    assert_eq!(smc.on_execute(0x200, 0x6012), None);
+    assert!(smc.executed(0x201));
+
+    let event = smc.on_write(0x201, 0x210, 0xF055).unwrap();
+    assert_eq!(event.kind, SmcKind::WriteToCode);
+    assert_eq!(event.addr, 0x201);
+    assert_eq!(event.pc, 0x210);
+    // only reported once per address
+    assert_eq!(smc.on_write(0x201, 0x210, 0xF055), None);
+    assert_eq!(smc.on_write(0x300, 0x210, 0xF055), None);
+    assert_eq!(smc.events().len(), 1);
+}
+
+#[test]
+fn test_execute_written() {
+    let mut smc = SmcTracker::new();
+    assert_eq!(smc.on_write(0x301, 0x200, 0xF133), None);
+    let event = smc.on_execute(0x300, 0x00E0).unwrap();
+    assert_eq!(event.kind, SmcKind::ExecuteWritten);
+    assert_eq!(event.addr, 0x301);
+
+    let mut report = Vec::new();
+    smc.write_report(&mut report).unwrap();
+    assert_eq!(
+        String::from_utf8(report).unwrap(),
+        "Self-modifying code:\n  300  CLS                executes written 301\n"
+    );
+}
@@ -0,0 +1,155 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::CHIP8_RAM;
+
+// Labels from the assembler so that addresses can be shown and typed in as names.
+//
+// Supported formats:
+// * plain text, one `ADDR NAME` pair per line, e.g. `0x202 main`
+// * Octo style directives, `:label NAME ADDR` and `:breakpoint NAME ADDR`
+// * JSON, `{ "main": 514, "draw": "0x2A0" }`, optionally nested one level deep, e.g. under
+//   `"labels"`
+//
+// `#` and `;` start comments in the text formats.
+#[derive(Clone, Default, Debug)]
+pub struct Symbols {
+    names: BTreeMap<usize, String>,
+    addrs: HashMap<String, usize>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Symbols::default()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Symbols::parse(&data)
+    }
+
+    pub fn parse(data: &str) -> Result<Self, String> {
+        if data.trim_start().starts_with('{') {
+            Symbols::parse_json(data)
+        } else {
+            Symbols::parse_text(data)
+        }
+    }
+
+    fn parse_text(data: &str) -> Result<Self, String> {
+        let mut symbols = Symbols::new();
+        for (n, line) in data.lines().enumerate() {
+            let line = line.split(['#', ';']).next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = || format!("Invalid symbol on line {}: {}", n + 1, line);
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let (addr, name) = match tokens[..] {
+                [":label" | ":breakpoint", name, addr] => (addr, name),
+                // other directives such as `:const` aren't addresses
+                [directive, ..] if directive.starts_with(':') => continue,
+                [addr, name] => (addr, name),
+                _ => return Err(invalid()),
+            };
+            let addr = Symbols::parse_addr(addr).ok_or_else(invalid)?;
+            symbols.insert(addr, name);
+        }
+        Ok(symbols)
+    }
+
+    fn parse_json(data: &str) -> Result<Self, String> {
+        let value: Value =
+            serde_json::from_str(data).map_err(|e| format!("Invalid symbol file: {}", e))?;
+        let mut symbols = Symbols::new();
+        let mut add = |name: &str, value: &Value| -> Result<(), String> {
+            let addr = match value {
+                Value::Number(n) => n.as_u64().map(|n| n as usize).filter(|&n| n < CHIP8_RAM),
+                Value::String(s) => Symbols::parse_addr(s),
+                _ => None,
+            };
+            let addr = addr.ok_or_else(|| format!("Invalid address for symbol {}", name))?;
+            symbols.insert(addr, name);
+            Ok(())
+        };
+
+        let Value::Object(map) = value else {
+            return Err("Symbol file must be a JSON object".to_string());
+        };
+        for (name, value) in map.iter() {
+            match value {
+                Value::Object(inner) => {
+                    for (name, value) in inner.iter() {
+                        add(name, value)?;
+                    }
+                }
+                _ => add(name, value)?,
+            }
+        }
+        Ok(symbols)
+    }
+
+    fn parse_addr(s: &str) -> Option<usize> {
+        let addr = usize::from_str_radix(s.trim_start_matches("0x"), 16).ok()?;
+        (addr < CHIP8_RAM).then_some(addr)
+    }
+
+    // The first name for an address wins, later ones can still be used as input.
+    pub fn insert(&mut self, addr: usize, name: &str) {
+        self.names.entry(addr).or_insert_with(|| name.to_string());
+        self.addrs.insert(name.to_string(), addr);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addrs.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.addrs.len()
+    }
+
+    pub fn name(&self, addr: usize) -> Option<&str> {
+        self.names.get(&addr).map(|name| name.as_str())
+    }
+
+    pub fn addr(&self, name: &str) -> Option<usize> {
+        self.addrs.get(name).copied()
+    }
+
+    // Accepts a symbol name or a hex address, e.g. `main`, `2A0` or `0x2A0`.
+    pub fn resolve(&self, s: &str) -> Result<usize, String> {
+        let s = s.trim();
+        self.addr(s)
+            .or_else(|| usize::from_str_radix(s.trim_start_matches("0x"), 16).ok())
+            .ok_or_else(|| format!("Invalid address {}", s))
+    }
+
+    // `draw-draw_end`, `200-2FF` or a single address or name.
+    pub fn resolve_range(&self, s: &str) -> Result<RangeInclusive<usize>, String> {
+        match s.split_once('-') {
+            Some((start, end)) => Ok(self.resolve(start)?..=self.resolve(end)?),
+            None => {
+                let addr = self.resolve(s)?;
+                Ok(addr..=addr)
+            }
+        }
+    }
+
+    // `main`, `main+4` relative to the closest preceding symbol, or `2A4` without one.
+    pub fn describe(&self, addr: usize) -> String {
+        match self.names.range(..=addr).next_back() {
+            Some((&start, name)) if start == addr => name.clone(),
+            Some((&start, name)) => format!("{}+{}", name, addr - start),
+            None => format!("{:03X}", addr),
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./symbols_test.rs"]
+mod symbols_test;
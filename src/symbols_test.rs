@@ -0,0 +1,40 @@
+use super::*;
+
+#[test]
+fn test_parse_text() {
+    let symbols = Symbols::parse(
+        "# labels\n0x200 main\n2A0 draw ; sprite routine\n:const SPEED 5\n:label loop 0x204\n",
+    )
+    .unwrap();
+    assert_eq!(symbols.len(), 3);
+    assert_eq!(symbols.name(0x200), Some("main"));
+    assert_eq!(symbols.addr("draw"), Some(0x2A0));
+    assert_eq!(symbols.addr("loop"), Some(0x204));
+    assert_eq!(symbols.addr("SPEED"), None);
+
+    assert!(Symbols::parse("main").is_err());
+    assert!(Symbols::parse("zz main").is_err());
+}
+
+#[test]
+fn test_parse_json() {
+    let symbols = Symbols::parse(r#"{ "labels": { "main": 512, "draw": "0x2A0" } }"#).unwrap();
+    assert_eq!(symbols.addr("main"), Some(0x200));
+    assert_eq!(symbols.name(0x2A0), Some("draw"));
+
+    assert!(Symbols::parse(r#"{ "main": true }"#).is_err());
+}
+
+#[test]
+fn test_resolve() {
+    let symbols = Symbols::parse("200 main\n2A0 add\n").unwrap();
+    // names win over hex addresses
+    assert_eq!(symbols.resolve("add"), Ok(0x2A0));
+    assert_eq!(symbols.resolve("0x3FE"), Ok(0x3FE));
+    assert!(symbols.resolve("nope").is_err());
+    assert_eq!(symbols.resolve_range("main-add"), Ok(0x200..=0x2A0));
+
+    assert_eq!(symbols.describe(0x200), "main");
+    assert_eq!(symbols.describe(0x204), "main+4");
+    assert_eq!(symbols.describe(0x100), "100");
+}
@@ -0,0 +1,131 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+use crate::bitmap::Bitmap;
+use crate::record::Recorder;
+use crate::{HeadlessKeypad, Processor, Quirks};
+
+// Runs test ROMs headlessly and compares the final screen against reference images.
+// References are stored as `<rom name>.<profile>.pbm` next to each other in one directory,
+// diffs and recordings of failed runs go to a separate output directory.
+
+// pixel size in recordings of failed runs
+const RECORD_SCALE: usize = 4;
+
+// Frames to run and cycles per frame, enough for the usual test ROMs to finish drawing. The
+// references in `tests/reference` were made with these.
+pub const DEFAULT_FRAMES: usize = 180;
+pub const DEFAULT_IPF: usize = 10;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    Pass,
+    // the diff image highlights which pixels differ
+    Fail(PathBuf),
+    // no reference image yet
+    Missing,
+    // the reference image was (re)written from this run
+    Blessed,
+}
+
+pub struct TestRun<'a> {
+    pub frames: usize,
+    pub cycles_per_frame: usize,
+    pub references: &'a Path,
+    // where `<rom name>.<profile>.diff.ppm` is written for failed runs
+    pub output: &'a Path,
+    pub bless: bool,
+    // record failing runs as `<rom name>.<profile>.gif` next to the diff
+    pub record: bool,
+}
+
+impl TestRun<'_> {
+    pub fn check(&self, rom_path: &Path, profile: &str) -> io::Result<Outcome> {
+        let quirks = Quirks::profile(profile).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown quirk profile {}", profile),
+            )
+        })?;
+        let rom = fs::read(rom_path)?;
+        let actual = run_rom(&rom, quirks, self.frames, self.cycles_per_frame);
+
+        let name = TestRun::name(rom_path);
+        let reference = self.references.join(format!("{}.{}.pbm", name, profile));
+        if self.bless {
+            actual.write_pbm(&mut BufWriter::new(File::create(&reference)?))?;
+            return Ok(Outcome::Blessed);
+        }
+
+        let expected = match fs::read_to_string(&reference) {
+            Ok(data) => Bitmap::parse_pbm(&data)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Outcome::Missing),
+            Err(e) => return Err(e),
+        };
+        if expected == actual {
+            return Ok(Outcome::Pass);
+        }
+
+        fs::create_dir_all(self.output)?;
+        let diff = self.output.join(format!("{}.{}.diff.ppm", name, profile));
+        expected.write_diff_ppm(&actual, &mut BufWriter::new(File::create(&diff)?))?;
+        if self.record {
+            let path = self.recording(rom_path, profile);
+            record_rom(&rom, quirks, self.frames, self.cycles_per_frame, &path)?;
+        }
+        Ok(Outcome::Fail(diff))
+    }
+
+    pub fn recording(&self, rom_path: &Path, profile: &str) -> PathBuf {
+        let name = TestRun::name(rom_path);
+        self.output.join(format!("{}.{}.gif", name, profile))
+    }
+
+    fn name(rom_path: &Path) -> String {
+        rom_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+}
+
+// Runs a ROM without any keys pressed and returns the screen after `frames` 60 Hz frames.
+pub fn run_rom(rom: &[u8], quirks: Quirks, frames: usize, cycles_per_frame: usize) -> Bitmap {
+    let mut processor = boot(rom, quirks);
+    let mut keypad = HeadlessKeypad::new();
+    for _ in 0..frames {
+        processor.run_frame(&mut keypad, cycles_per_frame);
+    }
+    Bitmap::from_vram(processor.vram())
+}
+
+// Same run as `run_rom` saved as a recording, see `Recorder` for the formats.
+pub fn record_rom(
+    rom: &[u8],
+    quirks: Quirks,
+    frames: usize,
+    cycles_per_frame: usize,
+    path: &Path,
+) -> io::Result<()> {
+    let mut processor = boot(rom, quirks);
+    let mut keypad = HeadlessKeypad::new();
+    let mut recorder = Recorder::create(path, (0x000000, 0xFFFFFF), RECORD_SCALE)?;
+    for _ in 0..frames {
+        processor.run_frame(&mut keypad, cycles_per_frame);
+        recorder.frame(processor.vram(), processor.sound_timer() > 0)?;
+    }
+    recorder.finish()
+}
+
+fn boot(rom: &[u8], quirks: Quirks) -> Processor {
+    let mut processor = Processor::new();
+    processor.set_quirks(quirks);
+    processor.load(rom);
+    processor
+}
+
+#[cfg(test)]
+#[path = "./testrom_test.rs"]
+mod testrom_test;
@@ -0,0 +1,86 @@
+use super::*;
+
+// LD V0, 0x0A; LD F, V0; LD V1, 1; DRW V1, V1, 5; JP 0x208
+// Draws the font sprite for "A" at (1, 1) and loops forever.
+const ROM: [u8; 10] = [0x60, 0x0A, 0xF0, 0x29, 0x61, 0x01, 0xD1, 0x15, 0x12, 0x08];
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("chip8-testrom-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_run_rom() {
+    let bitmap = run_rom(&ROM, Quirks::default(), 1, 10);
+    assert!(bitmap.pixels.iter().any(|&on| on));
+    assert!(!bitmap.get(0, 0));
+}
+
+#[test]
+fn test_check() {
+    let dir = temp_dir("check");
+    let rom = dir.join("draw.ch8");
+    fs::write(&rom, ROM).unwrap();
+
+    let mut run = TestRun {
+        frames: 1,
+        cycles_per_frame: 10,
+        references: &dir,
+        output: &dir.join("output"),
+        bless: false,
+        record: false,
+    };
+    assert_eq!(run.check(&rom, "default").unwrap(), Outcome::Missing);
+    assert!(run.check(&rom, "bogus").is_err());
+
+    run.bless = true;
+    assert_eq!(run.check(&rom, "default").unwrap(), Outcome::Blessed);
+    run.bless = false;
+    assert_eq!(run.check(&rom, "default").unwrap(), Outcome::Pass);
+
+    // stopping before the sprite is drawn leaves an empty screen
+    run.cycles_per_frame = 1;
+    let diff = dir.join("output/draw.default.diff.ppm");
+    assert_eq!(
+        run.check(&rom, "default").unwrap(),
+        Outcome::Fail(diff.clone())
+    );
+    assert!(diff.exists());
+    assert!(!dir.join("draw.default.diff.ppm").exists());
+
+    run.record = true;
+    assert_eq!(run.check(&rom, "default").unwrap(), Outcome::Fail(diff));
+    assert!(run.recording(&rom, "default").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_reference_roms() {
+    // the ROMs in tests/roms check op_8, op_d and op_f under every quirk profile
+    let tests = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let references = tests.join("reference");
+    let output = temp_dir("reference");
+    let run = TestRun {
+        frames: DEFAULT_FRAMES,
+        cycles_per_frame: DEFAULT_IPF,
+        references: &references,
+        output: &output,
+        bless: false,
+        record: false,
+    };
+    let roms: Vec<PathBuf> = fs::read_dir(tests.join("roms"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ch8"))
+        .collect();
+    assert!(!roms.is_empty());
+    for rom in roms.iter() {
+        for profile in Quirks::PROFILES.iter() {
+            let outcome = run.check(rom, profile).unwrap();
+            assert_eq!(outcome, Outcome::Pass, "{} [{}]", rom.display(), profile);
+        }
+    }
+    fs::remove_dir_all(&output).unwrap();
+}
@@ -0,0 +1,128 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+
+use crate::disassembler::disassemble_symbolic;
+use crate::symbols::Symbols;
+
+// Register state that is compared before and after every traced instruction.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct CpuState {
+    pub reg: [u8; 16],
+    pub i: usize,
+    pub sp: usize,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+pub struct Tracer {
+    out: Box<dyn Write + Send>,
+    ranges: Vec<RangeInclusive<usize>>,
+    // high nibble of the opcodes to trace, empty means everything
+    classes: Vec<u8>,
+    symbols: Symbols,
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write + Send>) -> Self {
+        Tracer {
+            out,
+            ranges: Vec::new(),
+            classes: Vec::new(),
+            symbols: Symbols::new(),
+        }
+    }
+
+    // `-` traces to stderr, anything else is treated as a file path.
+    pub fn open(path: &str) -> io::Result<Self> {
+        let out: Box<dyn Write + Send> = if path == "-" {
+            Box::new(io::stderr())
+        } else {
+            Box::new(BufWriter::new(File::create(path)?))
+        };
+        Ok(Tracer::new(out))
+    }
+
+    pub fn with_ranges(mut self, ranges: Vec<RangeInclusive<usize>>) -> Self {
+        self.ranges = ranges;
+        self
+    }
+
+    // Labels are written on their own line before the first instruction after them.
+    pub fn with_symbols(mut self, symbols: Symbols) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    pub fn with_classes(mut self, classes: Vec<u8>) -> Self {
+        self.classes = classes;
+        self
+    }
+
+    pub fn wants(&self, pc: usize, opcode: u16) -> bool {
+        let in_range = self.ranges.is_empty() || self.ranges.iter().any(|r| r.contains(&pc));
+        let class = (opcode >> 12) as u8;
+        in_range && (self.classes.is_empty() || self.classes.contains(&class))
+    }
+
+    pub fn log(
+        &mut self,
+        pc: usize,
+        opcode: u16,
+        before: &CpuState,
+        after: &CpuState,
+    ) -> io::Result<()> {
+        if let Some(name) = self.symbols.name(pc) {
+            writeln!(self.out, "{}:", name)?;
+        }
+        let text = disassemble_symbolic(opcode, &self.symbols);
+        write!(self.out, "{:04X}  {:04X}  {:<18}", pc, opcode, text)?;
+        for (x, (&old, &new)) in before.reg.iter().zip(after.reg.iter()).enumerate() {
+            if old != new {
+                write!(self.out, " V{:X}={:02X}", x, new)?;
+            }
+        }
+        if before.i != after.i {
+            write!(self.out, " I={:04X}", after.i)?;
+        }
+        if before.sp != after.sp {
+            write!(self.out, " SP={:X}", after.sp)?;
+        }
+        if before.delay_timer != after.delay_timer {
+            write!(self.out, " DT={:02X}", after.delay_timer)?;
+        }
+        if before.sound_timer != after.sound_timer {
+            write!(self.out, " ST={:02X}", after.sound_timer)?;
+        }
+        writeln!(self.out)
+    }
+
+    // Parses `200-2FF` or a single address `2A0`, both in hex.
+    pub fn parse_range(s: &str) -> Result<RangeInclusive<usize>, String> {
+        let parse = |s: &str| {
+            usize::from_str_radix(s.trim_start_matches("0x"), 16)
+                .map_err(|_| format!("Invalid address {}", s))
+        };
+        match s.split_once('-') {
+            Some((start, end)) => Ok(parse(start)?..=parse(end)?),
+            None => {
+                let addr = parse(s)?;
+                Ok(addr..=addr)
+            }
+        }
+    }
+
+    // Parses a comma separated list of opcode classes, e.g. `8,D,F`.
+    pub fn parse_classes(s: &str) -> Result<Vec<u8>, String> {
+        s.split(',')
+            .map(|class| match u8::from_str_radix(class.trim(), 16) {
+                Ok(class) if class <= 0xF => Ok(class),
+                _ => Err(format!("Invalid instruction class {}", class)),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+#[path = "./trace_test.rs"]
+mod trace_test;
@@ -0,0 +1,83 @@
+use super::*;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Default)]
+struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn state() -> CpuState {
+    CpuState {
+        reg: [0; 16],
+        i: 0,
+        sp: 0,
+        delay_timer: 0,
+        sound_timer: 0,
+    }
+}
+
+#[test]
+fn test_log_changes() {
+    let buf = SharedBuf::default();
+    let mut tracer = Tracer::new(Box::new(buf.clone()));
+    let before = state();
+    let mut after = state();
+    after.reg[0xA] = 0x02;
+    after.reg[0xF] = 0x01;
+    after.i = 0x300;
+
+    tracer.log(0x200, 0x6A02, &before, &after).unwrap();
+    tracer.log(0x202, 0x1202, &before, &before).unwrap();
+
+    let out = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(
+        lines[0],
+        "0200  6A02  LD VA, 0x02        VA=02 VF=01 I=0300"
+    );
+    assert_eq!(lines[1].trim_end(), "0202  1202  JP 0x202");
+}
+
+#[test]
+fn test_filters() {
+    let tracer = Tracer::new(Box::new(io::sink()))
+        .with_ranges(vec![Tracer::parse_range("300-3FF").unwrap()])
+        .with_classes(Tracer::parse_classes("8,d").unwrap());
+
+    assert!(tracer.wants(0x300, 0x8124));
+    assert!(tracer.wants(0x3FE, 0xD125));
+    assert!(!tracer.wants(0x300, 0x6A02));
+    assert!(!tracer.wants(0x400, 0x8124));
+
+    let all = Tracer::new(Box::new(io::sink()));
+    assert!(all.wants(0x200, 0x00E0));
+}
+
+#[test]
+fn test_parse_filters() {
+    assert_eq!(Tracer::parse_range("2a0"), Ok(0x2A0..=0x2A0));
+    assert_eq!(Tracer::parse_range("0x200-0x2ff"), Ok(0x200..=0x2FF));
+    assert!(Tracer::parse_range("zz").is_err());
+    assert!(Tracer::parse_classes("8,10").is_err());
+}
+
+#[test]
+fn test_log_symbols() {
+    let buf = SharedBuf::default();
+    let symbols = Symbols::parse("200 main\n").unwrap();
+    let mut tracer = Tracer::new(Box::new(buf.clone())).with_symbols(symbols);
+    tracer.log(0x200, 0x1200, &state(), &state()).unwrap();
+
+    let out = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines[0], "main:");
+    assert_eq!(lines[1].trim_end(), "0200  1200  JP main");
+}
@@ -0,0 +1,61 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+// Reloads a file when it changes on disk, e.g. the ROM while working on it with an assembler.
+// The modification time and size are polled a few times a second, which needs no platform
+// specific notification API.
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+pub struct FileWatcher {
+    path: PathBuf,
+    stamp: Option<(SystemTime, u64)>,
+    last_poll: Option<Instant>,
+}
+
+impl FileWatcher {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_path_buf();
+        FileWatcher {
+            stamp: FileWatcher::stamp(&path),
+            path,
+            last_poll: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn stamp(path: &Path) -> Option<(SystemTime, u64)> {
+        let metadata = fs::metadata(path).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    }
+
+    // The new contents if the file changed since the last call. Checks the file at most every
+    // `POLL_INTERVAL`, so this can be called every frame.
+    pub fn poll(&mut self, now: Instant) -> Option<Vec<u8>> {
+        if matches!(self.last_poll, Some(last) if now.duration_since(last) < POLL_INTERVAL) {
+            return None;
+        }
+        self.last_poll = Some(now);
+        self.check()
+    }
+
+    // Like `poll` without the rate limit.
+    pub fn check(&mut self) -> Option<Vec<u8>> {
+        let stamp = FileWatcher::stamp(&self.path)?;
+        if Some(stamp) == self.stamp {
+            return None;
+        }
+        // an empty file usually means the assembler is still writing it, try again later
+        let data = fs::read(&self.path).ok().filter(|data| !data.is_empty())?;
+        self.stamp = Some(stamp);
+        Some(data)
+    }
+}
+
+#[cfg(test)]
+#[path = "./watch_test.rs"]
+mod watch_test;
@@ -0,0 +1,19 @@
+use super::*;
+
+#[test]
+fn test_check() {
+    let path = std::env::temp_dir().join(format!("chip8-watch-{}.ch8", std::process::id()));
+    fs::write(&path, [0x12, 0x00]).unwrap();
+    let mut watcher = FileWatcher::new(&path);
+    assert_eq!(watcher.check(), None);
+
+    fs::write(&path, [0x60, 0x01, 0x12, 0x02]).unwrap();
+    assert_eq!(watcher.check(), Some(vec![0x60, 0x01, 0x12, 0x02]));
+    assert_eq!(watcher.check(), None);
+
+    // half written files are skipped
+    fs::write(&path, []).unwrap();
+    assert_eq!(watcher.check(), None);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(watcher.check(), None);
+}
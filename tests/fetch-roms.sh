@@ -0,0 +1,15 @@
+#!/bin/sh
+# Downloads Timendus' CHIP-8 test suite (https://github.com/Timendus/chip8-test-suite) into
+# tests/roms/timendus. The ROMs aren't part of this repository and neither are their references.
+# Blessed references only record what this emulator does, check every image against the
+# screenshots in the test suite's README (or a known-good emulator) before committing them:
+#
+#   cargo run --no-default-features --bin chip8-test -- --bless tests/roms/timendus tests/reference/timendus
+set -e
+
+dir="$(dirname "$0")/roms/timendus"
+url="https://github.com/Timendus/chip8-test-suite/raw/main/bin"
+mkdir -p "$dir" "$(dirname "$0")/reference/timendus"
+for rom in 1-chip8-logo 2-ibm-logo 3-corax+ 4-flags 5-quirks 6-keypad; do
+    curl -fsSL -o "$dir/$rom.ch8" "$url/$rom.ch8"
+done